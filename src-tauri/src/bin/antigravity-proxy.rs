@@ -0,0 +1,10 @@
+// Antigravity 反代守护进程 (无界面)
+// 用法: antigravity-proxy   (配置读取自 ~/.antigravity_tools/gui_config.json)
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = antigravity_tools_lib::headless::run().await {
+        eprintln!("antigravity-proxy: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,29 +1,50 @@
 use crate::models::{Account, AppConfig, QuotaData, TokenData};
 use crate::modules;
+#[cfg(feature = "gui")]
 use tauri::{Emitter, Manager};
+#[cfg(feature = "gui")]
 use tauri_plugin_opener::OpenerExt;
 
 // 导出 proxy 命令
 pub mod proxy;
 // 导出 autostart 命令
+#[cfg(feature = "gui")]
 pub mod autostart;
 
 /// 列出所有账号
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn list_accounts() -> Result<Vec<Account>, String> {
     modules::list_accounts()
 }
 
 /// 添加账号
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn add_account(
     app: tauri::AppHandle,
     _email: String,
     refresh_token: String,
 ) -> Result<Account, String> {
+    let account = internal_add_account(&refresh_token).await?;
+
+    // 更新托盘菜单
+    crate::modules::tray::update_tray_menus(&app);
+
+    // 6. If proxy is running, reload token pool so changes take effect immediately.
+    let _ = crate::commands::proxy::reload_proxy_accounts(
+        app.state::<crate::commands::proxy::ProxyServiceState>(),
+    )
+    .await;
+
+    Ok(account)
+}
+
+/// 添加账号的核心逻辑 (不依赖 Tauri，管理 API 复用)
+pub async fn internal_add_account(refresh_token: &str) -> Result<Account, String> {
     // 1. 使用 refresh_token 获取 access_token
-    // 注意：这里我们忽略传入的 _email，而是直接去 Google 获取真实的邮箱
-    let token_res = modules::oauth::refresh_access_token(&refresh_token).await?;
+    // 注意：这里我们忽略传入的 email，而是直接去 Google 获取真实的邮箱
+    let token_res = modules::oauth::refresh_access_token(refresh_token).await?;
 
     // 2. 获取用户信息
     let user_info = modules::oauth::get_user_info(&token_res.access_token).await?;
@@ -31,7 +52,7 @@ pub async fn add_account(
     // 3. 构造 TokenData
     let token = TokenData::new(
         token_res.access_token,
-        refresh_token, // 继续使用用户传入的 refresh_token
+        refresh_token.to_string(), // 继续使用用户传入的 refresh_token
         token_res.expires_in,
         Some(user_info.email.clone()),
         None, // project_id 将在需要时获取
@@ -39,27 +60,33 @@ pub async fn add_account(
     );
 
     // 4. 使用真实的 email 添加或更新账号
-    let account =
+    let mut account =
         modules::upsert_account(user_info.email.clone(), user_info.get_display_name(), token)?;
 
     modules::logger::log_info(&format!("添加账号成功: {}", account.email));
 
     // 5. 自动触发刷新额度
-    let mut account = account;
-    let _ = internal_refresh_account_quota(&app, &mut account).await;
-
-    // 6. If proxy is running, reload token pool so changes take effect immediately.
-    let _ = crate::commands::proxy::reload_proxy_accounts(
-        app.state::<crate::commands::proxy::ProxyServiceState>(),
-    )
-    .await;
+    match modules::account::fetch_quota_with_retry(&mut account).await {
+        Ok(quota) => {
+            let _ = modules::update_account_quota(&account.id, quota.clone());
+            account.quota = Some(quota);
+        }
+        Err(e) => {
+            modules::logger::log_warn(&format!("自动刷新配额失败 ({}): {}", account.email, e));
+        }
+    }
 
     Ok(account)
 }
 
 /// 删除账号
+#[cfg(feature = "gui")]
 #[tauri::command]
-pub async fn delete_account(app: tauri::AppHandle, account_id: String) -> Result<(), String> {
+pub async fn delete_account(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+) -> Result<(), String> {
     modules::logger::log_info(&format!("收到删除账号请求: {}", account_id));
     modules::delete_account(&account_id).map_err(|e| {
         modules::logger::log_error(&format!("删除账号失败: {}", e));
@@ -67,15 +94,20 @@ pub async fn delete_account(app: tauri::AppHandle, account_id: String) -> Result
     })?;
     modules::logger::log_info(&format!("账号删除成功: {}", account_id));
 
+    // 如果反代服务正在运行,重新加载账号池
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
     // 强制同步托盘
     crate::modules::tray::update_tray_menus(&app);
     Ok(())
 }
 
 /// 批量删除账号
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn delete_accounts(
     app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     account_ids: Vec<String>,
 ) -> Result<(), String> {
     modules::logger::log_info(&format!(
@@ -87,6 +119,9 @@ pub async fn delete_accounts(
         e
     })?;
 
+    // 如果反代服务正在运行,重新加载账号池
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
     // 强制同步托盘
     crate::modules::tray::update_tray_menus(&app);
     Ok(())
@@ -94,6 +129,7 @@ pub async fn delete_accounts(
 
 /// 重新排序账号列表
 /// 根据传入的账号ID数组顺序更新账号排列
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn reorder_accounts(account_ids: Vec<String>) -> Result<(), String> {
     modules::logger::log_info(&format!(
@@ -107,6 +143,7 @@ pub async fn reorder_accounts(account_ids: Vec<String>) -> Result<(), String> {
 }
 
 /// 切换账号
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn switch_account(app: tauri::AppHandle, account_id: String) -> Result<(), String> {
     let res = modules::switch_account(&account_id).await;
@@ -117,6 +154,7 @@ pub async fn switch_account(app: tauri::AppHandle, account_id: String) -> Result
 }
 
 /// 获取当前账号
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_current_account() -> Result<Option<Account>, String> {
     // println!("🚀 Backend Command: get_current_account called"); // Commented out to reduce noise for frequent calls, relies on frontend log for frequency
@@ -135,6 +173,7 @@ pub async fn get_current_account() -> Result<Option<Account>, String> {
 }
 
 /// 内部辅助功能：在添加或导入账号后自动刷新一次额度
+#[cfg(feature = "gui")]
 async fn internal_refresh_account_quota(
     app: &tauri::AppHandle,
     account: &mut Account,
@@ -158,6 +197,7 @@ async fn internal_refresh_account_quota(
 }
 
 /// 查询账号配额
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn fetch_account_quota(
     app: tauri::AppHandle,
@@ -193,9 +233,17 @@ pub async fn fetch_account_quota(
 pub use modules::account::RefreshStats;
 
 /// 刷新所有账号配额
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn refresh_all_quotas(
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+) -> Result<RefreshStats, String> {
+    internal_refresh_all_quotas(&proxy_state).await
+}
+
+/// 刷新全部配额的核心逻辑 (调度器与 Headless 模式复用)
+pub async fn internal_refresh_all_quotas(
+    proxy_state: &crate::commands::proxy::ProxyServiceState,
 ) -> Result<RefreshStats, String> {
     let stats = modules::account::refresh_all_quotas_logic().await?;
 
@@ -212,6 +260,7 @@ pub async fn refresh_all_quotas(
     Ok(stats)
 }
 /// 获取设备指纹（当前 storage.json + 账号绑定）
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_device_profiles(
     account_id: String,
@@ -220,6 +269,7 @@ pub async fn get_device_profiles(
 }
 
 /// 绑定设备指纹（capture: 采集当前；generate: 生成新指纹），并写入 storage.json
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn bind_device_profile(
     account_id: String,
@@ -229,12 +279,14 @@ pub async fn bind_device_profile(
 }
 
 /// 预览生成一个指纹（不落盘）
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn preview_generate_profile() -> Result<crate::models::DeviceProfile, String> {
     Ok(crate::modules::device::generate_profile())
 }
 
 /// 使用给定指纹直接绑定
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn bind_device_profile_with_profile(
     account_id: String,
@@ -244,6 +296,7 @@ pub async fn bind_device_profile_with_profile(
 }
 
 /// 将账号已绑定的指纹应用到 storage.json
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn apply_device_profile(
     account_id: String,
@@ -252,12 +305,14 @@ pub async fn apply_device_profile(
 }
 
 /// 恢复最早的 storage.json 备份（近似“原始”状态）
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn restore_original_device() -> Result<String, String> {
     modules::restore_original_device()
 }
 
 /// 列出指纹版本
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn list_device_versions(
     account_id: String,
@@ -266,6 +321,7 @@ pub async fn list_device_versions(
 }
 
 /// 按版本恢复指纹
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn restore_device_version(
     account_id: String,
@@ -275,12 +331,14 @@ pub async fn restore_device_version(
 }
 
 /// 删除历史指纹（baseline 不可删）
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn delete_device_version(account_id: String, version_id: String) -> Result<(), String> {
     modules::delete_device_version(&account_id, &version_id)
 }
 
 /// 打开设备存储目录
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn open_device_folder(app: tauri::AppHandle) -> Result<(), String> {
     let dir = modules::device::get_storage_dir()?;
@@ -294,12 +352,14 @@ pub async fn open_device_folder(app: tauri::AppHandle) -> Result<(), String> {
 }
 
 /// 加载配置
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn load_config() -> Result<AppConfig, String> {
     modules::load_app_config()
 }
 
 /// 保存配置
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn save_config(
     app: tauri::AppHandle,
@@ -312,32 +372,15 @@ pub async fn save_config(
     let _ = app.emit("config://updated", ());
 
     // 热更新正在运行的服务
-    let instance_lock = proxy_state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        // 更新模型映射
-        instance.axum_server.update_mapping(&config.proxy).await;
-        // 更新上游代理
-        instance
-            .axum_server
-            .update_proxy(config.proxy.upstream_proxy.clone())
-            .await;
-        // 更新安全策略 (auth)
-        instance.axum_server.update_security(&config.proxy).await;
-        // 更新 z.ai 配置
-        instance.axum_server.update_zai(&config.proxy).await;
-        // 更新实验性配置
-        instance
-            .axum_server
-            .update_experimental(&config.proxy)
-            .await;
-        tracing::debug!("已同步热更新反代服务配置");
-    }
+    crate::commands::proxy::internal_apply_proxy_config(&proxy_state, &config.proxy).await;
 
     Ok(())
 }
 
 // --- OAuth 命令 ---
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 pub async fn start_oauth_login(app_handle: tauri::AppHandle) -> Result<Account, String> {
     modules::logger::log_info("开始 OAuth 授权流程...");
@@ -404,6 +447,7 @@ pub async fn start_oauth_login(app_handle: tauri::AppHandle) -> Result<Account,
 }
 
 /// 完成 OAuth 授权（不自动打开浏览器）
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn complete_oauth_login(app_handle: tauri::AppHandle) -> Result<Account, String> {
     modules::logger::log_info("完成 OAuth 授权流程 (manual)...");
@@ -470,11 +514,14 @@ pub async fn complete_oauth_login(app_handle: tauri::AppHandle) -> Result<Accoun
 }
 
 /// 预生成 OAuth 授权链接 (不打开浏览器)
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn prepare_oauth_url(app_handle: tauri::AppHandle) -> Result<String, String> {
     crate::modules::oauth_server::prepare_oauth_url(app_handle).await
 }
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 pub async fn cancel_oauth_login() -> Result<(), String> {
     modules::oauth_server::cancel_oauth_flow();
@@ -483,6 +530,8 @@ pub async fn cancel_oauth_login() -> Result<(), String> {
 
 // --- 导入命令 ---
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 pub async fn import_v1_accounts(app: tauri::AppHandle) -> Result<Vec<Account>, String> {
     let accounts = modules::migration::import_from_v1().await?;
@@ -495,6 +544,8 @@ pub async fn import_v1_accounts(app: tauri::AppHandle) -> Result<Vec<Account>, S
     Ok(accounts)
 }
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 pub async fn import_from_db(app: tauri::AppHandle) -> Result<Account, String> {
     // 同步函数包装为 async
@@ -513,6 +564,8 @@ pub async fn import_from_db(app: tauri::AppHandle) -> Result<Account, String> {
     Ok(account)
 }
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 #[allow(dead_code)]
 pub async fn import_custom_db(app: tauri::AppHandle, path: String) -> Result<Account, String> {
@@ -532,6 +585,8 @@ pub async fn import_custom_db(app: tauri::AppHandle, path: String) -> Result<Acc
     Ok(account)
 }
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 pub async fn sync_account_from_db(app: tauri::AppHandle) -> Result<Option<Account>, String> {
     // 1. 获取 DB 中的 Refresh Token
@@ -567,18 +622,21 @@ pub async fn sync_account_from_db(app: tauri::AppHandle) -> Result<Option<Accoun
 }
 
 /// 保存文本文件 (绕过前端 Scope 限制)
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn save_text_file(path: String, content: String) -> Result<(), String> {
     std::fs::write(&path, content).map_err(|e| format!("写入文件失败: {}", e))
 }
 
 /// 清理日志缓存
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn clear_log_cache() -> Result<(), String> {
     modules::logger::clear_logs()
 }
 
 /// 打开数据目录
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn open_data_folder() -> Result<(), String> {
     let path = modules::account::get_data_dir()?;
@@ -611,6 +669,7 @@ pub async fn open_data_folder() -> Result<(), String> {
 }
 
 /// 获取数据目录绝对路径
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_data_dir_path() -> Result<String, String> {
     let path = modules::account::get_data_dir()?;
@@ -618,12 +677,14 @@ pub async fn get_data_dir_path() -> Result<String, String> {
 }
 
 /// 显示主窗口
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn show_main_window(window: tauri::Window) -> Result<(), String> {
     window.show().map_err(|e| e.to_string())
 }
 
 /// 获取 Antigravity 可执行文件路径
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_antigravity_path(bypass_config: Option<bool>) -> Result<String, String> {
     // 1. 优先从配置查询 (除非明确要求绕过)
@@ -645,6 +706,7 @@ pub async fn get_antigravity_path(bypass_config: Option<bool>) -> Result<String,
 }
 
 /// 获取 Antigravity 启动参数
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_antigravity_args() -> Result<Vec<String>, String> {
     match crate::modules::process::get_args_from_running_process() {
@@ -657,12 +719,15 @@ pub async fn get_antigravity_args() -> Result<Vec<String>, String> {
 pub use crate::modules::update_checker::UpdateInfo;
 
 /// 检测 GitHub releases 更新
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn check_for_updates() -> Result<UpdateInfo, String> {
     modules::logger::log_info("收到前端触发的更新检查请求");
     crate::modules::update_checker::check_for_updates().await
 }
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 pub async fn should_check_updates() -> Result<bool, String> {
     let settings = crate::modules::update_checker::load_update_settings()?;
@@ -671,12 +736,15 @@ pub async fn should_check_updates() -> Result<bool, String> {
     ))
 }
 
+#[cfg(feature = "gui")]
+
 #[tauri::command]
 pub async fn update_last_check_time() -> Result<(), String> {
     crate::modules::update_checker::update_last_check_time()
 }
 
 /// 获取更新设置
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_update_settings() -> Result<crate::modules::update_checker::UpdateSettings, String>
 {
@@ -684,6 +752,7 @@ pub async fn get_update_settings() -> Result<crate::modules::update_checker::Upd
 }
 
 /// 保存更新设置
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn save_update_settings(
     settings: crate::modules::update_checker::UpdateSettings,
@@ -692,6 +761,7 @@ pub async fn save_update_settings(
 }
 
 /// 切换账号的反代禁用状态
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn toggle_proxy_status(
     app: tauri::AppHandle,
@@ -759,13 +829,31 @@ pub async fn toggle_proxy_status(
     Ok(())
 }
 
+/// 设置账号标签 (反代路由规则使用)
+#[cfg(feature = "gui")]
+#[tauri::command]
+pub async fn update_account_tags(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+    tags: Vec<String>,
+) -> Result<Account, String> {
+    let account = modules::update_account_tags(&account_id, tags)?;
+    modules::logger::log_info(&format!("账号标签已更新: {} -> {:?}", account.email, account.tags));
+
+    // 如果反代服务正在运行,重新加载账号池
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+    Ok(account)
+}
+
 /// 预热所有可用账号
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn warm_up_all_accounts() -> Result<String, String> {
     modules::quota::warm_up_all_accounts().await
 }
 
 /// 预热指定账号
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn warm_up_account(account_id: String) -> Result<String, String> {
     modules::quota::warm_up_account(&account_id).await
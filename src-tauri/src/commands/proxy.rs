@@ -1,10 +1,13 @@
+#[cfg(feature = "gui")]
 use tauri::State;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use crate::proxy::{ProxyConfig, TokenManager};
+use crate::proxy::server::ShutdownReport;
 use tokio::time::Duration;
 use crate::proxy::monitor::{ProxyMonitor, ProxyRequestLog, ProxyStats};
+use crate::modules::events::SharedEventSink;
 
 
 /// 反代服务状态
@@ -17,6 +20,8 @@ pub struct ProxyStatus {
 }
 
 /// 反代服务全局状态
+/// 字段均为 Arc，克隆后共享同一份运行状态 (供 Headless 模式与调度器使用)
+#[derive(Clone)]
 pub struct ProxyServiceState {
     pub instance: Arc<RwLock<Option<ProxyServiceInstance>>>,
     pub monitor: Arc<RwLock<Option<Arc<ProxyMonitor>>>>,
@@ -27,7 +32,7 @@ pub struct ProxyServiceInstance {
     pub config: ProxyConfig,
     pub token_manager: Arc<TokenManager>,
     pub axum_server: crate::proxy::AxumServer,
-    pub server_handle: tokio::task::JoinHandle<()>,
+    pub server_handle: tokio::task::JoinHandle<crate::proxy::server::ShutdownReport>,
 }
 
 impl ProxyServiceState {
@@ -40,11 +45,29 @@ impl ProxyServiceState {
 }
 
 /// 启动反代服务
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn start_proxy_service(
     config: ProxyConfig,
     state: State<'_, ProxyServiceState>,
     app_handle: tauri::AppHandle,
+) -> Result<ProxyStatus, String> {
+    let event_sink: SharedEventSink = Arc::new(app_handle);
+    let status = internal_start_proxy_service(config.clone(), &state, Some(event_sink)).await?;
+
+    // 保存配置到全局 AppConfig
+    let mut app_config = crate::modules::config::load_app_config()?;
+    app_config.proxy = config;
+    crate::modules::config::save_app_config(&app_config)?;
+
+    Ok(status)
+}
+
+/// 启动反代服务的核心逻辑 (不依赖 Tauri，GUI 与 Headless 模式共用)
+pub async fn internal_start_proxy_service(
+    config: ProxyConfig,
+    state: &ProxyServiceState,
+    event_sink: Option<SharedEventSink>,
 ) -> Result<ProxyStatus, String> {
     let mut instance_lock = state.instance.write().await;
     
@@ -57,7 +80,7 @@ pub async fn start_proxy_service(
     {
         let mut monitor_lock = state.monitor.write().await;
         if monitor_lock.is_none() {
-            *monitor_lock = Some(Arc::new(ProxyMonitor::new(1000, Some(app_handle.clone()))));
+            *monitor_lock = Some(Arc::new(ProxyMonitor::new(1000, event_sink)));
         }
         // Sync enabled state from config
         if let Some(monitor) = monitor_lock.as_ref() {
@@ -76,6 +99,9 @@ pub async fn start_proxy_service(
     let token_manager = Arc::new(TokenManager::new(accounts_dir));
     // 同步 UI 传递的调度配置
     token_manager.update_sticky_config(config.scheduling.clone()).await;
+    token_manager.update_routing_rules(config.routing_rules.clone());
+    token_manager.update_token_refresh_config(config.token_refresh.clone());
+    crate::proxy::model_registry::ModelRegistry::global().set_custom(config.model_registry.clone());
     
     // 3. 加载账号
     let active_accounts = token_manager.load_accounts().await
@@ -95,14 +121,16 @@ pub async fn start_proxy_service(
             config.get_bind_address().to_string(),
             config.port,
             token_manager.clone(),
-            config.custom_mapping.clone(),
+            crate::proxy::common::model_mapping::ModelRouter::from_config(&config),
             config.request_timeout,
             config.upstream_proxy.clone(),
             crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
             config.zai.clone(),
             monitor.clone(),
             config.experimental.clone(),
-
+            config.enable_metrics,
+            &config.tls,
+            &config.unix_socket,
         ).await {
             Ok((server, handle)) => (server, handle),
             Err(e) => return Err(format!("启动 Axum 服务器失败: {}", e)),
@@ -118,42 +146,130 @@ pub async fn start_proxy_service(
     
     *instance_lock = Some(instance);
     
-
-    // 保存配置到全局 AppConfig
-    let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
-    app_config.proxy = config.clone();
-    crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
-    
     Ok(ProxyStatus {
         running: true,
         port: config.port,
-        base_url: format!("http://127.0.0.1:{}", config.port),
+        base_url: config.get_base_url(),
         active_accounts,
     })
 }
 
 /// 停止反代服务
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn stop_proxy_service(
     state: State<'_, ProxyServiceState>,
-) -> Result<(), String> {
+) -> Result<ShutdownReport, String> {
+    internal_stop_proxy_service(&state).await
+}
+
+/// 停止反代服务的核心逻辑
+/// 停止接收新连接，等待进行中的请求结束 (最长 shutdown_grace_seconds)，返回排空/中断的连接数
+pub async fn internal_stop_proxy_service(
+    state: &ProxyServiceState,
+) -> Result<ShutdownReport, String> {
+    // 先取出实例并释放锁，排空期间状态查询不被阻塞
+    let instance = state
+        .instance
+        .write()
+        .await
+        .take()
+        .ok_or_else(|| "服务未运行".to_string())?;
+
+    // 停止 Axum 服务器
+    let grace = Duration::from_secs(instance.config.shutdown_grace_seconds);
+    instance.axum_server.stop(grace);
+    // 等待连接排空
+    let report = instance.server_handle.await.unwrap_or_default();
+
+    // 保存限流锁定与会话绑定，下次启动时恢复
+    if let Err(e) = instance.token_manager.save_scheduler_state() {
+        tracing::warn!("{}", e);
+    }
+
+    Ok(report)
+}
+
+/// 配置热更新结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigApplyReport {
+    /// 与运行中的配置是否有差异
+    pub changed: bool,
+    /// 已变更但需要重启服务才能生效的配置项
+    pub restart_required: Vec<String>,
+}
+
+/// 将新配置热更新到运行中的反代服务 (save_config 与配置文件监听共用)
+/// 服务未运行时返回 None
+pub async fn internal_apply_proxy_config(
+    state: &ProxyServiceState,
+    config: &ProxyConfig,
+) -> Option<ConfigApplyReport> {
     let mut instance_lock = state.instance.write().await;
-    
-    if instance_lock.is_none() {
-        return Err("服务未运行".to_string());
+    let instance = instance_lock.as_mut()?;
+
+    let changed = serde_json::to_value(&instance.config).ok() != serde_json::to_value(config).ok();
+    if !changed {
+        return Some(ConfigApplyReport::default());
     }
-    
-    // 停止 Axum 服务器
-    if let Some(instance) = instance_lock.take() {
-        instance.axum_server.stop();
-        // 等待服务器任务完成
-        instance.server_handle.await.ok();
+
+    // 更新模型映射
+    instance.axum_server.update_mapping(config).await;
+    // 更新上游代理
+    instance
+        .axum_server
+        .update_proxy(config.upstream_proxy.clone())
+        .await;
+    // 更新安全策略 (auth)
+    instance.axum_server.update_security(config).await;
+    // 更新 z.ai 配置
+    instance.axum_server.update_zai(config).await;
+    // 更新实验性配置
+    instance.axum_server.update_experimental(config).await;
+    // 更新指标端点开关
+    instance.axum_server.update_metrics(config);
+    // 更新调度配置
+    instance
+        .token_manager
+        .update_sticky_config(config.scheduling.clone())
+        .await;
+    // 更新账号分组路由规则
+    instance.token_manager.update_routing_rules(config.routing_rules.clone());
+    // 更新后台 token 刷新配置
+    instance.token_manager.update_token_refresh_config(config.token_refresh.clone());
+    // 同步监控开关
+    if let Some(monitor) = state.monitor.read().await.as_ref() {
+        monitor.set_enabled(config.enable_logging);
     }
-    
-    Ok(())
+
+    // 监听地址/端口/TLS 保持运行中的值，确保状态中的 base_url 与实际一致
+    let restart_required = config.restart_required_changes(&instance.config);
+    let mut applied = config.clone();
+    applied.port = instance.config.port;
+    applied.allow_lan_access = instance.config.allow_lan_access;
+    applied.tls = instance.config.tls.clone();
+    applied.unix_socket = crate::proxy::config::ProxyUnixSocketConfig {
+        auth_mode: config.unix_socket.auth_mode.clone(),
+        ..instance.config.unix_socket.clone()
+    };
+    instance.config = applied;
+
+    if !restart_required.is_empty() {
+        tracing::warn!(
+            "以下配置需要重启反代服务才能生效: {}",
+            restart_required.join(", ")
+        );
+    }
+    tracing::debug!("已同步热更新反代服务配置");
+
+    Some(ConfigApplyReport {
+        changed,
+        restart_required: restart_required.into_iter().map(String::from).collect(),
+    })
 }
 
 /// 获取反代服务状态
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_proxy_status(
     state: State<'_, ProxyServiceState>,
@@ -164,7 +280,7 @@ pub async fn get_proxy_status(
         Some(instance) => Ok(ProxyStatus {
             running: true,
             port: instance.config.port,
-            base_url: format!("http://127.0.0.1:{}", instance.config.port),
+            base_url: instance.config.get_base_url(),
             active_accounts: instance.token_manager.len(),
         }),
         None => Ok(ProxyStatus {
@@ -177,6 +293,7 @@ pub async fn get_proxy_status(
 }
 
 /// 获取反代服务统计
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_proxy_stats(
     state: State<'_, ProxyServiceState>,
@@ -190,6 +307,7 @@ pub async fn get_proxy_stats(
 }
 
 /// 获取反代请求日志
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_proxy_logs(
     state: State<'_, ProxyServiceState>,
@@ -204,6 +322,7 @@ pub async fn get_proxy_logs(
 }
 
 /// 设置监控开启状态
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn set_proxy_monitor_enabled(
     state: State<'_, ProxyServiceState>,
@@ -217,6 +336,7 @@ pub async fn set_proxy_monitor_enabled(
 }
 
 /// 清除反代请求日志
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn clear_proxy_logs(
     state: State<'_, ProxyServiceState>,
@@ -229,6 +349,7 @@ pub async fn clear_proxy_logs(
 }
 
 /// 获取反代请求日志 (分页)
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_proxy_logs_paginated(
     limit: Option<usize>,
@@ -241,6 +362,7 @@ pub async fn get_proxy_logs_paginated(
 }
 
 /// 获取单条日志的完整详情
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_proxy_log_detail(
     log_id: String,
@@ -249,15 +371,24 @@ pub async fn get_proxy_log_detail(
 }
 
 /// 生成 API Key
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub fn generate_api_key() -> String {
     format!("sk-{}", uuid::Uuid::new_v4().simple())
 }
 
 /// 重新加载账号（当主应用添加/删除账号时调用）
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn reload_proxy_accounts(
     state: State<'_, ProxyServiceState>,
+) -> Result<usize, String> {
+    internal_reload_proxy_accounts(&state).await
+}
+
+/// 重新加载账号的核心逻辑
+pub async fn internal_reload_proxy_accounts(
+    state: &ProxyServiceState,
 ) -> Result<usize, String> {
     let instance_lock = state.instance.read().await;
     
@@ -272,11 +403,13 @@ pub async fn reload_proxy_accounts(
 }
 
 /// 更新模型映射表 (热更新)
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn update_model_mapping(
     config: ProxyConfig,
     state: State<'_, ProxyServiceState>,
 ) -> Result<(), String> {
+    crate::proxy::config::validate_mapping_rules(&config.custom_mapping)?;
     let instance_lock = state.instance.read().await;
     
     // 1. 如果服务正在运行，立即更新内存中的映射 (这里目前只更新了 anthropic_mapping 的 RwLock, 
@@ -287,9 +420,9 @@ pub async fn update_model_mapping(
     }
     
     // 2. 无论是否运行，都保存到全局配置持久化
-    let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
+    let mut app_config = crate::modules::config::load_app_config()?;
     app_config.proxy.custom_mapping = config.custom_mapping;
-    crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
+    crate::modules::config::save_app_config(&app_config)?;
     
     Ok(())
 }
@@ -353,6 +486,7 @@ fn extract_model_ids(value: &serde_json::Value) -> Vec<String> {
 }
 
 /// Fetch available models from the configured z.ai Anthropic-compatible API (`/v1/models`).
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn fetch_zai_models(
     zai: crate::proxy::ZaiConfig,
@@ -406,6 +540,7 @@ pub async fn fetch_zai_models(
 }
 
 /// 获取当前调度配置
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn get_proxy_scheduling_config(
     state: State<'_, ProxyServiceState>,
@@ -419,6 +554,7 @@ pub async fn get_proxy_scheduling_config(
 }
 
 /// 更新调度配置
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn update_proxy_scheduling_config(
     state: State<'_, ProxyServiceState>,
@@ -434,6 +570,7 @@ pub async fn update_proxy_scheduling_config(
 }
 
 /// 清除所有会话粘性绑定
+#[cfg(feature = "gui")]
 #[tauri::command]
 pub async fn clear_proxy_session_bindings(
     state: State<'_, ProxyServiceState>,
@@ -0,0 +1,66 @@
+// Headless 模式: 仅运行反代服务与预热调度器，不依赖窗口、托盘与 WebView
+use crate::commands::proxy::{self, ProxyServiceState};
+use crate::modules::events::{LogEventSink, SharedEventSink};
+use crate::modules::{config, config_watcher, logger, scheduler};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// 以守护进程方式运行反代服务，直到收到 Ctrl+C / SIGTERM
+/// 配置读取自数据目录下的 gui_config.json (与 GUI 版本共用)，忽略 auto_start
+pub async fn run() -> Result<(), String> {
+    logger::init_logger();
+
+    let app_config = config::load_app_config()?;
+    let state = ProxyServiceState::new();
+    let sink: SharedEventSink = Arc::new(LogEventSink);
+
+    let status =
+        proxy::internal_start_proxy_service(app_config.proxy, &state, Some(sink.clone())).await?;
+    info!(
+        "[Headless] 反代服务已启动: {} (可用账号: {})",
+        status.base_url, status.active_accounts
+    );
+
+    scheduler::start_scheduler(sink.clone(), state.clone());
+
+    // 手动或由配置管理工具修改 gui_config.json 后自动热加载
+    if let Err(e) = config_watcher::start_config_watcher(state.clone(), sink) {
+        error!("[Headless] {}", e);
+    }
+
+    wait_for_shutdown_signal().await;
+    info!("[Headless] 收到退出信号，正在停止反代服务...");
+
+    match proxy::internal_stop_proxy_service(&state).await {
+        Ok(report) => info!(
+            "[Headless] 反代服务已停止 (正常结束连接: {}, 中断连接: {})",
+            report.drained, report.aborted
+        ),
+        Err(e) => error!("[Headless] 停止反代服务失败: {}", e),
+    }
+    Ok(())
+}
+
+async fn wait_for_shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
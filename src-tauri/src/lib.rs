@@ -1,20 +1,29 @@
+// 无界面构建 (--no-default-features) 下，仅供前端命令调用的账号/设备/更新等函数不会被引用
+#![cfg_attr(not(feature = "gui"), allow(dead_code, unused_imports, unused_variables, unused_mut))]
+
 mod models;
 mod modules;
 mod commands;
 mod utils;
 mod proxy;  // 反代服务模块
 pub mod error;
+pub mod headless; // 无界面反代守护进程入口
 
+#[cfg(feature = "gui")]
 use tauri::Manager;
+#[cfg(feature = "gui")]
 use modules::logger;
+#[cfg(feature = "gui")]
 use tracing::{info, error};
 
 // 测试命令
+#[cfg(feature = "gui")]
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+#[cfg(feature = "gui")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // 初始化日志
@@ -65,7 +74,11 @@ pub fn run() {
             });
             
             // 启动智能调度器
-            modules::scheduler::start_scheduler(app.handle().clone());
+            let sink: modules::events::SharedEventSink = std::sync::Arc::new(app.handle().clone());
+            let proxy_state = app.state::<commands::proxy::ProxyServiceState>().inner().clone();
+            tauri::async_runtime::spawn(async move {
+                modules::scheduler::start_scheduler(sink, proxy_state);
+            });
             
             Ok(())
         })
@@ -129,6 +142,7 @@ pub fn run() {
             commands::should_check_updates,
             commands::update_last_check_time,
             commands::toggle_proxy_status,
+            commands::update_account_tags,
             // 反代服务命令
             commands::proxy::start_proxy_service,
             commands::proxy::stop_proxy_service,
@@ -33,6 +33,9 @@ pub struct Account {
     /// Unix timestamp when the proxy was disabled.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub proxy_disabled_at: Option<i64>,
+    /// 账号标签 (反代路由规则按标签将请求限定到账号分组)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub created_at: i64,
     pub last_used: i64,
 }
@@ -54,6 +57,7 @@ impl Account {
             proxy_disabled: false,
             proxy_disabled_reason: None,
             proxy_disabled_at: None,
+            tags: Vec::new(),
             created_at: now,
             last_used: now,
         }
@@ -690,6 +690,21 @@ pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), St
     save_account(&account)
 }
 
+/// 设置账号标签 (去除首尾空白、空项与重复项)
+pub fn update_account_tags(account_id: &str, tags: Vec<String>) -> Result<Account, String> {
+    let mut account = load_account(account_id)?;
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    account.tags = normalized;
+    save_account(&account)?;
+    Ok(account)
+}
+
 /// 导出所有账号的 refresh_token
 #[allow(dead_code)]
 pub fn export_accounts() -> Result<Vec<(String, String)>, String> {
@@ -4,7 +4,7 @@ use serde_json;
 use crate::models::AppConfig;
 use super::account::get_data_dir;
 
-const CONFIG_FILE: &str = "gui_config.json";
+pub const CONFIG_FILE: &str = "gui_config.json";
 
 /// 加载应用配置
 pub fn load_app_config() -> Result<AppConfig, String> {
@@ -25,42 +25,42 @@ pub fn load_app_config() -> Result<AppConfig, String> {
 
     // 迁移逻辑
     if let Some(proxy) = v.get_mut("proxy") {
-        let mut custom_mapping = proxy.get("custom_mapping")
-            .and_then(|m| m.as_object())
-            .map(|m| m.clone())
-            .unwrap_or_default();
-
-        // 迁移 Anthropic 映射
-        if let Some(anthropic) = proxy.get_mut("anthropic_mapping").and_then(|m| m.as_object_mut()) {
-            for (k, v) in anthropic.iter() {
-                // 只有非系列字段才搬移。因为系列字段现在由 Preset 逻辑或内置表处理
-                if !k.ends_with("-series") {
-                    if !custom_mapping.contains_key(k) {
-                        custom_mapping.insert(k.clone(), v.clone());
-                    }
-                }
+        // custom_mapping 可能是规则列表或旧版对象，统一转换为规则列表后合并旧字段
+        let mut custom_mapping: Vec<crate::proxy::config::ModelMappingRule> = match proxy.get("custom_mapping") {
+            Some(serde_json::Value::Object(m)) => {
+                let legacy: std::collections::HashMap<String, String> = m
+                    .iter()
+                    .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                    .collect();
+                crate::proxy::config::mapping_rules_from_legacy(&legacy)
             }
-            // 移除旧字段
-            proxy.as_object_mut().unwrap().remove("anthropic_mapping");
-            modified = true;
-        }
+            Some(rules) => serde_json::from_value(rules.clone()).unwrap_or_default(),
+            None => Vec::new(),
+        };
 
-        // 迁移 OpenAI 映射
-        if let Some(openai) = proxy.get_mut("openai_mapping").and_then(|m| m.as_object_mut()) {
-            for (k, v) in openai.iter() {
-                if !k.ends_with("-series") {
-                    if !custom_mapping.contains_key(k) {
-                        custom_mapping.insert(k.clone(), v.clone());
+        // 迁移 Anthropic / OpenAI 映射
+        for field in ["anthropic_mapping", "openai_mapping"] {
+            let Some(legacy) = proxy.get(field).and_then(|m| m.as_object()) else {
+                continue;
+            };
+            let mut entries = std::collections::HashMap::new();
+            for (k, v) in legacy.iter() {
+                // 只有非系列字段才搬移。因为系列字段现在由 Preset 逻辑或内置表处理
+                if !k.ends_with("-series") && !custom_mapping.iter().any(|r| &r.pattern == k) {
+                    if let Some(v) = v.as_str() {
+                        entries.insert(k.clone(), v.to_string());
                     }
                 }
             }
+            custom_mapping.extend(crate::proxy::config::mapping_rules_from_legacy(&entries));
             // 移除旧字段
-            proxy.as_object_mut().unwrap().remove("openai_mapping");
+            proxy.as_object_mut().unwrap().remove(field);
             modified = true;
         }
 
         if modified {
-            proxy.as_object_mut().unwrap().insert("custom_mapping".to_string(), serde_json::Value::Object(custom_mapping));
+            let rules = serde_json::to_value(&custom_mapping).unwrap_or_default();
+            proxy.as_object_mut().unwrap().insert("custom_mapping".to_string(), rules);
         }
     }
 
@@ -0,0 +1,91 @@
+// 配置文件热加载
+// 监听数据目录下的 gui_config.json，变更后重新加载、校验并热更新运行中的反代服务
+use crate::commands::proxy::{self, ProxyServiceState};
+use crate::modules::config::{self, CONFIG_FILE};
+use crate::modules::events::SharedEventSink;
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::time::{self, Duration};
+
+/// 编辑器保存或配置管理工具写入通常会触发多次事件，合并后再加载
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// 启动配置文件监听 (监听目录而非文件本身，兼容先写临时文件再重命名的保存方式)
+pub fn start_config_watcher(
+    state: ProxyServiceState,
+    event_sink: SharedEventSink,
+) -> Result<(), String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        if event
+            .paths
+            .iter()
+            .any(|p| p.file_name().is_some_and(|name| name == CONFIG_FILE))
+        {
+            let _ = tx.send(());
+        }
+    })
+    .map_err(|e| format!("创建配置文件监听失败: {}", e))?;
+
+    watcher
+        .watch(&data_dir, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("监听目录 {} 失败: {}", data_dir.display(), e))?;
+
+    tracing::info!("已启用配置文件热加载: {}", data_dir.join(CONFIG_FILE).display());
+
+    tokio::spawn(async move {
+        // watcher 需在任务存续期间保持存活
+        let _watcher = watcher;
+        while rx.recv().await.is_some() {
+            time::sleep(DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+            reload_config(&state, &event_sink).await;
+        }
+    });
+
+    Ok(())
+}
+
+/// 重新加载配置并热更新，加载或校验失败时保持当前配置
+async fn reload_config(state: &ProxyServiceState, event_sink: &SharedEventSink) {
+    let app_config = match config::load_app_config() {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::warn!("配置文件重新加载失败，保持当前配置: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = app_config.proxy.validate() {
+        tracing::warn!("配置文件校验未通过，保持当前配置: {}", e);
+        return;
+    }
+
+    let Some(report) = proxy::internal_apply_proxy_config(state, &app_config.proxy).await else {
+        tracing::debug!("配置文件已变更，反代服务未运行，下次启动时生效");
+        return;
+    };
+    if !report.changed {
+        return;
+    }
+
+    if report.restart_required.is_empty() {
+        tracing::info!("配置文件已热加载");
+    } else {
+        tracing::warn!(
+            "配置文件已热加载，以下配置需重启反代服务后生效: {}",
+            report.restart_required.join(", ")
+        );
+    }
+    event_sink.emit(
+        "config://reloaded",
+        serde_json::to_value(&report).unwrap_or_default(),
+    );
+}
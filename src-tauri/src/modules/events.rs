@@ -0,0 +1,27 @@
+// 事件分发抽象
+// GUI 模式下事件通过 Tauri 推送到前端; Headless 模式下仅写入日志
+use std::sync::Arc;
+
+/// 事件接收端 (替代直接持有 tauri::AppHandle)
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: &str, payload: serde_json::Value);
+}
+
+pub type SharedEventSink = Arc<dyn EventSink>;
+
+#[cfg(feature = "gui")]
+impl EventSink for tauri::AppHandle {
+    fn emit(&self, event: &str, payload: serde_json::Value) {
+        use tauri::Emitter;
+        let _ = Emitter::emit(self, event, payload);
+    }
+}
+
+/// Headless 模式使用的事件接收端: 没有前端可推送，只记录调试日志
+pub struct LogEventSink;
+
+impl EventSink for LogEventSink {
+    fn emit(&self, event: &str, _payload: serde_json::Value) {
+        tracing::debug!("[Event] {}", event);
+    }
+}
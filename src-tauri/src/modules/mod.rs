@@ -5,14 +5,18 @@ pub mod logger;
 pub mod db;
 pub mod process;
 pub mod oauth;
+#[cfg(feature = "gui")]
 pub mod oauth_server;
 pub mod migration;
+#[cfg(feature = "gui")]
 pub mod tray;
 pub mod i18n;
 pub mod proxy_db;
 pub mod device;
 pub mod update_checker;
 pub mod scheduler;
+pub mod events;
+pub mod config_watcher;
 
 use crate::models;
 
@@ -32,6 +32,8 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_email TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN api_key_name TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN scheduling_trace TEXT", []); // JSON
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -44,16 +46,31 @@ pub fn init_db() -> Result<(), String> {
         [],
     ).map_err(|e| e.to_string())?;
 
+    // API Key token 用量计数 (period: "d:YYYY-MM-DD" / "m:YYYY-MM")
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_key_usage (
+            key_name TEXT NOT NULL,
+            period TEXT NOT NULL,
+            tokens INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (key_name, period)
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
 pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let scheduling_trace = log
+        .scheduling_trace
+        .as_ref()
+        .and_then(|t| serde_json::to_string(t).ok());
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, api_key_name, scheduling_trace)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         params![
             log.id,
             log.timestamp,
@@ -69,6 +86,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.output_tokens,
             log.account_email,
             log.mapped_model,
+            log.api_key_name,
+            scheduling_trace,
         ],
     ).map_err(|e| e.to_string())?;
 
@@ -83,7 +102,7 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model
+                input_tokens, output_tokens, account_email, mapped_model, api_key_name
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
@@ -105,6 +124,8 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
             response_body: None, // Don't query large fields for list view
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            api_key_name: row.get(14).unwrap_or(None),
+            scheduling_trace: None, // Only returned by get_log_detail
         })
     }).map_err(|e| e.to_string())?;
 
@@ -150,7 +171,7 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model
+                account_email, mapped_model, api_key_name, scheduling_trace
          FROM request_logs 
          WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
@@ -171,6 +192,11 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
             response_body: row.get(9).unwrap_or(None),
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            api_key_name: row.get(14).unwrap_or(None),
+            scheduling_trace: row
+                .get::<_, Option<String>>(15)
+                .unwrap_or(None)
+                .and_then(|s| serde_json::from_str(&s).ok()),
         })
     }).map_err(|e| e.to_string())
 }
@@ -210,6 +236,40 @@ pub fn limit_max_logs(max_count: usize) -> Result<usize, String> {
     Ok(deleted)
 }
 
+/// 读取 API Key 在指定周期内的 token 用量
+pub fn get_key_usage(key_name: &str, period: &str) -> Result<u64, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let tokens: Option<i64> = conn
+        .query_row(
+            "SELECT tokens FROM api_key_usage WHERE key_name = ?1 AND period = ?2",
+            params![key_name, period],
+            |row| row.get(0),
+        )
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            other => Err(other),
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(tokens.unwrap_or(0).max(0) as u64)
+}
+
+/// 累加 API Key 在指定周期内的 token 用量
+pub fn add_key_usage(key_name: &str, period: &str, tokens: u64) -> Result<(), String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT INTO api_key_usage (key_name, period, tokens) VALUES (?1, ?2, ?3)
+         ON CONFLICT(key_name, period) DO UPDATE SET tokens = tokens + excluded.tokens",
+        params![key_name, period, tokens as i64],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
 pub fn clear_logs() -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
@@ -5,7 +5,8 @@ use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Mutex;
-use tauri::{Emitter, Manager};
+use crate::commands::proxy::ProxyServiceState;
+use crate::modules::events::SharedEventSink;
 use tokio::time::{self, Duration};
 
 // 预热历史记录：key = "email:model_name:100", value = 预热时间戳
@@ -98,8 +99,10 @@ fn is_in_warmup_window(peak_hours: &[String]) -> Option<String> {
     None
 }
 
-pub fn start_scheduler(app_handle: tauri::AppHandle) {
-    tauri::async_runtime::spawn(async move {
+/// 启动预热调度器
+/// 需在 tokio 运行时内调用；事件通过 event_sink 分发 (GUI 推送到前端，Headless 仅记录日志)
+pub fn start_scheduler(event_sink: SharedEventSink, proxy_state: ProxyServiceState) {
+    tokio::spawn(async move {
         logger::log_info("Peak-Based Smart Warmup Scheduler started. Checking warmup windows...");
 
         // 每 10 分钟扫描一次
@@ -275,7 +278,8 @@ pub fn start_scheduler(app_handle: tauri::AppHandle) {
                     total
                 ));
 
-                let handle_for_warmup = app_handle.clone();
+                let sink_for_warmup = event_sink.clone();
+                let state_for_warmup = proxy_state.clone();
                 tokio::spawn(async move {
                     let mut success = 0;
                     let batch_size = 3;
@@ -331,13 +335,11 @@ pub fn start_scheduler(app_handle: tauri::AppHandle) {
 
                     // 刷新配额，同步到前端
                     tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                    let state =
-                        handle_for_warmup.state::<crate::commands::proxy::ProxyServiceState>();
-                    let _ = crate::commands::refresh_all_quotas(state).await;
+                    let _ = crate::commands::internal_refresh_all_quotas(&state_for_warmup).await;
 
                     // [FIX] 发送事件通知前端刷新账号列表
                     logger::log_info("[Scheduler] Emitting quota-updated event to frontend");
-                    let _ = handle_for_warmup.emit("quota-updated", ());
+                    sink_for_warmup.emit("quota-updated", serde_json::Value::Null);
                 });
             } else if skipped_cooldown > 0 {
                 logger::log_info(&format!(
@@ -349,11 +351,10 @@ pub fn start_scheduler(app_handle: tauri::AppHandle) {
             }
 
             // 扫描完成后刷新前端显示（确保调度器获取的最新数据同步到 UI）
-            let handle_inner = app_handle.clone();
+            let state_inner = proxy_state.clone();
             tokio::spawn(async move {
                 tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                let state = handle_inner.state::<crate::commands::proxy::ProxyServiceState>();
-                let _ = crate::commands::refresh_all_quotas(state).await;
+                let _ = crate::commands::internal_refresh_all_quotas(&state_inner).await;
                 logger::log_info("[Scheduler] Quota data synced to frontend");
             });
 
@@ -0,0 +1,237 @@
+// 准入排队
+// 账号池对请求的模型全部处于限流锁定时，请求在 TokenManager 前按先后顺序排队：
+// 同一模型的请求先进先出，只有该模型的队首等待最早的锁定解除后重试，放行或离开时唤醒下一位，
+// 避免所有等待者同时醒来争抢账号；该模型已有请求排队时新请求直接排到队尾，不与队首争抢释放的账号；
+// 超出队列深度或等待上限的请求由 handler 返回带 retry-after 的 429
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+struct Waiter {
+    id: u64,
+    /// 请求的模型，队首按模型分别计算
+    model: String,
+    notify: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// 排队中的请求 (同一模型内先进先出)
+    waiters: Mutex<VecDeque<Waiter>>,
+    next_id: AtomicU64,
+    /// 最近一次入队/重试时预计的等待秒数
+    eta_seconds: AtomicU64,
+    admitted: AtomicU64,
+    rejected_full: AtomicU64,
+    rejected_timeout: AtomicU64,
+}
+
+impl QueueState {
+    fn depth(&self) -> usize {
+        self.waiters.lock().map(|w| w.len()).unwrap_or(0)
+    }
+}
+
+/// 指定模型的队首
+fn head_of<'a>(waiters: &'a VecDeque<Waiter>, model: &str) -> Option<&'a Waiter> {
+    waiters.iter().find(|w| w.model == model)
+}
+
+/// 队列统计，供 Prometheus 指标导出
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdmissionStats {
+    pub depth: usize,
+    pub eta_seconds: u64,
+    pub admitted: u64,
+    pub rejected_full: u64,
+    pub rejected_timeout: u64,
+}
+
+#[derive(Default)]
+pub struct AdmissionQueue {
+    state: Arc<QueueState>,
+}
+
+/// 排队凭证，Drop 时离开队列 (队首离开时唤醒下一位)
+pub struct QueueTicket {
+    state: Arc<QueueState>,
+    id: u64,
+    model: String,
+    notify: Arc<Notify>,
+    /// 入队时在同一模型请求中的位置 (1 表示队首)
+    pub position: usize,
+}
+
+impl QueueTicket {
+    /// 是否位于同一模型请求的队首
+    pub fn is_head(&self) -> bool {
+        self.state
+            .waiters
+            .lock()
+            .map(|w| head_of(&w, &self.model).is_some_and(|head| head.id == self.id))
+            .unwrap_or(true)
+    }
+
+    /// 等待轮到自己 (成为队首)
+    pub async fn wait_turn(&self) {
+        // notify_one 在无人等待时保留许可，检查与等待之间的唤醒不会丢失
+        while !self.is_head() {
+            self.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        let Ok(mut waiters) = self.state.waiters.lock() else {
+            return;
+        };
+        let was_head = head_of(&waiters, &self.model).is_some_and(|head| head.id == self.id);
+        let Some(index) = waiters.iter().position(|w| w.id == self.id) else {
+            return;
+        };
+        waiters.remove(index);
+        if was_head {
+            if let Some(next) = head_of(&waiters, &self.model) {
+                next.notify.notify_one();
+            }
+        }
+    }
+}
+
+impl AdmissionQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 尝试入队 (排到队尾)，队列已满 (或 max_depth 为 0 表示不排队) 时返回 None
+    /// `max_depth` 限制所有模型的排队总数
+    pub fn enter(&self, model: &str, max_depth: u32, eta_seconds: u64) -> Option<QueueTicket> {
+        let ticket = {
+            let mut waiters = self.state.waiters.lock().ok()?;
+            if waiters.len() < max_depth as usize {
+                let id = self.state.next_id.fetch_add(1, Ordering::Relaxed);
+                let notify = Arc::new(Notify::new());
+                waiters.push_back(Waiter { id, model: model.to_string(), notify: notify.clone() });
+                Some(QueueTicket {
+                    state: self.state.clone(),
+                    id,
+                    model: model.to_string(),
+                    notify,
+                    position: waiters.iter().filter(|w| w.model == model).count(),
+                })
+            } else {
+                None
+            }
+        };
+        match &ticket {
+            Some(_) => self.set_eta(eta_seconds),
+            None => {
+                self.state.rejected_full.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        ticket
+    }
+
+    /// 指定模型是否已有请求在排队
+    pub fn has_waiters(&self, model: &str) -> bool {
+        self.state
+            .waiters
+            .lock()
+            .map(|w| head_of(&w, model).is_some())
+            .unwrap_or(false)
+    }
+
+    /// 最近一次预计的等待秒数
+    pub fn eta(&self) -> u64 {
+        self.state.eta_seconds.load(Ordering::Relaxed)
+    }
+
+    pub fn set_eta(&self, eta_seconds: u64) {
+        self.state.eta_seconds.store(eta_seconds, Ordering::Relaxed);
+    }
+
+    pub fn record_admitted(&self) {
+        self.state.admitted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_timeout(&self) {
+        self.state.rejected_timeout.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> AdmissionStats {
+        let depth = self.state.depth();
+        AdmissionStats {
+            depth,
+            // 队列为空时 ETA 无意义
+            eta_seconds: if depth == 0 { 0 } else { self.state.eta_seconds.load(Ordering::Relaxed) },
+            admitted: self.state.admitted.load(Ordering::Relaxed),
+            rejected_full: self.state.rejected_full.load(Ordering::Relaxed),
+            rejected_timeout: self.state.rejected_timeout.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_depth_limit_and_release() {
+        let queue = AdmissionQueue::new();
+        let first = queue.enter("m", 2, 30).unwrap();
+        let second = queue.enter("m", 2, 30).unwrap();
+        assert_eq!((first.position, second.position), (1, 2));
+        assert!(queue.enter("m", 2, 30).is_none());
+        assert_eq!(queue.stats().rejected_full, 1);
+        assert_eq!(queue.stats().eta_seconds, 30);
+
+        drop(first);
+        assert_eq!(queue.stats().depth, 1);
+        assert!(queue.enter("m", 0, 30).is_none());
+        drop(second);
+        assert_eq!(queue.stats().eta_seconds, 0);
+    }
+
+    #[tokio::test]
+    async fn test_waiters_are_released_in_order() {
+        let queue = AdmissionQueue::new();
+        let first = queue.enter("m", 3, 5).unwrap();
+        let second = queue.enter("m", 3, 5).unwrap();
+        let third = queue.enter("m", 3, 5).unwrap();
+        assert!(first.is_head() && !second.is_head());
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for ticket in [third, second] {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                ticket.wait_turn().await;
+                tx.send(ticket.position).unwrap();
+                // 放行后离开队列，唤醒下一位
+            });
+        }
+        tokio::task::yield_now().await;
+        assert!(rx.try_recv().is_err());
+
+        drop(first);
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(3));
+    }
+
+    #[test]
+    fn test_heads_are_per_model() {
+        let queue = AdmissionQueue::new();
+        let pro = queue.enter("gemini-3-pro-high", 3, 5).unwrap();
+        let flash = queue.enter("gemini-3-flash", 3, 5).unwrap();
+        let pro_second = queue.enter("gemini-3-pro-high", 3, 5).unwrap();
+        assert!(pro.is_head() && flash.is_head() && !pro_second.is_head());
+        assert_eq!((flash.position, pro_second.position), (1, 2));
+        assert!(queue.has_waiters("gemini-3-flash") && !queue.has_waiters("claude-sonnet-4-5"));
+
+        drop(flash);
+        assert!(!queue.has_waiters("gemini-3-flash"));
+        drop(pro);
+        assert!(pro_second.is_head());
+    }
+}
@@ -0,0 +1,254 @@
+// 账号熔断器
+// 连续 5xx、网络错误或空响应流达到阈值后熔断 (open)，冷却结束进入半开 (half-open)，
+// 由后台探测任务复用 /internal/warmup 的请求构建发送一次轻量请求，成功后才恢复真实流量
+use dashmap::DashMap;
+use std::collections::HashSet;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::proxy::token_manager::TokenManager;
+use crate::proxy::upstream::client::UpstreamClient;
+
+/// 多次熔断时冷却时间翻倍的上限 (秒)
+const MAX_OPEN_SECONDS: u64 = 600;
+/// 后台探测任务的检查间隔
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+/// 未记录失败模型时使用的探测模型
+const DEFAULT_PROBE_MODEL: &str = "gemini-2.5-flash";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BreakerEntry {
+    state: BreakerState,
+    consecutive_failures: u32,
+    /// 本轮熔断前累计的熔断次数 (决定冷却时间)
+    trips: u32,
+    open_until: Instant,
+    /// 最近一次失败的模型，半开探测使用
+    probe_model: Option<String>,
+}
+
+impl BreakerEntry {
+    fn open(&mut self, open_seconds: u64) {
+        let cooldown = open_seconds
+            .saturating_mul(1u64 << self.trips.min(16))
+            .min(MAX_OPEN_SECONDS.max(open_seconds));
+        self.state = BreakerState::Open;
+        self.open_until = Instant::now() + Duration::from_secs(cooldown);
+        self.trips += 1;
+    }
+}
+
+#[derive(Default)]
+pub struct CircuitBreaker {
+    entries: DashMap<String, BreakerEntry>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次失败，返回本次是否触发熔断
+    /// `threshold` 为 0 表示关闭熔断
+    pub fn record_failure(&self, account: &str, model: Option<&str>, threshold: u32, open_seconds: u64) -> bool {
+        if threshold == 0 {
+            return false;
+        }
+        let mut entry = self.entries.entry(account.to_string()).or_insert_with(|| BreakerEntry {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            trips: 0,
+            open_until: Instant::now(),
+            probe_model: None,
+        });
+        if model.is_some() {
+            entry.probe_model = model.map(|m| m.to_string());
+        }
+        if entry.state != BreakerState::Closed {
+            return false;
+        }
+        entry.consecutive_failures += 1;
+        if entry.consecutive_failures < threshold {
+            return false;
+        }
+        entry.open(open_seconds);
+        true
+    }
+
+    /// 请求成功，恢复为关闭状态
+    pub fn record_success(&self, account: &str) {
+        self.entries.remove(account);
+    }
+
+    /// 移除账号的熔断状态 (账号被删除或禁用时调用)
+    pub fn remove(&self, account: &str) {
+        self.entries.remove(account);
+    }
+
+    /// 仅保留仍在账号池中的账号的熔断状态
+    pub fn retain_accounts(&self, accounts: &HashSet<String>) {
+        self.entries.retain(|account, _| accounts.contains(account));
+    }
+
+    /// 账号是否可以接收真实流量 (仅关闭状态)
+    pub fn allows(&self, account: &str) -> bool {
+        self.state(account) == BreakerState::Closed
+    }
+
+    pub fn state(&self, account: &str) -> BreakerState {
+        self.entries.get(account).map_or(BreakerState::Closed, |e| e.state)
+    }
+
+    /// 冷却结束的账号转入半开状态并返回 (账号, 探测模型)，每个账号同一时间只有一次探测
+    pub fn take_due_probes(&self) -> Vec<(String, String)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for mut entry in self.entries.iter_mut() {
+            if entry.state == BreakerState::Open && entry.open_until <= now {
+                entry.state = BreakerState::HalfOpen;
+                let model = entry.probe_model.clone().unwrap_or_else(|| DEFAULT_PROBE_MODEL.to_string());
+                due.push((entry.key().clone(), model));
+            }
+        }
+        due
+    }
+
+    /// 半开探测结果: 成功则关闭，失败则以翻倍的冷却时间重新熔断
+    pub fn record_probe_result(&self, account: &str, success: bool, open_seconds: u64) {
+        if success {
+            self.entries.remove(account);
+            return;
+        }
+        if let Some(mut entry) = self.entries.get_mut(account) {
+            if entry.state == BreakerState::HalfOpen {
+                entry.open(open_seconds);
+            }
+        }
+    }
+
+    /// 非关闭状态的账号，供 Prometheus 指标导出
+    pub fn snapshot(&self) -> Vec<(String, BreakerState)> {
+        let mut states: Vec<(String, BreakerState)> = self
+            .entries
+            .iter()
+            .filter(|e| e.state != BreakerState::Closed)
+            .map(|e| (e.key().clone(), e.state))
+            .collect();
+        states.sort_by(|a, b| a.0.cmp(&b.0));
+        states
+    }
+}
+
+/// 启动半开探测任务，TokenManager 释放后自动退出
+pub fn spawn_prober(token_manager: Weak<TokenManager>, upstream: Arc<UpstreamClient>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let Some(tm) = token_manager.upgrade() else {
+                break;
+            };
+            for (email, model) in tm.take_due_probes() {
+                let tm = tm.clone();
+                let upstream = upstream.clone();
+                tokio::spawn(async move {
+                    let success = probe(&tm, &upstream, &email, &model).await;
+                    tm.record_probe_result(&email, success).await;
+                });
+            }
+        }
+    });
+}
+
+async fn probe(tm: &TokenManager, upstream: &UpstreamClient, email: &str, model: &str) -> bool {
+    let result = async {
+        let (access_token, project_id, _) = tm.get_token_by_email(email).await?;
+        let call = crate::proxy::handlers::warmup::build_warmup_request(model, &project_id)?;
+        let response = upstream
+            .call_v1_internal(call.method, &access_token, call.body, call.query)
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("HTTP {}", response.status().as_u16()))
+        }
+    }
+    .await;
+
+    match result {
+        Ok(()) => {
+            tracing::info!("[CircuitBreaker] Probe succeeded for {} ({}), closing circuit", email, model);
+            true
+        }
+        Err(e) => {
+            tracing::warn!("[CircuitBreaker] Probe failed for {} ({}): {}", email, model, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_probes_and_closes() {
+        let breaker = CircuitBreaker::new();
+        assert!(!breaker.record_failure("a", Some("gemini-3-flash"), 3, 0));
+        assert!(!breaker.record_failure("a", None, 3, 0));
+        breaker.record_success("a");
+        assert!(!breaker.record_failure("a", None, 3, 0));
+        assert!(!breaker.record_failure("a", Some("gemini-3-flash"), 3, 0));
+        assert!(breaker.record_failure("a", None, 3, 0));
+        assert_eq!(breaker.state("a"), BreakerState::Open);
+        assert!(!breaker.allows("a"));
+
+        // 冷却结束 -> 半开，仅返回一次探测
+        assert_eq!(breaker.take_due_probes(), vec![("a".to_string(), "gemini-3-flash".to_string())]);
+        assert!(breaker.take_due_probes().is_empty());
+        assert_eq!(breaker.state("a"), BreakerState::HalfOpen);
+        assert!(!breaker.allows("a"));
+
+        breaker.record_probe_result("a", false, 0);
+        assert_eq!(breaker.state("a"), BreakerState::Open);
+        assert_eq!(breaker.take_due_probes().len(), 1);
+        breaker.record_probe_result("a", true, 0);
+        assert!(breaker.allows("a"));
+        assert!(breaker.snapshot().is_empty());
+
+        // 阈值为 0 时不熔断
+        for _ in 0..10 {
+            assert!(!breaker.record_failure("b", None, 0, 30));
+        }
+        assert!(breaker.allows("b"));
+    }
+
+    #[test]
+    fn test_breaker_state_pruned_for_removed_accounts() {
+        let breaker = CircuitBreaker::new();
+        for account in ["a", "b", "c"] {
+            assert!(breaker.record_failure(account, None, 1, 30));
+        }
+        breaker.remove("a");
+        breaker.retain_accounts(&HashSet::from(["b".to_string()]));
+        assert_eq!(breaker.snapshot(), vec![("b".to_string(), BreakerState::Open)]);
+        assert!(breaker.allows("a") && breaker.allows("c"));
+    }
+}
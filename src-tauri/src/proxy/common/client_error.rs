@@ -0,0 +1,121 @@
+// 面向客户端的协议错误响应
+// 代理自身拒绝请求时 (鉴权/权限/限额)，按调用方使用的协议返回对应的错误结构，
+// 便于 OpenAI / Anthropic / Gemini SDK 正确解析与重试
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+
+/// 客户端使用的 API 协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiProtocol {
+    OpenAI,
+    Claude,
+    Gemini,
+}
+
+impl ApiProtocol {
+    /// 根据请求路径推断协议 (无法识别时按 OpenAI 处理)
+    pub fn from_path(path: &str) -> Self {
+        if path.starts_with("/v1beta/") {
+            ApiProtocol::Gemini
+        } else if path.starts_with("/v1/messages") || path == "/v1/models/claude" {
+            ApiProtocol::Claude
+        } else {
+            ApiProtocol::OpenAI
+        }
+    }
+}
+
+/// 构造协议对应的错误 JSON
+pub fn error_body(protocol: ApiProtocol, status: StatusCode, message: &str) -> Value {
+    match protocol {
+        ApiProtocol::OpenAI => {
+            let (error_type, code) = match status {
+                StatusCode::UNAUTHORIZED => ("invalid_request_error", "invalid_api_key"),
+                StatusCode::FORBIDDEN => ("permission_error", "permission_denied"),
+                StatusCode::TOO_MANY_REQUESTS => ("rate_limit_error", "rate_limit_exceeded"),
+                _ => ("invalid_request_error", "invalid_request"),
+            };
+            json!({
+                "error": {
+                    "message": message,
+                    "type": error_type,
+                    "param": null,
+                    "code": code
+                }
+            })
+        }
+        ApiProtocol::Claude => {
+            let error_type = match status {
+                StatusCode::UNAUTHORIZED => "authentication_error",
+                StatusCode::FORBIDDEN => "permission_error",
+                StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+                _ => "invalid_request_error",
+            };
+            json!({
+                "type": "error",
+                "error": {
+                    "type": error_type,
+                    "message": message
+                }
+            })
+        }
+        ApiProtocol::Gemini => {
+            let grpc_status = match status {
+                StatusCode::UNAUTHORIZED => "UNAUTHENTICATED",
+                StatusCode::FORBIDDEN => "PERMISSION_DENIED",
+                StatusCode::TOO_MANY_REQUESTS => "RESOURCE_EXHAUSTED",
+                _ => "INVALID_ARGUMENT",
+            };
+            json!({
+                "error": {
+                    "code": status.as_u16(),
+                    "message": message,
+                    "status": grpc_status
+                }
+            })
+        }
+    }
+}
+
+/// 构造协议对应的错误响应
+pub fn error_response(protocol: ApiProtocol, status: StatusCode, message: &str) -> Response {
+    (status, Json(error_body(protocol, status, message))).into_response()
+}
+
+/// 构造协议对应的 429 响应，并在 retry-after 头中写入建议等待的秒数
+pub fn rate_limit_response(protocol: ApiProtocol, message: &str, retry_after_sec: u64) -> Response {
+    let mut response = error_response(protocol, StatusCode::TOO_MANY_REQUESTS, message);
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_sec));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_from_path() {
+        assert_eq!(ApiProtocol::from_path("/v1/messages"), ApiProtocol::Claude);
+        assert_eq!(ApiProtocol::from_path("/v1beta/models/gemini-3-flash:generateContent"), ApiProtocol::Gemini);
+        assert_eq!(ApiProtocol::from_path("/v1/chat/completions"), ApiProtocol::OpenAI);
+    }
+
+    #[test]
+    fn test_error_shapes() {
+        let body = error_body(ApiProtocol::Claude, StatusCode::FORBIDDEN, "nope");
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "permission_error");
+
+        let body = error_body(ApiProtocol::Gemini, StatusCode::TOO_MANY_REQUESTS, "slow down");
+        assert_eq!(body["error"]["code"], 429);
+        assert_eq!(body["error"]["status"], "RESOURCE_EXHAUSTED");
+
+        let body = error_body(ApiProtocol::OpenAI, StatusCode::TOO_MANY_REQUESTS, "slow down");
+        assert_eq!(body["error"]["type"], "rate_limit_error");
+    }
+}
@@ -3,5 +3,7 @@
 // pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
+pub mod request_features; // 模型路由使用的请求特征
 pub mod utils;
 pub mod json_schema;
+pub mod client_error;     // 面向客户端的协议错误响应
@@ -1,6 +1,12 @@
 // 模型名称映射
 use std::collections::HashMap;
 use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::proxy::common::request_features::RequestFeatures;
+use crate::proxy::config::{ModelFallbackConfig, ModelMappingRule, ProxyConfig, QuotaDowngradeRule};
+use crate::proxy::model_registry::{ModelDescriptor, ModelRegistry};
 
 static CLAUDE_TO_GEMINI: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
     let mut m = HashMap::new();
@@ -61,8 +67,8 @@ pub fn map_claude_model_to_gemini(input: &str) -> String {
         return mapped.to_string();
     }
 
-    // 2. Pass-through known prefixes (gemini-, -thinking) to support dynamic suffixes
-    if input.starts_with("gemini-") || input.contains("thinking") {
+    // 2. Pass-through models known to the capability registry (gemini-*, *-thinking, config entries)
+    if ModelRegistry::global().lookup(input).is_some() {
         return input.to_string();
     }
 
@@ -77,7 +83,7 @@ pub fn get_supported_models() -> Vec<String> {
 
 /// 动态获取所有可用模型列表 (包含内置与用户自定义)
 pub async fn get_all_dynamic_models(
-    custom_mapping: &tokio::sync::RwLock<std::collections::HashMap<String, String>>,
+    custom_mapping: &tokio::sync::RwLock<ModelRouter>,
 ) -> Vec<String> {
     use std::collections::HashSet;
     let mut model_ids = HashSet::new();
@@ -90,8 +96,8 @@ pub async fn get_all_dynamic_models(
     // 2. 获取所有自定义映射模型 (Custom)
     {
         let mapping = custom_mapping.read().await;
-        for key in mapping.keys() {
-            model_ids.insert(key.clone());
+        for rule in mapping.rules().iter().filter(|r| !r.regex) {
+            model_ids.insert(rule.pattern.clone());
         }
     }
 
@@ -125,56 +131,244 @@ pub async fn get_all_dynamic_models(
     sorted_ids
 }
 
-/// 通配符匹配辅助函数
-/// 支持简单的 * 通配符匹配
-/// 
-/// # 示例
-/// - `gpt-4*` 匹配 `gpt-4`, `gpt-4-turbo`, `gpt-4-0613` 等
-/// - `claude-3-5-sonnet-*` 匹配所有 3.5 sonnet 版本
-/// - `*-thinking` 匹配所有以 `-thinking` 结尾的模型
-fn wildcard_match(pattern: &str, text: &str) -> bool {
-    if let Some(star_pos) = pattern.find('*') {
-        let prefix = &pattern[..star_pos];
-        let suffix = &pattern[star_pos + 1..];
-        text.starts_with(prefix) && text.ends_with(suffix)
-    } else {
-        pattern == text
+/// 动态模型列表及其能力描述 (用于 /models 端点)
+pub async fn get_all_dynamic_model_descriptors(
+    custom_mapping: &tokio::sync::RwLock<ModelRouter>,
+) -> Vec<ModelDescriptor> {
+    let model_ids = get_all_dynamic_models(custom_mapping).await;
+    let router = custom_mapping.read().await;
+    model_ids.iter().map(|id| router.describe(id)).collect()
+}
+
+/// 多通配符 glob 匹配 (`*` 匹配任意长度字符串)
+/// 用于 API Key 模型白名单等场景，例如 `gemini-*-flash*`、`*`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+
+    // 中间片段按顺序贪心匹配
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+struct CompiledRule {
+    /// 在 custom_mapping 中的位置
+    index: usize,
+    regex: Option<Regex>,
+}
+
+/// 自定义模型映射规则 (按优先级排序并预编译正则)、模型降级链与配额降级规则
+#[derive(Default)]
+pub struct ModelRouter {
+    rules: Vec<ModelMappingRule>,
+    compiled: Vec<CompiledRule>,
+    fallback: ModelFallbackConfig,
+    quota_downgrade: Vec<QuotaDowngradeRule>,
+}
+
+impl ModelRouter {
+    pub fn new(rules: Vec<ModelMappingRule>) -> Self {
+        let mut compiled: Vec<CompiledRule> = Vec::with_capacity(rules.len());
+        for (index, rule) in rules.iter().enumerate() {
+            let regex = if rule.regex {
+                match Regex::new(&rule.pattern) {
+                    Ok(re) => Some(re),
+                    Err(e) => {
+                        tracing::warn!("[Router] 跳过无效的正则映射规则 {}: {}", rule.pattern, e);
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            compiled.push(CompiledRule { index, regex });
+        }
+        // 稳定排序: 优先级相同的规则保持列表顺序
+        compiled.sort_by_key(|c| std::cmp::Reverse(rules[c.index].priority));
+        Self { rules, compiled, ..Default::default() }
+    }
+
+    /// 由反代配置构建 (映射规则 + 降级链)
+    pub fn from_config(config: &ProxyConfig) -> Self {
+        Self {
+            fallback: config.model_fallback.clone(),
+            quota_downgrade: config.quota_downgrade.clone(),
+            ..Self::new(config.custom_mapping.clone())
+        }
     }
+
+    /// 替换映射规则，保留降级链与配额降级规则
+    pub fn set_rules(&mut self, rules: Vec<ModelMappingRule>) {
+        let fallback = std::mem::take(&mut self.fallback);
+        let quota_downgrade = std::mem::take(&mut self.quota_downgrade);
+        *self = Self { fallback, quota_downgrade, ..Self::new(rules) };
+    }
+
+    /// 映射后的模型无可用账号时依次尝试的替代模型
+    pub fn fallback_chain(&self, mapped_model: &str) -> &[String] {
+        self.fallback.chain(mapped_model)
+    }
+
+    /// 是否在响应中附加降级提示头
+    pub fn fallback_header(&self) -> bool {
+        self.fallback.response_header
+    }
+
+    /// 首条匹配映射后模型的配额降级规则
+    pub fn quota_downgrade_rule(&self, mapped_model: &str) -> Option<&QuotaDowngradeRule> {
+        self.quota_downgrade.iter().find(|r| glob_match(&r.model, mapped_model))
+    }
+
+    /// 按账号池平均剩余配额 (`pool_quota`) 逐级降级，返回实际发生的降级步骤
+    /// 目标模型也可能命中规则继续降级，同一模型不会重复访问；
+    /// 目标模型不满足 `allowed` (如 API Key 无权访问) 时停在当前模型
+    pub fn quota_downgrade(
+        &self,
+        mapped_model: &str,
+        pool_quota: impl Fn(&str) -> Option<f64>,
+        allowed: impl Fn(&str) -> bool,
+    ) -> Vec<QuotaDowngradeStep> {
+        let mut steps: Vec<QuotaDowngradeStep> = Vec::new();
+        let mut current = mapped_model.to_string();
+        while let Some(rule) = self.quota_downgrade_rule(&current) {
+            let Some(quota) = pool_quota(&current) else { break };
+            if quota >= rule.threshold_percentage as f64
+                || rule.target == mapped_model
+                || steps.iter().any(|s| s.to == rule.target)
+                || !allowed(&rule.target)
+            {
+                break;
+            }
+            steps.push(QuotaDowngradeStep {
+                from: current,
+                to: rule.target.clone(),
+                threshold_percentage: rule.threshold_percentage,
+                pool_quota: quota,
+            });
+            current = rule.target.clone();
+        }
+        steps
+    }
+
+    /// 规则列表 (配置中的原始顺序)
+    pub fn rules(&self) -> &[ModelMappingRule] {
+        &self.rules
+    }
+
+    /// 模型能力描述: 已登记的名称直接查注册表，否则按映射后的模型描述 (不带请求特征，不写日志)
+    pub fn describe(&self, model: &str) -> ModelDescriptor {
+        let registry = ModelRegistry::global();
+        let descriptor = registry.lookup(model).unwrap_or_else(|| {
+            let mapped = self
+                .find(model, &RequestFeatures::default())
+                .map(|(target, _)| target)
+                .unwrap_or_else(|| map_claude_model_to_gemini(model));
+            registry.get(&mapped)
+        });
+        ModelDescriptor { id: model.to_string(), ..descriptor }
+    }
+
+    /// 查找首条命中的规则 (模型名与请求特征条件均满足)，返回 (目标模型, 规则下标)
+    fn find(&self, model: &str, features: &RequestFeatures) -> Option<(String, usize)> {
+        self.compiled.iter().find_map(|c| {
+            let rule = &self.rules[c.index];
+            if !rule.when.matches(features) {
+                return None;
+            }
+            match &c.regex {
+                Some(re) => re.captures(model).map(|caps| {
+                    let mut target = String::new();
+                    caps.expand(&rule.target, &mut target);
+                    (target, c.index)
+                }),
+                None => glob_match(&rule.pattern, model).then(|| (rule.target.clone(), c.index)),
+            }
+        })
+    }
+}
+
+/// 一次配额降级
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuotaDowngradeStep {
+    pub from: String,
+    pub to: String,
+    pub threshold_percentage: u32,
+    /// 降级时账号池对 `from` 的平均剩余配额 (%)
+    pub pool_quota: f64,
+}
+
+/// 命中的自定义映射规则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchedMappingRule {
+    /// 在 custom_mapping 中的位置
+    pub index: usize,
+    #[serde(flatten)]
+    pub rule: ModelMappingRule,
+}
+
+/// 模型路由解析结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModelRoute {
+    pub mapped_model: String,
+    /// None 表示未命中自定义规则，使用系统默认映射
+    pub rule: Option<MatchedMappingRule>,
 }
 
 /// 核心模型路由解析引擎
-/// 优先级：精确匹配 > 通配符匹配 > 系统默认映射
+/// 优先级：自定义规则 (按 priority 与列表顺序) > 系统默认映射
 /// 
 /// # 参数
 /// - `original_model`: 原始模型名称
-/// - `custom_mapping`: 用户自定义映射表
+/// - `custom_mapping`: 用户自定义映射规则
+/// - `features`: 请求特征 (图片/工具/思考/输入长度/调用方/协议)，用于带 `when` 条件的规则
 /// 
 /// # 返回
 /// 映射后的目标模型名称
 pub fn resolve_model_route(
     original_model: &str,
-    custom_mapping: &std::collections::HashMap<String, String>,
+    custom_mapping: &ModelRouter,
+    features: &RequestFeatures,
 ) -> String {
-    // 1. 精确匹配 (最高优先级)
-    if let Some(target) = custom_mapping.get(original_model) {
-        crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
-        return target.clone();
-    }
-    
-    // 2. 通配符匹配
-    for (pattern, target) in custom_mapping.iter() {
-        if pattern.contains('*') && wildcard_match(pattern, original_model) {
-            crate::modules::logger::log_info(&format!("[Router] 通配符映射: {} -> {} (规则: {})", original_model, target, pattern));
-            return target.clone();
-        }
+    resolve_model_route_detailed(original_model, custom_mapping, features).mapped_model
+}
+
+/// 同 `resolve_model_route`，并返回命中的规则 (用于 /v1/models/detect)
+pub fn resolve_model_route_detailed(
+    original_model: &str,
+    custom_mapping: &ModelRouter,
+    features: &RequestFeatures,
+) -> ModelRoute {
+    if let Some((target, index)) = custom_mapping.find(original_model, features) {
+        let rule = &custom_mapping.rules()[index];
+        crate::modules::logger::log_info(&format!(
+            "[Router] 自定义映射: {} -> {} (规则 #{}: {})",
+            original_model, target, index, rule.pattern
+        ));
+        return ModelRoute {
+            mapped_model: target,
+            rule: Some(MatchedMappingRule { index, rule: rule.clone() }),
+        };
     }
-    
-    // 3. 系统默认映射
+
+    // 系统默认映射
     let result = map_claude_model_to_gemini(original_model);
     if result != original_model {
         crate::modules::logger::log_info(&format!("[Router] 系统默认映射: {} -> {}", original_model, result));
     }
-    result
+    ModelRoute { mapped_model: result, rule: None }
 }
 
 #[cfg(test)]
@@ -201,4 +395,163 @@ mod tests {
             "claude-sonnet-4-5"
         );
     }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "gemini-3-flash"));
+        assert!(glob_match("gemini-3-flash", "gemini-3-flash"));
+        assert!(glob_match("gemini-*", "gemini-3-pro-high"));
+        assert!(glob_match("gemini-*-flash*", "gemini-2.5-flash-lite"));
+        assert!(glob_match("*-thinking", "claude-opus-4-5-thinking"));
+        assert!(!glob_match("gemini-*-flash*", "gemini-3-pro-high"));
+        assert!(!glob_match("claude-*", "gemini-3-flash"));
+        // 前后缀不能重叠
+        assert!(!glob_match("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn test_resolve_model_route_rules() {
+        let router = ModelRouter::new(vec![
+            ModelMappingRule::new("gpt-4*", "gemini-3-pro-high"),
+            ModelMappingRule::new("gpt-4o*", "gemini-3-flash"),
+            ModelMappingRule::new("gpt-*-mini*", "gemini-2.5-flash"),
+            ModelMappingRule {
+                pattern: r"^claude-(.*)-2025\d+$".to_string(),
+                target: "claude-$1".to_string(),
+                regex: true,
+                ..Default::default()
+            },
+            ModelMappingRule { priority: 5, ..ModelMappingRule::new("gpt-4o-mini", "gemini-2.5-flash-lite") },
+            ModelMappingRule { regex: true, ..ModelMappingRule::new("(", "broken") },
+        ]);
+        let none = RequestFeatures::default();
+
+        // 同优先级按列表顺序，首条命中生效
+        assert_eq!(resolve_model_route("gpt-4o", &router, &none), "gemini-3-pro-high");
+        // 高优先级先匹配
+        let route = resolve_model_route_detailed("gpt-4o-mini", &router, &none);
+        assert_eq!(route.mapped_model, "gemini-2.5-flash-lite");
+        assert_eq!(route.rule.as_ref().map(|r| r.index), Some(4));
+        // 多个通配符
+        assert_eq!(resolve_model_route("gpt-3.5-mini-0125", &router, &none), "gemini-2.5-flash");
+        // 正则捕获组替换
+        let route = resolve_model_route_detailed("claude-sonnet-4-5-20250929", &router, &none);
+        assert_eq!(route.mapped_model, "claude-sonnet-4-5");
+        assert!(route.rule.unwrap().rule.regex);
+        // 未命中时使用系统默认映射
+        let route = resolve_model_route_detailed("claude-opus-4", &router, &none);
+        assert_eq!(route.mapped_model, "claude-opus-4-5-thinking");
+        assert!(route.rule.is_none());
+    }
+
+    #[test]
+    fn test_resolve_model_route_conditions() {
+        use crate::proxy::config::{ApiKeyScope, MappingConditions};
+
+        let router = ModelRouter::new(vec![
+            ModelMappingRule {
+                when: MappingConditions { has_images: Some(true), ..Default::default() },
+                ..ModelMappingRule::new("claude-*", "gemini-3-pro-high")
+            },
+            ModelMappingRule {
+                when: MappingConditions { min_tokens: Some(100_000), ..Default::default() },
+                ..ModelMappingRule::new("claude-*", "gemini-3-flash")
+            },
+            ModelMappingRule {
+                when: MappingConditions {
+                    api_keys: vec!["ci".to_string()],
+                    protocols: vec![ApiKeyScope::Openai],
+                    ..Default::default()
+                },
+                ..ModelMappingRule::new("*", "gemini-2.5-flash-lite")
+            },
+            ModelMappingRule::new("claude-*", "claude-sonnet-4-5"),
+        ]);
+
+        let plain = RequestFeatures::default();
+        assert_eq!(resolve_model_route("claude-opus-4", &router, &plain), "claude-sonnet-4-5");
+
+        let images = RequestFeatures { has_images: true, ..Default::default() };
+        assert_eq!(resolve_model_route("claude-opus-4", &router, &images), "gemini-3-pro-high");
+
+        let long = RequestFeatures { estimated_tokens: 150_000, ..Default::default() };
+        let route = resolve_model_route_detailed("claude-opus-4", &router, &long);
+        assert_eq!((route.mapped_model.as_str(), route.rule.map(|r| r.index)), ("gemini-3-flash", Some(1)));
+
+        // API Key 与协议条件需同时满足
+        let ci = RequestFeatures { api_key: Some("ci".to_string()), ..Default::default() };
+        assert!(resolve_model_route_detailed("gpt-4o", &router, &ci).rule.is_none());
+        let ci_openai = RequestFeatures { protocol: Some(ApiKeyScope::Openai), ..ci };
+        assert_eq!(resolve_model_route("gpt-4o", &router, &ci_openai), "gemini-2.5-flash-lite");
+    }
+
+    #[test]
+    fn test_fallback_chains() {
+        let mut config = ProxyConfig::default();
+        config.model_fallback.chains.insert(
+            "claude-opus-4-5-thinking".to_string(),
+            vec!["claude-sonnet-4-5-thinking".to_string(), "gemini-3-pro-high".to_string()],
+        );
+        config.model_fallback.response_header = true;
+        assert!(config.validate().is_ok());
+
+        let mut router = ModelRouter::from_config(&config);
+        assert_eq!(router.fallback_chain("claude-opus-4-5-thinking").len(), 2);
+        assert!(router.fallback_chain("gemini-3-flash").is_empty());
+        // 替换映射规则 (PUT /admin/model-mapping) 不影响降级链
+        router.set_rules(vec![ModelMappingRule::new("gpt-4*", "gemini-3-pro-high")]);
+        assert_eq!(router.fallback_chain("claude-opus-4-5-thinking")[1], "gemini-3-pro-high");
+        assert!(router.fallback_header());
+
+        config
+            .model_fallback
+            .chains
+            .insert("gemini-3-flash".to_string(), vec!["gemini-3-flash".to_string()]);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_quota_downgrade_steps() {
+        let mut config = ProxyConfig {
+            quota_downgrade: vec![
+                QuotaDowngradeRule {
+                    model: "gemini-3-pro-*".to_string(),
+                    target: "gemini-3-flash".to_string(),
+                    threshold_percentage: 20,
+                },
+                QuotaDowngradeRule {
+                    model: "gemini-3-flash".to_string(),
+                    target: "gemini-2.5-flash".to_string(),
+                    threshold_percentage: 10,
+                },
+            ],
+            ..ProxyConfig::default()
+        };
+        let router = ModelRouter::from_config(&config);
+        let pool = |flash: f64| {
+            move |model: &str| match model {
+                "gemini-3-pro-high" => Some(15.0),
+                "gemini-3-flash" => Some(flash),
+                _ => None,
+            }
+        };
+
+        let any = |_: &str| true;
+        let steps = router.quota_downgrade("gemini-3-pro-high", pool(50.0), any);
+        assert_eq!(steps.len(), 1);
+        assert_eq!((steps[0].to.as_str(), steps[0].pool_quota), ("gemini-3-flash", 15.0));
+        // 目标模型同样低于阈值时继续降级
+        let steps = router.quota_downgrade("gemini-3-pro-high", pool(5.0), any);
+        assert_eq!(steps.last().map(|s| s.to.as_str()), Some("gemini-2.5-flash"));
+        // 不允许访问的目标模型: 停在上一级
+        let steps = router.quota_downgrade("gemini-3-pro-high", pool(5.0), |m| m != "gemini-2.5-flash");
+        assert_eq!(steps.last().map(|s| s.to.as_str()), Some("gemini-3-flash"));
+        assert!(router.quota_downgrade("gemini-3-pro-high", pool(5.0), |m| m.starts_with("gemini-3-pro")).is_empty());
+        // 配额未知或高于阈值时不降级
+        assert!(router.quota_downgrade("gemini-3-flash", pool(10.0), any).is_empty());
+        assert!(router.quota_downgrade("claude-sonnet-4-5", pool(0.0), any).is_empty());
+
+        config.quota_downgrade[1].threshold_percentage = 0;
+        assert!(config.validate().is_err());
+    }
 }
@@ -0,0 +1,258 @@
+// 请求特征
+// 由各协议 handler 在模型路由前提取，供模型映射规则按图片/音频/工具/思考/输入长度/调用方/协议匹配
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::proxy::config::ApiKeyScope;
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent, SystemPrompt};
+use crate::proxy::mappers::openai::{OpenAIContent, OpenAIContentBlock, OpenAIRequest};
+
+/// 粗略估算: 每 4 字节 UTF-8 文本约 1 token (不含图片/音频等二进制数据)
+const BYTES_PER_TOKEN: usize = 4;
+/// 遍历 JSON 时不计入文本长度的字段 (base64 数据与结构性字段)
+const SKIPPED_KEYS: [&str; 6] = ["data", "role", "type", "mimeType", "mime_type", "media_type"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestFeatures {
+    pub has_images: bool,
+    pub has_audio: bool,
+    /// 请求声明了工具 (函数调用)
+    pub has_tools: bool,
+    /// 请求开启了思考
+    pub has_thinking: bool,
+    /// 估算的输入 token 数
+    pub estimated_tokens: u64,
+    pub message_count: usize,
+    /// 发起请求的 API Key 名称
+    pub api_key: Option<String>,
+    pub protocol: Option<ApiKeyScope>,
+}
+
+/// 累计文本长度并识别多模态内容
+#[derive(Default)]
+struct Scanner {
+    bytes: usize,
+    has_images: bool,
+    has_audio: bool,
+}
+
+impl Scanner {
+    fn text(&mut self, text: &str) {
+        self.bytes += text.len();
+    }
+
+    fn mime(&mut self, mime: &str) {
+        if mime.starts_with("image/") {
+            self.has_images = true;
+        } else if mime.starts_with("audio/") {
+            self.has_audio = true;
+        }
+    }
+
+    /// 遍历任意 JSON (工具参数、工具结果、Gemini contents)，跳过 base64 数据
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::String(s) if s.starts_with("data:") => {
+                self.mime(s.trim_start_matches("data:"));
+            }
+            Value::String(s) => self.text(s),
+            Value::Array(items) => items.iter().for_each(|v| self.value(v)),
+            Value::Object(map) => {
+                for key in ["mimeType", "mime_type", "media_type"] {
+                    if let Some(mime) = map.get(key).and_then(|v| v.as_str()) {
+                        self.mime(mime);
+                    }
+                }
+                if map.get("type").and_then(|v| v.as_str()) == Some("image") {
+                    self.has_images = true;
+                }
+                for (key, v) in map {
+                    if !SKIPPED_KEYS.contains(&key.as_str()) {
+                        self.value(v);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self, features: &mut RequestFeatures) {
+        features.has_images |= self.has_images;
+        features.has_audio |= self.has_audio;
+        features.estimated_tokens = self.bytes.div_ceil(BYTES_PER_TOKEN) as u64;
+    }
+}
+
+impl RequestFeatures {
+    pub fn from_claude(request: &ClaudeRequest) -> Self {
+        let mut scanner = Scanner::default();
+        match &request.system {
+            Some(SystemPrompt::String(s)) => scanner.text(s),
+            Some(SystemPrompt::Array(blocks)) => blocks.iter().for_each(|b| scanner.text(&b.text)),
+            None => {}
+        }
+        for message in &request.messages {
+            match &message.content {
+                MessageContent::String(s) => scanner.text(s),
+                MessageContent::Array(blocks) => {
+                    for block in blocks {
+                        match block {
+                            ContentBlock::Text { text } => scanner.text(text),
+                            ContentBlock::Thinking { thinking, .. } => scanner.text(thinking),
+                            ContentBlock::Image { source, .. } => scanner.mime(&source.media_type),
+                            ContentBlock::ToolUse { input, .. } | ContentBlock::ServerToolUse { input, .. } => {
+                                scanner.value(input)
+                            }
+                            ContentBlock::ToolResult { content, .. }
+                            | ContentBlock::WebSearchToolResult { content, .. } => scanner.value(content),
+                            ContentBlock::Document { .. } | ContentBlock::RedactedThinking { .. } => {}
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(tools) = &request.tools {
+            scanner.text(&serde_json::to_string(tools).unwrap_or_default());
+        }
+
+        let mut features = Self {
+            has_tools: request.tools.as_ref().is_some_and(|t| !t.is_empty()),
+            has_thinking: request.thinking.as_ref().is_some_and(|t| t.type_ == "enabled"),
+            message_count: request.messages.len(),
+            protocol: Some(ApiKeyScope::Claude),
+            ..Default::default()
+        };
+        scanner.finish(&mut features);
+        features
+    }
+
+    pub fn from_openai(request: &OpenAIRequest) -> Self {
+        let mut scanner = Scanner::default();
+        let mut has_reasoning = false;
+        for message in &request.messages {
+            match &message.content {
+                Some(OpenAIContent::String(s)) => scanner.text(s),
+                Some(OpenAIContent::Array(blocks)) => {
+                    for block in blocks {
+                        match block {
+                            OpenAIContentBlock::Text { text } => scanner.text(text),
+                            OpenAIContentBlock::ImageUrl { .. } => scanner.has_images = true,
+                            OpenAIContentBlock::AudioUrl { .. } => scanner.has_audio = true,
+                        }
+                    }
+                }
+                None => {}
+            }
+            if let Some(reasoning) = &message.reasoning_content {
+                has_reasoning = true;
+                scanner.text(reasoning);
+            }
+            if let Some(calls) = &message.tool_calls {
+                scanner.text(&serde_json::to_string(calls).unwrap_or_default());
+            }
+        }
+        // Legacy completions / Codex 字段
+        for text in [&request.prompt, &request.instructions].into_iter().flatten() {
+            scanner.text(text);
+        }
+        if let Some(input) = &request.input {
+            scanner.value(input);
+        }
+        if let Some(tools) = &request.tools {
+            scanner.value(&Value::Array(tools.clone()));
+        }
+
+        let mut features = Self {
+            has_tools: request.tools.as_ref().is_some_and(|t| !t.is_empty()),
+            // OpenAI 协议没有思考开关，以 -thinking 模型名或历史中的 reasoning_content 为准
+            has_thinking: has_reasoning || request.model.contains("thinking"),
+            message_count: request.messages.len(),
+            protocol: Some(ApiKeyScope::Openai),
+            ..Default::default()
+        };
+        scanner.finish(&mut features);
+        features
+    }
+
+    pub fn from_gemini(body: &Value) -> Self {
+        let mut scanner = Scanner::default();
+        if let Some(system) = body.get("systemInstruction") {
+            scanner.value(system);
+        }
+        let contents = body.get("contents").and_then(|c| c.as_array());
+        if let Some(contents) = contents {
+            contents.iter().for_each(|c| scanner.value(c));
+        }
+        let tools = body.get("tools").and_then(|t| t.as_array());
+        if let Some(tools) = tools {
+            tools.iter().for_each(|t| scanner.value(t));
+        }
+        let thinking_budget = body
+            .get("generationConfig")
+            .and_then(|g| g.get("thinkingConfig"))
+            .map(|t| t.get("thinkingBudget").and_then(|b| b.as_i64()).unwrap_or(-1));
+
+        let mut features = Self {
+            has_tools: tools.is_some_and(|t| !t.is_empty()),
+            has_thinking: thinking_budget.is_some_and(|b| b != 0),
+            message_count: contents.map_or(0, |c| c.len()),
+            protocol: Some(ApiKeyScope::Gemini),
+            ..Default::default()
+        };
+        scanner.finish(&mut features);
+        features
+    }
+
+    pub fn with_api_key(mut self, api_key: Option<&str>) -> Self {
+        self.api_key = api_key.map(|k| k.to_string());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_features_from_each_protocol() {
+        let claude: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": [
+                { "type": "text", "text": "a".repeat(400) },
+                { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "x".repeat(10_000) } }
+            ]}],
+            "thinking": { "type": "enabled", "budget_tokens": 1024 }
+        }))
+        .unwrap();
+        let f = RequestFeatures::from_claude(&claude).with_api_key(Some("ci"));
+        assert!(f.has_images && f.has_thinking && !f.has_tools && !f.has_audio);
+        // base64 数据不计入估算
+        assert_eq!(f.estimated_tokens, 100);
+        assert_eq!((f.message_count, f.api_key.as_deref(), f.protocol), (1, Some("ci"), Some(ApiKeyScope::Claude)));
+
+        let openai: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": [
+                { "type": "text", "text": "hi" },
+                { "type": "audio_url", "audio_url": { "url": "data:audio/wav;base64,AAAA" } }
+            ]}],
+            "tools": [{ "type": "function", "function": { "name": "f" } }]
+        }))
+        .unwrap();
+        let f = RequestFeatures::from_openai(&openai);
+        assert!(f.has_audio && f.has_tools && !f.has_images && !f.has_thinking);
+
+        let gemini = json!({
+            "contents": [{ "role": "user", "parts": [
+                { "text": "describe" },
+                { "inlineData": { "mimeType": "image/jpeg", "data": "x".repeat(1000) } }
+            ]}],
+            "generationConfig": { "thinkingConfig": { "thinkingBudget": 0 } }
+        });
+        let f = RequestFeatures::from_gemini(&gemini);
+        assert!(f.has_images && !f.has_thinking && !f.has_tools);
+        assert_eq!(f.estimated_tokens, 2);
+    }
+}
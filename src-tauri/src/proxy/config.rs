@@ -2,6 +2,9 @@ use serde::{Deserialize, Serialize};
 // use std::path::PathBuf;
 use std::collections::HashMap;
 
+use crate::proxy::common::request_features::RequestFeatures;
+use crate::proxy::model_registry::{validate_model_descriptors, ModelDescriptor};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ProxyAuthMode {
@@ -150,6 +153,340 @@ impl Default for ExperimentalConfig {
 
 fn default_true() -> bool { true }
 
+/// 后台主动刷新 access token
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenRefreshConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 距过期还剩多少秒时刷新
+    #[serde(default = "default_token_refresh_margin_seconds")]
+    pub margin_seconds: u64,
+    /// 同时进行的刷新请求数
+    #[serde(default = "default_token_refresh_concurrency")]
+    pub max_concurrency: usize,
+}
+
+impl Default for TokenRefreshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            margin_seconds: default_token_refresh_margin_seconds(),
+            max_concurrency: default_token_refresh_concurrency(),
+        }
+    }
+}
+
+fn default_token_refresh_margin_seconds() -> u64 {
+    900
+}
+
+fn default_token_refresh_concurrency() -> usize {
+    4
+}
+
+/// API Key 可访问的协议范围
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyScope {
+    /// /v1/chat/completions, /v1/completions, /v1/responses, /v1/models
+    Openai,
+    /// /v1/messages, /v1/models/claude
+    Claude,
+    /// /v1beta/*
+    Gemini,
+    /// /mcp/*
+    Mcp,
+    /// /v1/images/*
+    Images,
+    /// /v1/audio/*
+    Audio,
+}
+
+/// 命名 API Key (多客户端共用一个反代时区分调用方)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    /// 名称 (写入请求日志，用于追溯调用方)
+    pub name: String,
+    /// 密钥
+    pub key: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 过期时间 (Unix 秒)，None 表示永不过期
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// 允许访问的协议，为空表示不限制
+    #[serde(default)]
+    pub scopes: Vec<ApiKeyScope>,
+    /// 允许使用的模型 (glob，匹配模型映射之后的名称)，为空表示不限制
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// 用量限制
+    #[serde(default)]
+    pub limits: ApiKeyLimits,
+}
+
+/// API Key 用量限制 (None 表示不限制)
+/// token 预算按 input + output 计算，日/月以 UTC 自然日/自然月为周期
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ApiKeyLimits {
+    /// 每分钟请求数 (滑动窗口)
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    /// 同时进行中的请求数
+    #[serde(default)]
+    pub max_concurrency: Option<u32>,
+    /// 每日 token 预算
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+    /// 每月 token 预算
+    #[serde(default)]
+    pub monthly_tokens: Option<u64>,
+}
+
+impl ApiKeyLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.requests_per_minute.is_none()
+            && self.max_concurrency.is_none()
+            && !self.has_token_budget()
+    }
+
+    pub fn has_token_budget(&self) -> bool {
+        self.daily_tokens.is_some() || self.monthly_tokens.is_some()
+    }
+}
+
+/// 模型映射规则
+/// 按 `priority` 从高到低匹配，优先级相同时按列表顺序，首条命中生效
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelMappingRule {
+    /// 原始模型名: 精确名称、glob (`*` 可出现多次) 或正则 (`regex` 为 true)
+    pub pattern: String,
+    /// 目标模型，正则规则可引用捕获组 (`$1`、`${name}`)
+    pub target: String,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub priority: i32,
+    /// 按请求特征附加的匹配条件，为空表示只按模型名匹配
+    #[serde(default, skip_serializing_if = "MappingConditions::is_empty")]
+    pub when: MappingConditions,
+}
+
+/// 模型映射规则的请求特征条件，所有非空条件同时满足时规则才生效
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MappingConditions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_images: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_audio: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_tools: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_thinking: Option<bool>,
+    /// 估算输入 token 数下限 (含)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_tokens: Option<u64>,
+    /// 估算输入 token 数上限 (含)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_messages: Option<usize>,
+    /// API Key 名称，为空表示任意调用方
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<String>,
+    /// 协议，为空表示任意协议
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protocols: Vec<ApiKeyScope>,
+}
+
+impl MappingConditions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn matches(&self, features: &RequestFeatures) -> bool {
+        let flag_ok = |cond: Option<bool>, actual: bool| cond.is_none_or(|c| c == actual);
+        flag_ok(self.has_images, features.has_images)
+            && flag_ok(self.has_audio, features.has_audio)
+            && flag_ok(self.has_tools, features.has_tools)
+            && flag_ok(self.has_thinking, features.has_thinking)
+            && self.min_tokens.is_none_or(|min| features.estimated_tokens >= min)
+            && self.max_tokens.is_none_or(|max| features.estimated_tokens <= max)
+            && self.max_messages.is_none_or(|max| features.message_count <= max)
+            && (self.api_keys.is_empty()
+                || features.api_key.as_ref().is_some_and(|name| self.api_keys.contains(name)))
+            && (self.protocols.is_empty()
+                || features.protocol.is_some_and(|p| self.protocols.contains(&p)))
+    }
+}
+
+impl ModelMappingRule {
+    pub fn new(pattern: &str, target: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            target: target.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 旧版 `{ "原始模型": "目标模型" }` 映射表转换为规则列表
+/// 保持旧版语义: 精确映射优先于通配符；通配符按模式长度从长到短排列，结果不再依赖 HashMap 遍历顺序
+pub fn mapping_rules_from_legacy<'a>(
+    entries: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> Vec<ModelMappingRule> {
+    let mut rules: Vec<ModelMappingRule> = entries
+        .into_iter()
+        .map(|(pattern, target)| ModelMappingRule::new(pattern, target))
+        .collect();
+    rules.sort_by(|a, b| {
+        a.pattern
+            .contains('*')
+            .cmp(&b.pattern.contains('*'))
+            .then_with(|| b.pattern.len().cmp(&a.pattern.len()))
+            .then_with(|| a.pattern.cmp(&b.pattern))
+    });
+    rules
+}
+
+/// `custom_mapping` 同时接受规则列表与旧版对象格式
+pub fn deserialize_mapping_rules<'de, D>(deserializer: D) -> Result<Vec<ModelMappingRule>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum MappingFormat {
+        Rules(Vec<ModelMappingRule>),
+        Legacy(HashMap<String, String>),
+    }
+
+    Ok(match MappingFormat::deserialize(deserializer)? {
+        MappingFormat::Rules(rules) => rules,
+        MappingFormat::Legacy(map) => mapping_rules_from_legacy(&map),
+    })
+}
+
+/// 校验模型映射规则 (pattern/target 非空，正则可编译)
+pub fn validate_mapping_rules(rules: &[ModelMappingRule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.pattern.trim().is_empty() || rule.target.trim().is_empty() {
+            return Err(format!("custom_mapping[{}] 的 pattern 或 target 为空", i));
+        }
+        if rule.regex {
+            regex::Regex::new(&rule.pattern)
+                .map_err(|e| format!("custom_mapping[{}] 正则无效 ({}): {}", i, rule.pattern, e))?;
+        }
+        if let (Some(min), Some(max)) = (rule.when.min_tokens, rule.when.max_tokens) {
+            if min > max {
+                return Err(format!("custom_mapping[{}] 的 min_tokens 大于 max_tokens", i));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 模型降级链
+/// 主模型 (映射后的名称) 在账号池中找不到可用账号时，按顺序尝试链中的模型
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelFallbackConfig {
+    /// 映射后的模型名 -> 依次尝试的替代模型
+    #[serde(default)]
+    pub chains: HashMap<String, Vec<String>>,
+    /// 发生降级时在响应中附加 `X-Model-Fallback-From` 头 (值为原模型)
+    #[serde(default)]
+    pub response_header: bool,
+}
+
+impl ModelFallbackConfig {
+    pub fn chain(&self, model: &str) -> &[String] {
+        self.chains.get(model).map_or(&[], |c| c.as_slice())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (model, chain) in &self.chains {
+            if chain.iter().any(|m| m.trim().is_empty()) {
+                return Err(format!("model_fallback.chains[{}] 中存在空模型名", model));
+            }
+            if chain.iter().any(|m| m == model) {
+                return Err(format!("model_fallback.chains[{}] 不能包含模型自身", model));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 配额驱动的模型降级规则
+/// 账号池对 `model` 的平均剩余配额低于阈值时改用 `target`，账号本身继续参与轮换
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuotaDowngradeRule {
+    /// 映射后的模型名 (glob)
+    pub model: String,
+    pub target: String,
+    /// 账号池平均剩余配额百分比 (1-100)，低于该值时降级
+    pub threshold_percentage: u32,
+}
+
+/// 校验配额降级规则
+pub fn validate_quota_downgrade_rules(rules: &[QuotaDowngradeRule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.model.trim().is_empty() || rule.target.trim().is_empty() {
+            return Err(format!("quota_downgrade[{}] 的 model 或 target 为空", i));
+        }
+        if rule.model == rule.target {
+            return Err(format!("quota_downgrade[{}] 的 target 不能与 model 相同", i));
+        }
+        if !(1..=100).contains(&rule.threshold_percentage) {
+            return Err(format!("quota_downgrade[{}] 的 threshold_percentage 需在 1-100 之间", i));
+        }
+    }
+    Ok(())
+}
+
+/// 账号分组路由规则
+/// 请求同时满足所有非空条件时只从 `group` 标签的账号中选择；按顺序匹配，首条命中生效
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountRoutingRule {
+    /// 模型 (glob，匹配模型映射之后的名称)，为空表示任意模型
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// API Key 名称，为空表示任意调用方
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// 协议路由，为空表示任意路由
+    #[serde(default)]
+    pub routes: Vec<ApiKeyScope>,
+    /// 账号标签
+    pub group: String,
+}
+
+impl AccountRoutingRule {
+    pub fn matches(&self, mapped_model: &str, api_key: Option<&str>, route: Option<ApiKeyScope>) -> bool {
+        let model_ok = self.models.is_empty()
+            || self
+                .models
+                .iter()
+                .any(|p| crate::proxy::common::model_mapping::glob_match(p, mapped_model));
+        let key_ok = self.api_keys.is_empty()
+            || api_key.is_some_and(|name| self.api_keys.iter().any(|k| k == name));
+        let route_ok = self.routes.is_empty() || route.is_some_and(|r| self.routes.contains(&r));
+        model_ok && key_ok && route_ok
+    }
+}
+
+/// 按顺序查找首条命中的规则，返回请求应使用的账号分组
+pub fn resolve_account_group<'a>(
+    rules: &'a [AccountRoutingRule],
+    mapped_model: &str,
+    api_key: Option<&str>,
+    route: Option<ApiKeyScope>,
+) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(mapped_model, api_key, route))
+        .map(|rule| rule.group.as_str())
+}
+
 /// 反代服务配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
@@ -175,14 +512,34 @@ pub struct ProxyConfig {
     
     /// API 密钥
     pub api_key: String,
-    
+
+    /// 管理 API (`/admin/*`) 密钥，与 api_key 相互独立
+    /// 为空时管理 API 关闭
+    #[serde(default)]
+    pub admin_key: String,
+
+    /// 命名 API Key 列表 (与 api_key 并存，api_key 视为不受限的 "default" key)
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
 
     /// 是否自动启动
     pub auto_start: bool,
 
-    /// 自定义精确模型映射表 (key: 原始模型名, value: 目标模型名)
+    /// 自定义模型映射规则 (有序，兼容旧版对象格式)
+    #[serde(default, deserialize_with = "deserialize_mapping_rules")]
+    pub custom_mapping: Vec<ModelMappingRule>,
+
+    /// 模型降级链 (主模型账号池耗尽时改用替代模型)
+    #[serde(default)]
+    pub model_fallback: ModelFallbackConfig,
+
+    /// 配额驱动的模型降级规则 (按顺序匹配，首条命中生效)
+    #[serde(default)]
+    pub quota_downgrade: Vec<QuotaDowngradeRule>,
+
+    /// 模型能力描述 (优先于内置条目，用于补充新模型或修正上下文窗口等)
     #[serde(default)]
-    pub custom_mapping: std::collections::HashMap<String, String>,
+    pub model_registry: Vec<ModelDescriptor>,
 
     /// API 请求超时时间(秒)
     #[serde(default = "default_request_timeout")]
@@ -192,6 +549,22 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub enable_logging: bool,
 
+    /// 是否开放 Prometheus 指标端点 (`GET /metrics`)
+    #[serde(default)]
+    pub enable_metrics: bool,
+
+    /// 停止服务时等待进行中连接 (如长时间思考的 SSE 流) 结束的最长时间(秒)
+    #[serde(default = "default_shutdown_grace_seconds")]
+    pub shutdown_grace_seconds: u64,
+
+    /// TLS 配置 (局域网访问时建议开启)
+    #[serde(default)]
+    pub tls: ProxyTlsConfig,
+
+    /// Unix domain socket 监听配置
+    #[serde(default)]
+    pub unix_socket: ProxyUnixSocketConfig,
+
     /// 上游代理配置
     #[serde(default)]
     pub upstream_proxy: UpstreamProxyConfig,
@@ -204,11 +577,94 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub scheduling: crate::proxy::sticky_config::StickySessionConfig,
 
+    /// 账号分组路由规则 (按模型/API Key/路由限定可用账号)
+    #[serde(default)]
+    pub routing_rules: Vec<AccountRoutingRule>,
+
+    /// 后台主动刷新 access token
+    #[serde(default)]
+    pub token_refresh: TokenRefreshConfig,
+
     /// 实验性功能配置
     #[serde(default)]
     pub experimental: ExperimentalConfig,
 }
 
+/// Unix domain socket 监听配置
+/// 本机工具 (编辑器插件、挂载 socket 的容器) 无需占用 TCP 端口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyUnixSocketConfig {
+    /// 是否监听 socket
+    #[serde(default)]
+    pub enabled: bool,
+    /// socket 文件路径 (为空时使用数据目录下的 proxy.sock)
+    #[serde(default)]
+    pub path: String,
+    /// socket 文件权限 (八进制字符串，如 "660")
+    #[serde(default = "default_unix_socket_mode")]
+    pub mode: String,
+    /// 只监听 socket，不再监听 TCP 端口
+    #[serde(default)]
+    pub disable_tcp: bool,
+    /// socket 连接的鉴权模式 (文件权限已限制访问，默认不鉴权；auto 视为 off)
+    #[serde(default)]
+    pub auth_mode: ProxyAuthMode,
+}
+
+impl Default for ProxyUnixSocketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: String::new(),
+            mode: default_unix_socket_mode(),
+            disable_tcp: false,
+            auth_mode: ProxyAuthMode::Off,
+        }
+    }
+}
+
+impl ProxyUnixSocketConfig {
+    /// 实际使用的 socket 路径
+    pub fn socket_path(&self) -> Result<std::path::PathBuf, String> {
+        if self.path.trim().is_empty() {
+            Ok(crate::modules::account::get_data_dir()?.join("proxy.sock"))
+        } else {
+            Ok(std::path::PathBuf::from(self.path.trim()))
+        }
+    }
+
+    /// 解析八进制权限
+    pub fn parsed_mode(&self) -> Result<u32, String> {
+        let mode = self.mode.trim().trim_start_matches("0o");
+        u32::from_str_radix(mode, 8)
+            .ok()
+            .filter(|m| *m <= 0o777)
+            .ok_or_else(|| format!("unix_socket.mode 不是有效的八进制权限: {}", self.mode))
+    }
+}
+
+fn default_unix_socket_mode() -> String {
+    "600".to_string()
+}
+
+/// 反代监听端 TLS 配置
+/// 同时设置 cert_path 与 key_path 时使用指定证书，否则在数据目录生成并复用自签名证书
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProxyTlsConfig {
+    /// 是否启用 TLS
+    #[serde(default)]
+    pub enabled: bool,
+    /// PEM 证书 (链) 路径
+    #[serde(default)]
+    pub cert_path: Option<String>,
+    /// PEM 私钥路径
+    #[serde(default)]
+    pub key_path: Option<String>,
+    /// 自签名证书额外的 SAN (局域网 IP 或主机名)，localhost/127.0.0.1 始终包含
+    #[serde(default)]
+    pub subject_alt_names: Vec<String>,
+}
+
 /// 上游代理配置
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct UpstreamProxyConfig {
@@ -226,13 +682,24 @@ impl Default for ProxyConfig {
             auth_mode: ProxyAuthMode::default(),
             port: 8045,
             api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
+            admin_key: String::new(),
+            api_keys: Vec::new(),
             auto_start: false,
-            custom_mapping: std::collections::HashMap::new(),
+            custom_mapping: Vec::new(),
+            model_fallback: ModelFallbackConfig::default(),
+            quota_downgrade: Vec::new(),
+            model_registry: Vec::new(),
             request_timeout: default_request_timeout(),
             enable_logging: false, // 默认关闭，节省性能
+            enable_metrics: false,
+            shutdown_grace_seconds: default_shutdown_grace_seconds(),
+            tls: ProxyTlsConfig::default(),
+            unix_socket: ProxyUnixSocketConfig::default(),
             upstream_proxy: UpstreamProxyConfig::default(),
             zai: ZaiConfig::default(),
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
+            routing_rules: Vec::new(),
+            token_refresh: TokenRefreshConfig::default(),
             experimental: ExperimentalConfig::default(),
         }
     }
@@ -242,6 +709,10 @@ fn default_request_timeout() -> u64 {
     120  // 默认 120 秒,原来 60 秒太短
 }
 
+fn default_shutdown_grace_seconds() -> u64 {
+    30
+}
+
 fn default_zai_base_url() -> String {
     "https://api.z.ai/api/anthropic".to_string()
 }
@@ -269,4 +740,185 @@ impl ProxyConfig {
             "127.0.0.1"
         }
     }
+
+    /// 校验配置 (手动编辑配置文件后热加载前调用)
+    pub fn validate(&self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("port 不能为 0".to_string());
+        }
+
+        let mut names = std::collections::HashSet::new();
+        for key in &self.api_keys {
+            if key.name.trim().is_empty() {
+                return Err("api_keys 中存在未命名的 key".to_string());
+            }
+            if key.key.trim().is_empty() {
+                return Err(format!("API key '{}' 的 key 为空", key.name));
+            }
+            if !names.insert(key.name.as_str()) {
+                return Err(format!("API key 名称重复: {}", key.name));
+            }
+        }
+
+        validate_mapping_rules(&self.custom_mapping)?;
+        self.model_fallback.validate()?;
+        validate_quota_downgrade_rules(&self.quota_downgrade)?;
+        validate_model_descriptors(&self.model_registry)?;
+
+        if let Some(i) = self.routing_rules.iter().position(|r| r.group.trim().is_empty()) {
+            return Err(format!("routing_rules[{}] 未指定 group", i));
+        }
+
+        let has_cert = self.tls.cert_path.as_deref().is_some_and(|p| !p.trim().is_empty());
+        let has_key = self.tls.key_path.as_deref().is_some_and(|p| !p.trim().is_empty());
+        if has_cert != has_key {
+            return Err("tls.cert_path 与 tls.key_path 需同时设置".to_string());
+        }
+
+        if self.unix_socket.enabled {
+            self.unix_socket.parsed_mode()?;
+        }
+        if self.unix_socket.disable_tcp && !self.unix_socket.enabled {
+            return Err("unix_socket.disable_tcp 需要同时启用 unix_socket".to_string());
+        }
+
+        if self.upstream_proxy.enabled && self.upstream_proxy.url.trim().is_empty() {
+            return Err("已启用上游代理但 url 为空".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// 与运行中配置相比，需要重启服务才能生效的配置项
+    pub fn restart_required_changes(&self, running: &ProxyConfig) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.port != running.port {
+            changes.push("port");
+        }
+        if self.allow_lan_access != running.allow_lan_access {
+            changes.push("allow_lan_access");
+        }
+        if self.tls.enabled != running.tls.enabled
+            || self.tls.cert_path != running.tls.cert_path
+            || self.tls.key_path != running.tls.key_path
+            || self.tls.subject_alt_names != running.tls.subject_alt_names
+        {
+            changes.push("tls");
+        }
+        // socket 的鉴权模式随安全配置热更新，其余项需要重新绑定
+        if self.unix_socket.enabled != running.unix_socket.enabled
+            || self.unix_socket.path != running.unix_socket.path
+            || self.unix_socket.mode != running.unix_socket.mode
+            || self.unix_socket.disable_tcp != running.unix_socket.disable_tcp
+        {
+            changes.push("unix_socket");
+        }
+        changes
+    }
+
+    /// 本机访问地址 (启用 TLS 时为 https，仅监听 socket 时为 unix:<path>)
+    pub fn get_base_url(&self) -> String {
+        if self.unix_socket.enabled && self.unix_socket.disable_tcp {
+            if let Ok(path) = self.unix_socket.socket_path() {
+                return format!("unix:{}", path.display());
+            }
+        }
+        let scheme = if self.tls.enabled { "https" } else { "http" };
+        format!("{}://127.0.0.1:{}", scheme, self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_duplicate_key_names() {
+        let mut config = ProxyConfig::default();
+        assert!(config.validate().is_ok());
+
+        let key = ApiKeyConfig {
+            name: "ci".to_string(),
+            key: "sk-ci".to_string(),
+            enabled: true,
+            expires_at: None,
+            scopes: Vec::new(),
+            allowed_models: Vec::new(),
+            limits: ApiKeyLimits::default(),
+        };
+        config.api_keys = vec![key.clone(), key];
+        assert!(config.validate().unwrap_err().contains("ci"));
+    }
+
+    #[test]
+    fn test_restart_required_changes() {
+        let running = ProxyConfig::default();
+        let mut next = running.clone();
+        next.custom_mapping.push(ModelMappingRule::new("a", "b"));
+        assert!(next.restart_required_changes(&running).is_empty());
+
+        let mut socket_auth = running.clone();
+        socket_auth.unix_socket.auth_mode = ProxyAuthMode::Strict;
+        assert!(socket_auth.restart_required_changes(&running).is_empty());
+
+        next.port = running.port + 1;
+        next.allow_lan_access = !running.allow_lan_access;
+        assert_eq!(next.restart_required_changes(&running), vec!["port", "allow_lan_access"]);
+    }
+
+    #[test]
+    fn test_custom_mapping_accepts_legacy_object() {
+        let with_mapping = |mapping: serde_json::Value| {
+            let mut value = serde_json::to_value(ProxyConfig::default()).unwrap();
+            value["custom_mapping"] = mapping;
+            serde_json::from_value::<ProxyConfig>(value).unwrap()
+        };
+
+        let legacy = with_mapping(serde_json::json!({
+            "gpt-4*": "gemini-3-pro-high",
+            "gpt-4o*": "gemini-3-flash",
+            "gpt-4": "gemini-2.5-pro"
+        }));
+        let patterns: Vec<&str> = legacy.custom_mapping.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, vec!["gpt-4", "gpt-4o*", "gpt-4*"]);
+
+        let config = with_mapping(serde_json::json!([
+            { "pattern": "^claude-(.*$", "target": "claude-$1", "regex": true, "priority": 10 }
+        ]));
+        assert_eq!(config.custom_mapping[0].priority, 10);
+        assert!(config.validate().unwrap_err().contains("custom_mapping[0]"));
+    }
+
+    #[test]
+    fn test_resolve_account_group() {
+        let rules = vec![
+            AccountRoutingRule {
+                models: vec!["claude-opus-*".to_string()],
+                group: "ultra-only".to_string(),
+                ..Default::default()
+            },
+            AccountRoutingRule {
+                api_keys: vec!["ci-bot".to_string()],
+                routes: vec![ApiKeyScope::Openai],
+                group: "batch".to_string(),
+                ..Default::default()
+            },
+        ];
+        let resolve = |model, key, route| resolve_account_group(&rules, model, key, route);
+
+        assert_eq!(resolve("claude-opus-4-5-thinking", Some("ci-bot"), None), Some("ultra-only"));
+        assert_eq!(resolve("gemini-3-flash", Some("ci-bot"), Some(ApiKeyScope::Openai)), Some("batch"));
+        assert_eq!(resolve("gemini-3-flash", Some("ci-bot"), Some(ApiKeyScope::Claude)), None);
+        assert_eq!(resolve("gemini-3-flash", None, Some(ApiKeyScope::Openai)), None);
+    }
+
+    #[test]
+    fn test_unix_socket_mode() {
+        let mut socket = ProxyUnixSocketConfig::default();
+        assert_eq!(socket.parsed_mode().unwrap(), 0o600);
+        socket.mode = "0o660".to_string();
+        assert_eq!(socket.parsed_mode().unwrap(), 0o660);
+        socket.mode = "rw".to_string();
+        assert!(socket.parsed_mode().is_err());
+    }
 }
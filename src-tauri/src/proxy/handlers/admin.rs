@@ -0,0 +1,296 @@
+// 管理 API 处理器 (`/admin/*`)
+// 与 Tauri 命令 (commands::mod / commands::proxy) 一一对应，供 Headless 实例脚本化管理
+// 鉴权由 auth_middleware 使用独立的 admin_key 完成
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::modules;
+use crate::proxy::server::AppState;
+use crate::proxy::sticky_config::StickySessionConfig;
+
+fn admin_error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+fn bad_request(message: String) -> Response {
+    admin_error(StatusCode::BAD_REQUEST, message)
+}
+
+fn internal_error(message: String) -> Response {
+    admin_error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+/// 账号变更后同步到 Token 池
+async fn reload_pool(state: &AppState) {
+    if let Err(e) = state.token_manager.reload_all_accounts().await {
+        tracing::warn!("[Admin] 重新加载账号池失败: {}", e);
+    }
+}
+
+// ===== 服务状态 =====
+
+/// GET /admin/status
+pub async fn handle_status(State(state): State<AppState>) -> Response {
+    Json(json!({
+        "running": true,
+        "active_accounts": state.token_manager.len(),
+        "monitor_enabled": state.monitor.is_enabled(),
+    }))
+    .into_response()
+}
+
+/// GET /admin/stats
+pub async fn handle_get_stats(State(state): State<AppState>) -> Response {
+    Json(state.monitor.get_stats().await).into_response()
+}
+
+// ===== 账号管理 =====
+
+/// GET /admin/accounts
+pub async fn handle_list_accounts() -> Response {
+    match modules::list_accounts() {
+        Ok(accounts) => Json(accounts).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AddAccountRequest {
+    pub refresh_token: String,
+}
+
+/// POST /admin/accounts
+pub async fn handle_add_account(
+    State(state): State<AppState>,
+    Json(req): Json<AddAccountRequest>,
+) -> Response {
+    if req.refresh_token.trim().is_empty() {
+        return bad_request("refresh_token is required".to_string());
+    }
+    match crate::commands::internal_add_account(req.refresh_token.trim()).await {
+        Ok(account) => {
+            reload_pool(&state).await;
+            Json(account).into_response()
+        }
+        Err(e) => bad_request(e),
+    }
+}
+
+/// DELETE /admin/accounts/:account_id
+pub async fn handle_delete_account(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+) -> Response {
+    modules::logger::log_info(&format!("[Admin] 收到删除账号请求: {}", account_id));
+    match modules::delete_account(&account_id) {
+        Ok(()) => {
+            reload_pool(&state).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => bad_request(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AccountIdsRequest {
+    pub account_ids: Vec<String>,
+}
+
+/// POST /admin/accounts/batch-delete
+pub async fn handle_delete_accounts(
+    State(state): State<AppState>,
+    Json(req): Json<AccountIdsRequest>,
+) -> Response {
+    modules::logger::log_info(&format!(
+        "[Admin] 收到批量删除请求，共 {} 个账号",
+        req.account_ids.len()
+    ));
+    match modules::account::delete_accounts(&req.account_ids) {
+        Ok(()) => {
+            reload_pool(&state).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => bad_request(e),
+    }
+}
+
+/// POST /admin/accounts/reorder
+pub async fn handle_reorder_accounts(Json(req): Json<AccountIdsRequest>) -> Response {
+    match modules::account::reorder_accounts(&req.account_ids) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => bad_request(e),
+    }
+}
+
+/// POST /admin/accounts/:account_id/quota
+pub async fn handle_refresh_account_quota(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+) -> Response {
+    let mut account = match modules::load_account(&account_id) {
+        Ok(account) => account,
+        Err(e) => return admin_error(StatusCode::NOT_FOUND, e),
+    };
+    let quota = match modules::account::fetch_quota_with_retry(&mut account).await {
+        Ok(quota) => quota,
+        Err(e) => return admin_error(StatusCode::BAD_GATEWAY, e.to_string()),
+    };
+    if let Err(e) = modules::update_account_quota(&account_id, quota.clone()) {
+        return internal_error(e);
+    }
+    let _ = state.token_manager.reload_account(&account_id).await;
+    Json(quota).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct AccountTagsRequest {
+    pub tags: Vec<String>,
+}
+
+/// PUT /admin/accounts/:account_id/tags
+pub async fn handle_update_account_tags(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+    Json(req): Json<AccountTagsRequest>,
+) -> Response {
+    match modules::update_account_tags(&account_id, req.tags) {
+        Ok(account) => {
+            let _ = state.token_manager.reload_account(&account_id).await;
+            Json(account).into_response()
+        }
+        Err(e) => admin_error(StatusCode::NOT_FOUND, e),
+    }
+}
+
+/// POST /admin/accounts/refresh-quotas
+pub async fn handle_refresh_all_quotas(State(state): State<AppState>) -> Response {
+    match modules::account::refresh_all_quotas_logic().await {
+        Ok(stats) => {
+            reload_pool(&state).await;
+            Json(stats).into_response()
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+/// POST /admin/accounts/reload
+pub async fn handle_reload_accounts(State(state): State<AppState>) -> Response {
+    match state.token_manager.load_accounts().await {
+        Ok(count) => Json(json!({ "active_accounts": count })).into_response(),
+        Err(e) => internal_error(format!("重新加载账号失败: {}", e)),
+    }
+}
+
+// ===== 模型映射与调度 =====
+
+/// GET /admin/model-mapping
+pub async fn handle_get_model_mapping(State(state): State<AppState>) -> Response {
+    Json(state.custom_mapping.read().await.rules()).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct ModelMappingRequest {
+    /// 有序规则列表，兼容旧版 `{ "原始模型": "目标模型" }` 对象
+    #[serde(deserialize_with = "crate::proxy::config::deserialize_mapping_rules")]
+    pub custom_mapping: Vec<crate::proxy::config::ModelMappingRule>,
+}
+
+/// PUT /admin/model-mapping
+/// 全量替换映射规则，立即生效并持久化到 gui_config.json
+pub async fn handle_update_model_mapping(
+    State(state): State<AppState>,
+    Json(req): Json<ModelMappingRequest>,
+) -> Response {
+    if let Err(e) = crate::proxy::config::validate_mapping_rules(&req.custom_mapping) {
+        return admin_error(StatusCode::BAD_REQUEST, e);
+    }
+    state.custom_mapping.write().await.set_rules(req.custom_mapping.clone());
+
+    let result = modules::config::load_app_config().and_then(|mut app_config| {
+        app_config.proxy.custom_mapping = req.custom_mapping;
+        modules::config::save_app_config(&app_config)
+    });
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// GET /admin/scheduling
+pub async fn handle_get_scheduling(State(state): State<AppState>) -> Response {
+    Json(state.token_manager.get_sticky_config().await).into_response()
+}
+
+/// PUT /admin/scheduling
+pub async fn handle_update_scheduling(
+    State(state): State<AppState>,
+    Json(config): Json<StickySessionConfig>,
+) -> Response {
+    state.token_manager.update_sticky_config(config).await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// DELETE /admin/sessions
+pub async fn handle_clear_session_bindings(State(state): State<AppState>) -> Response {
+    state.token_manager.clear_all_sessions();
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// ===== 请求日志 =====
+
+#[derive(Deserialize)]
+pub struct LogsQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// GET /admin/logs?limit=&offset=
+pub async fn handle_get_logs(Query(query): Query<LogsQuery>) -> Response {
+    match modules::proxy_db::get_logs_summary(query.limit.unwrap_or(20), query.offset.unwrap_or(0))
+    {
+        Ok(logs) => Json(logs).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// GET /admin/logs/:log_id
+pub async fn handle_get_log_detail(Path(log_id): Path<String>) -> Response {
+    match modules::proxy_db::get_log_detail(&log_id) {
+        Ok(log) => Json(log).into_response(),
+        Err(e) => admin_error(StatusCode::NOT_FOUND, e),
+    }
+}
+
+/// GET /admin/logs/:log_id/scheduling
+/// 返回该请求的选号决策记录 (重试换号时每次 get_token 一条)
+pub async fn handle_get_log_scheduling(Path(log_id): Path<String>) -> Response {
+    match modules::proxy_db::get_log_detail(&log_id) {
+        Ok(log) => Json(log.scheduling_trace.unwrap_or_default()).into_response(),
+        Err(e) => admin_error(StatusCode::NOT_FOUND, e),
+    }
+}
+
+/// DELETE /admin/logs
+pub async fn handle_clear_logs(State(state): State<AppState>) -> Response {
+    state.monitor.clear().await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[derive(Deserialize)]
+pub struct MonitorRequest {
+    pub enabled: bool,
+}
+
+/// PUT /admin/monitor
+pub async fn handle_set_monitor_enabled(
+    State(state): State<AppState>,
+    Json(req): Json<MonitorRequest>,
+) -> Response {
+    state.monitor.set_enabled(req.enabled);
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Multipart, State},
+    extract::{Extension, Multipart, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
@@ -10,12 +10,15 @@ use uuid::Uuid;
 
 use crate::proxy::{
     audio::AudioProcessor,
+    common::client_error::ApiProtocol,
+    security::{model_access_denied, ApiKeyIdentity},
     server::AppState,
 };
 
 /// 处理音频转录请求 (OpenAI Whisper API 兼容)
 pub async fn handle_audio_transcription(
     State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let mut audio_data: Option<Vec<u8>> = None;
@@ -63,6 +66,10 @@ pub async fn handle_audio_transcription(
         model
     );
 
+    if let Some(resp) = model_access_denied(identity.as_deref(), ApiProtocol::OpenAI, &model) {
+        return Ok(resp);
+    }
+
     // 2. 检测 MIME 类型
     let mime_type = AudioProcessor::detect_mime_type(&file_name)
         .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
@@ -101,7 +108,7 @@ pub async fn handle_audio_transcription(
     // 6. 获取 Token 和上游客户端
     let token_manager = state.token_manager;
     let (access_token, project_id, email) = token_manager
-        .get_token("text", false, None)
+        .get_token("text", false, None, &model)
         .await
         .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
 
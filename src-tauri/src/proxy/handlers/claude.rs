@@ -2,7 +2,7 @@
 
 use axum::{
     body::Body,
-    extract::{Json, State},
+    extract::{Extension, Json, State},
     http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
@@ -12,6 +12,10 @@ use serde_json::{json, Value};
 use tokio::time::{sleep, Duration};
 use tracing::{debug, error, info};
 
+use crate::proxy::common::client_error::{self, ApiProtocol};
+use crate::proxy::common::request_features::RequestFeatures;
+use crate::proxy::handlers::common::{acquire_fallback_token, allows_admission_queue, apply_quota_downgrade, with_fallback_header};
+use crate::proxy::security::{model_access_denied, ApiKeyIdentity};
 use crate::proxy::mappers::claude::{
     transform_claude_request_in, transform_response, create_claude_sse_stream, ClaudeRequest,
     close_tool_loop_for_thinking,
@@ -306,6 +310,7 @@ fn should_rotate_account(status_code: u16) -> bool {
 /// 处理 Chat 消息请求流程
 pub async fn handle_messages(
     State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
     headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Response {
@@ -507,23 +512,33 @@ pub async fn handle_messages(
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
+    // 请求特征 (用于条件模型映射，按客户端原始请求计算)
+    let features = RequestFeatures::from_claude(&request)
+        .with_api_key(identity.as_deref().map(|id| id.name.as_str()));
+
     let mut last_error = String::new();
     let mut retried_without_thinking = false;
     let mut last_email: Option<String> = None;
     
     for attempt in 0..max_attempts {
         // 2. 模型路由解析
-        let mut mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
             &request_for_body.model,
             &*state.custom_mapping.read().await,
+            &features,
         );
+        // 账号池对该模型的剩余配额低于阈值时降级
+        let mut mapped_model = apply_quota_downgrade(&state.custom_mapping, &token_manager, identity.as_deref(), mapped_model).await;
+        if let Some(resp) = model_access_denied(identity.as_deref(), ApiProtocol::Claude, &mapped_model) {
+            return resp;
+        }
         
         // 将 Claude 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = request_for_body.tools.as_ref().map(|list| {
             list.iter().map(|t| serde_json::to_value(t).unwrap_or(json!({}))).collect()
         });
 
-        let config = crate::proxy::mappers::common_utils::resolve_request_config(&request_for_body.model, &mapped_model, &tools_val);
+        let mut config = crate::proxy::mappers::common_utils::resolve_request_config(&request_for_body.model, &mapped_model, &tools_val);
 
         // 0. 尝试提取 session_id 用于粘性调度 (Phase 2/3)
         // 使用 SessionManager 生成稳定的会话指纹
@@ -531,25 +546,52 @@ pub async fn handle_messages(
         let session_id = Some(session_id_str.as_str());
 
         let force_rotate_token = attempt > 0;
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id).await {
+        let mut fallback_from: Option<String> = None;
+        // 配置了降级链时不进入准入排队，主模型无可用账号时直接尝试替代模型
+        let allow_queue = allows_admission_queue(&state.custom_mapping, &mapped_model).await;
+        let (access_token, project_id, email) = match token_manager.get_token_queued(&config.request_type, force_rotate_token, session_id, &config.final_model, allow_queue).await {
             Ok(t) => t,
-            Err(e) => {
-                let safe_message = if e.contains("invalid_grant") {
-                    "OAuth refresh failed (invalid_grant): refresh_token likely revoked/expired; reauthorize account(s) to restore service.".to_string()
-                } else {
-                    e
-                };
-                 return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(json!({
-                        "type": "error",
-                        "error": {
-                            "type": "overloaded_error",
-                            "message": format!("No available accounts: {}", safe_message)
-                        }
-                    }))
-                ).into_response();
-            }
+            // 主模型无可用账号: 按降级链尝试替代模型
+            Err(e) => match acquire_fallback_token(
+                &state.custom_mapping,
+                &token_manager,
+                identity.as_deref(),
+                ApiProtocol::Claude,
+                &request_for_body.model,
+                &mapped_model,
+                &tools_val,
+                session_id,
+            )
+            .await
+            {
+                Some(fallback) => {
+                    fallback_from = fallback.announce.then(|| mapped_model.clone());
+                    mapped_model = fallback.model;
+                    config = fallback.config;
+                    fallback.token
+                }
+                None => {
+                    // 账号池全部限流 (排队后仍无法分配): 返回 429 与真实的锁定解除时间
+                    if let Some(retry_after) = token_manager.pool_retry_after(&config.final_model) {
+                        return client_error::rate_limit_response(ApiProtocol::Claude, &e, retry_after);
+                    }
+                    let safe_message = if e.contains("invalid_grant") {
+                        "OAuth refresh failed (invalid_grant): refresh_token likely revoked/expired; reauthorize account(s) to restore service.".to_string()
+                    } else {
+                        e
+                    };
+                     return (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        Json(json!({
+                            "type": "error",
+                            "error": {
+                                "type": "overloaded_error",
+                                "message": format!("No available accounts: {}", safe_message)
+                            }
+                        }))
+                    ).into_response();
+                }
+            },
         };
 
         last_email = Some(email.clone());
@@ -661,6 +703,7 @@ pub async fn handle_messages(
             Err(e) => {
                 last_error = e.clone();
                 debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                token_manager.mark_upstream_failure(&email, Some(&request_with_mapped.model)).await;
                 continue;
             }
         };
@@ -670,7 +713,7 @@ pub async fn handle_messages(
         // 成功
         if status.is_success() {
             // [智能限流] 请求成功，重置该账号的连续失败计数
-            token_manager.mark_account_success(&email);
+            token_manager.mark_account_success(&email, Some(&request_with_mapped.model));
             
             // 处理流式响应
             if actual_stream {
@@ -694,6 +737,7 @@ pub async fn handle_messages(
                         if bytes.is_empty() {
                             tracing::warn!("[{}] Empty first chunk received, treating as Empty Response and retrying...", trace_id);
                             last_error = "Empty response stream (0 bytes)".to_string();
+                            token_manager.mark_upstream_failure(&email, Some(&request_with_mapped.model)).await;
                             continue;
                         }
                         
@@ -710,7 +754,7 @@ pub async fn handle_messages(
                         // 判断客户端期望的格式
                         if client_wants_stream {
                             // 客户端本就要 Stream，直接返回 SSE
-                            return Response::builder()
+                            let response = Response::builder()
                                 .status(StatusCode::OK)
                                 .header(header::CONTENT_TYPE, "text/event-stream")
                                 .header(header::CACHE_CONTROL, "no-cache")
@@ -719,6 +763,7 @@ pub async fn handle_messages(
                                 .header("X-Mapped-Model", &request_with_mapped.model)
                                 .body(Body::from_stream(combined_stream))
                                 .unwrap();
+                            return with_fallback_header(response, fallback_from.as_deref());
                         } else {
                             // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                             use crate::proxy::mappers::claude::collect_stream_to_json;
@@ -726,13 +771,14 @@ pub async fn handle_messages(
                             match collect_stream_to_json(combined_stream).await {
                                 Ok(full_response) => {
                                     info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
-                                    return Response::builder()
+                                    let response = Response::builder()
                                         .status(StatusCode::OK)
                                         .header(header::CONTENT_TYPE, "application/json")
                                         .header("X-Account-Email", &email)
                                         .header("X-Mapped-Model", &request_with_mapped.model)
                                         .body(Body::from(serde_json::to_string(&full_response).unwrap()))
                                         .unwrap();
+                                    return with_fallback_header(response, fallback_from.as_deref());
                                 }
                                 Err(e) => {
                                     return (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response();
@@ -743,11 +789,13 @@ pub async fn handle_messages(
                     Some(Err(e)) => {
                         tracing::warn!("[{}] Stream error on first chunk: {}, retrying...", trace_id, e);
                         last_error = format!("Stream error: {}", e);
+                        token_manager.mark_upstream_failure(&email, Some(&request_with_mapped.model)).await;
                         continue;
                     },
                     None => {
                         tracing::warn!("[{}] Stream ended immediately (Empty Response), retrying...", trace_id);
                         last_error = "Empty response stream (None)".to_string();
+                        token_manager.mark_upstream_failure(&email, Some(&request_with_mapped.model)).await;
                         continue;
                     }
                 }
@@ -799,7 +847,8 @@ pub async fn handle_messages(
                     cache_info
                 );
 
-                return (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                let response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                return with_fallback_header(response, fallback_from.as_deref());
             }
         }
         
@@ -814,6 +863,9 @@ pub async fn handle_messages(
         
         // 3. 标记限流状态(用于 UI 显示) - 使用异步版本以支持实时配额刷新
         // 🆕 传入实际使用的模型,实现模型级别限流,避免不同模型配额互相影响
+        if status.is_server_error() {
+            token_manager.mark_upstream_failure(&email, Some(&request_with_mapped.model)).await;
+        }
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
             token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
         }
@@ -916,18 +968,21 @@ pub async fn handle_messages(
 
 /// 列出可用模型
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+    use crate::proxy::common::model_mapping::get_all_dynamic_model_descriptors;
 
-    let model_ids = get_all_dynamic_models(
+    let models = get_all_dynamic_model_descriptors(
         &state.custom_mapping,
     ).await;
 
-    let data: Vec<_> = model_ids.into_iter().map(|id| {
+    let data: Vec<_> = models.into_iter().map(|model| {
         json!({
-            "id": id,
+            "id": model.id,
             "object": "model",
             "created": 1706745600,
-            "owned_by": "antigravity"
+            "owned_by": "antigravity",
+            "context_window": model.context_window,
+            "max_output_tokens": model.max_output_tokens,
+            "capabilities": model.capabilities()
         })
     }).collect();
 
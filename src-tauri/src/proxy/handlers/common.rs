@@ -1,6 +1,95 @@
-use axum::{extract::State, extract::Json, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::Json, http::{HeaderValue, StatusCode}, response::{IntoResponse, Response}};
 use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use crate::proxy::common::client_error::ApiProtocol;
+use crate::proxy::common::model_mapping::ModelRouter;
+use crate::proxy::common::request_features::RequestFeatures;
+use crate::proxy::mappers::common_utils::{resolve_request_config, RequestConfig};
+use crate::proxy::security::{model_access_denied, ApiKeyIdentity};
 use crate::proxy::server::AppState;
+use crate::proxy::token_manager::TokenManager;
+
+/// 模型降级结果
+pub(crate) struct ModelFallback {
+    /// 替代模型 (映射后的名称)
+    pub model: String,
+    pub config: RequestConfig,
+    /// (access_token, project_id, email)
+    pub token: (String, String, String),
+    /// 是否需要在响应中附加 X-Model-Fallback-From
+    pub announce: bool,
+}
+
+/// 主模型在账号池中找不到可用账号时，按降级链依次为替代模型获取账号
+/// 跳过 API Key 无权访问的模型，替代模型同样不进入准入排队，全部失败时返回 None
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn acquire_fallback_token(
+    router: &RwLock<ModelRouter>,
+    token_manager: &TokenManager,
+    identity: Option<&ApiKeyIdentity>,
+    protocol: ApiProtocol,
+    original_model: &str,
+    primary_model: &str,
+    tools: &Option<Vec<Value>>,
+    session_id: Option<&str>,
+) -> Option<ModelFallback> {
+    let (chain, announce) = {
+        let router = router.read().await;
+        (router.fallback_chain(primary_model).to_vec(), router.fallback_header())
+    };
+    for model in chain {
+        if model_access_denied(identity, protocol, &model).is_some() {
+            continue;
+        }
+        let config = resolve_request_config(original_model, &model, tools);
+        match token_manager
+            .get_token_queued(&config.request_type, false, session_id, &config.final_model, false)
+            .await
+        {
+            Ok(token) => {
+                tracing::warn!("[Fallback] {} 无可用账号，降级到 {}", primary_model, model);
+                return Some(ModelFallback { model, config, token, announce });
+            }
+            Err(e) => tracing::debug!("[Fallback] 替代模型 {} 同样不可用: {}", model, e),
+        }
+    }
+    None
+}
+
+/// 模型未配置降级链时才允许主模型进入准入排队，否则应立即尝试替代模型
+pub(crate) async fn allows_admission_queue(router: &RwLock<ModelRouter>, mapped_model: &str) -> bool {
+    router.read().await.fallback_chain(mapped_model).is_empty()
+}
+
+/// 账号池对映射后模型的平均剩余配额低于阈值时，按配额降级规则改用替代模型
+/// 不降级到 API Key 无权访问的模型
+pub(crate) async fn apply_quota_downgrade(
+    router: &RwLock<ModelRouter>,
+    token_manager: &TokenManager,
+    identity: Option<&ApiKeyIdentity>,
+    mapped_model: String,
+) -> String {
+    let steps = router.read().await.quota_downgrade(
+        &mapped_model,
+        |m| token_manager.pool_average_quota(m),
+        |m| identity.is_none_or(|id| id.allows_model(m)),
+    );
+    for step in &steps {
+        tracing::info!(
+            "[QuotaDowngrade] {} 账号池平均剩余配额 {:.1}% < {}%，降级到 {}",
+            step.from, step.pool_quota, step.threshold_percentage, step.to
+        );
+    }
+    steps.into_iter().last().map_or(mapped_model, |s| s.to)
+}
+
+/// 发生降级且开启了提示头时附加 X-Model-Fallback-From (值为原模型)
+pub(crate) fn with_fallback_header(mut response: Response, fallback_from: Option<&str>) -> Response {
+    if let Some(value) = fallback_from.and_then(|m| HeaderValue::from_str(m).ok()) {
+        response.headers_mut().insert("X-Model-Fallback-From", value);
+    }
+    response
+}
 
 /// Detects model capabilities and configuration
 /// POST /v1/models/detect
@@ -14,11 +103,43 @@ pub async fn handle_detect_model(
         return (StatusCode::BAD_REQUEST, "Missing 'model' field").into_response();
     }
 
+    // Optional request features to evaluate conditional mapping rules
+    let features: RequestFeatures = match body.get("request_features") {
+        Some(v) => match serde_json::from_value(v.clone()) {
+            Ok(f) => f,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, format!("Invalid 'request_features': {}", e)).into_response();
+            }
+        },
+        None => RequestFeatures::default(),
+    };
+
     // 1. Resolve mapping
-    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+    let route = crate::proxy::common::model_mapping::resolve_model_route_detailed(
         model_name,
         &*state.custom_mapping.read().await,
+        &features,
     );
+    let mapped_model = route.mapped_model;
+
+    // Quota-driven downgrade: configured threshold and current pool average
+    let (quota_downgrade, effective_model) = {
+        let router = state.custom_mapping.read().await;
+        let pool_quota = |m: &str| state.token_manager.pool_average_quota(m);
+        let steps = router.quota_downgrade(&mapped_model, pool_quota, |_| true);
+        let info = router.quota_downgrade_rule(&mapped_model).map(|rule| {
+            json!({
+                "model": rule.model,
+                "target": rule.target,
+                "threshold_percentage": rule.threshold_percentage,
+                "pool_quota": pool_quota(&mapped_model),
+                "active": !steps.is_empty(),
+                "steps": steps,
+            })
+        });
+        let effective = steps.last().map_or_else(|| mapped_model.clone(), |s| s.to.clone());
+        (info, effective)
+    };
 
     // 2. Resolve capabilities
     let config = crate::proxy::mappers::common_utils::resolve_request_config(
@@ -31,6 +152,10 @@ pub async fn handle_detect_model(
     let mut response = json!({
         "model": model_name,
         "mapped_model": mapped_model,
+        "mapping_rule": route.rule,
+        "quota_downgrade": quota_downgrade,
+        "capabilities": crate::proxy::model_registry::ModelRegistry::global().get(&effective_model),
+        "effective_model": effective_model,
         "type": config.request_type,
         "features": {
             "has_web_search": config.inject_google_search,
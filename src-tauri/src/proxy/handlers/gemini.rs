@@ -1,9 +1,13 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::{Extension, Json, Path}, http::StatusCode, response::IntoResponse};
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
+use crate::proxy::common::client_error::{self, ApiProtocol};
+use crate::proxy::common::request_features::RequestFeatures;
+use crate::proxy::handlers::common::apply_quota_downgrade;
+use crate::proxy::security::{model_access_denied, ApiKeyIdentity};
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
  
@@ -13,6 +17,7 @@ const MAX_RETRY_ATTEMPTS: usize = 3;
 /// 路径参数: model_name, method (e.g. "gemini-pro", "generateContent")
 pub async fn handle_generate(
     State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
     Path(model_action): Path<String>,
     Json(body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
@@ -37,6 +42,10 @@ pub async fn handle_generate(
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
     
+    // 请求特征 (用于条件模型映射)
+    let features = RequestFeatures::from_gemini(&body)
+        .with_api_key(identity.as_deref().map(|id| id.name.as_str()));
+
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
@@ -45,7 +54,13 @@ pub async fn handle_generate(
         let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
             &model_name,
             &*state.custom_mapping.read().await,
+            &features,
         );
+        // 账号池对该模型的剩余配额低于阈值时降级
+        let mapped_model = apply_quota_downgrade(&state.custom_mapping, &token_manager, identity.as_deref(), mapped_model).await;
+        if let Some(resp) = model_access_denied(identity.as_deref(), ApiProtocol::Gemini, &mapped_model) {
+            return Ok(resp);
+        }
         // 提取 tools 列表以进行联网探测 (Gemini 风格可能是嵌套的)
         let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
             let mut flattened = Vec::new();
@@ -66,9 +81,12 @@ pub async fn handle_generate(
         let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
 
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
+        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id), &config.final_model).await {
             Ok(t) => t,
             Err(e) => {
+                if let Some(retry_after) = token_manager.pool_retry_after(&config.final_model) {
+                    return Ok(client_error::rate_limit_response(ApiProtocol::Gemini, &e, retry_after));
+                }
                 return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
             }
         };
@@ -90,12 +108,14 @@ pub async fn handle_generate(
                 Err(e) => {
                     last_error = e.clone();
                     debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                    token_manager.mark_upstream_failure(&email, Some(&config.final_model)).await;
                     continue;
                 }
             };
 
         let status = response.status();
         if status.is_success() {
+            token_manager.mark_account_success(&email, Some(&config.final_model));
             // 6. 响应处理
             if is_stream {
                 use axum::body::Body;
@@ -185,11 +205,14 @@ pub async fn handle_generate(
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        if status.is_server_error() {
+            token_manager.mark_upstream_failure(&email, Some(&config.final_model)).await;
+        }
  
         // 只有 429 (限流), 529 (过载), 503, 403 (权限) 和 401 (认证失效) 触发账号轮换
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 || status_code == 403 || status_code == 401 {
             // 记录限流信息 (全局同步)
-            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
+            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text, Some(&config.final_model));
 
             // 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判上游的频率限制提示 (如 "check quota")
             if status_code == 429 && error_text.contains("QUOTA_EXHAUSTED") {
@@ -214,22 +237,23 @@ pub async fn handle_generate(
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+    use crate::proxy::common::model_mapping::get_all_dynamic_model_descriptors;
 
-    // 获取所有动态模型列表（与 /v1/models 一致）
-    let model_ids = get_all_dynamic_models(
+    // 获取所有动态模型列表（与 /v1/models 一致），上下文窗口与能力取自模型注册表
+    let descriptors = get_all_dynamic_model_descriptors(
         &state.custom_mapping,
     ).await;
 
     // 转换为 Gemini API 格式
-    let models: Vec<_> = model_ids.into_iter().map(|id| {
+    let models: Vec<_> = descriptors.into_iter().map(|model| {
         json!({
-            "name": format!("models/{}", id),
+            "name": format!("models/{}", model.id),
             "version": "001",
-            "displayName": id.clone(),
+            "displayName": model.id,
             "description": "",
-            "inputTokenLimit": 128000,
-            "outputTokenLimit": 8192,
+            "inputTokenLimit": model.context_window,
+            "outputTokenLimit": model.max_output_tokens,
+            "thinking": model.thinking,
             "supportedGenerationMethods": ["generateContent", "countTokens"],
             "temperature": 1.0,
             "topP": 0.95,
@@ -247,9 +271,9 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
     }))
 }
 
-pub async fn handle_count_tokens(State(state): State<AppState>, Path(_model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
+pub async fn handle_count_tokens(State(state): State<AppState>, Path(model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
     let model_group = "gemini";
-    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, false, None).await
+    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, false, None, &model_name).await
         .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
     
     Ok(Json(json!({"totalTokens": 0})))
@@ -9,3 +9,4 @@ pub mod common;
 pub mod audio;  // 音频转录处理器 (PR #311)
 pub mod warmup; // 预热处理器
 
+pub mod admin;  // 管理 API (/admin/*)
@@ -1,5 +1,5 @@
 // OpenAI Handler
-use axum::{extract::Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum::{extract::Extension, extract::Json, extract::State, http::StatusCode, response::IntoResponse};
 use base64::Engine as _; 
 use bytes::Bytes;
 use serde_json::{json, Value};
@@ -9,6 +9,10 @@ use crate::proxy::mappers::openai::{
     transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
+use crate::proxy::common::client_error::{self, ApiProtocol};
+use crate::proxy::common::request_features::RequestFeatures;
+use crate::proxy::handlers::common::{acquire_fallback_token, allows_admission_queue, apply_quota_downgrade, with_fallback_header};
+use crate::proxy::security::{model_access_denied, ApiKeyIdentity};
 use crate::proxy::server::AppState;
 
 const MAX_RETRY_ATTEMPTS: usize = 3;
@@ -16,6 +20,7 @@ use crate::proxy::session_manager::SessionManager;
 
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
@@ -46,6 +51,10 @@ pub async fn handle_chat_completions(
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
+    // 请求特征 (用于条件模型映射)
+    let features = RequestFeatures::from_openai(&openai_req)
+        .with_api_key(identity.as_deref().map(|id| id.name.as_str()));
+
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
@@ -54,13 +63,19 @@ pub async fn handle_chat_completions(
         let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
             &openai_req.model,
             &*state.custom_mapping.read().await,
+            &features,
         );
+        // 账号池对该模型的剩余配额低于阈值时降级
+        let mut mapped_model = apply_quota_downgrade(&state.custom_mapping, &token_manager, identity.as_deref(), mapped_model).await;
+        if let Some(resp) = model_access_denied(identity.as_deref(), ApiProtocol::OpenAI, &mapped_model) {
+            return Ok(resp);
+        }
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
             .as_ref()
             .map(|list| list.iter().cloned().collect());
-        let config = crate::proxy::mappers::common_utils::resolve_request_config(
+        let mut config = crate::proxy::mappers::common_utils::resolve_request_config(
             &openai_req.model,
             &mapped_model,
             &tools_val,
@@ -70,18 +85,43 @@ pub async fn handle_chat_completions(
         let session_id = SessionManager::extract_openai_session_id(&openai_req);
 
         // 4. 获取 Token (使用准确的 request_type)
-        // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
+        // 关键：在重试尝试 (attempt > 0) 时强制轮换账号；配置了降级链时不排队，直接尝试替代模型
+        let mut fallback_from: Option<String> = None;
+        let allow_queue = allows_admission_queue(&state.custom_mapping, &mapped_model).await;
         let (access_token, project_id, email) = match token_manager
-            .get_token(&config.request_type, attempt > 0, Some(&session_id))
+            .get_token_queued(&config.request_type, attempt > 0, Some(&session_id), &config.final_model, allow_queue)
             .await
         {
             Ok(t) => t,
-            Err(e) => {
-                return Err((
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    format!("Token error: {}", e),
-                ));
-            }
+            // 主模型无可用账号: 按降级链尝试替代模型
+            Err(e) => match acquire_fallback_token(
+                &state.custom_mapping,
+                &token_manager,
+                identity.as_deref(),
+                ApiProtocol::OpenAI,
+                &openai_req.model,
+                &mapped_model,
+                &tools_val,
+                Some(&session_id),
+            )
+            .await
+            {
+                Some(fallback) => {
+                    fallback_from = fallback.announce.then(|| mapped_model.clone());
+                    mapped_model = fallback.model;
+                    config = fallback.config;
+                    fallback.token
+                }
+                None => {
+                    if let Some(retry_after) = token_manager.pool_retry_after(&config.final_model) {
+                        return Ok(client_error::rate_limit_response(ApiProtocol::OpenAI, &e, retry_after));
+                    }
+                    return Err((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        format!("Token error: {}", e),
+                    ));
+                }
+            },
         };
 
         last_email = Some(email.clone());
@@ -125,12 +165,14 @@ pub async fn handle_chat_completions(
                     max_attempts,
                     e
                 );
+                token_manager.mark_upstream_failure(&email, Some(&config.final_model)).await;
                 continue;
             }
         };
 
         let status = response.status();
         if status.is_success() {
+            token_manager.mark_account_success(&email, Some(&config.final_model));
             // 5. 处理流式 vs 非流式
             if actual_stream {
                 use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
@@ -145,7 +187,7 @@ pub async fn handle_chat_completions(
                 if client_wants_stream {
                     // 客户端本就要 Stream，直接返回 SSE
                     let body = Body::from_stream(openai_stream);
-                    return Ok(Response::builder()
+                    let response = Response::builder()
                         .header("Content-Type", "text/event-stream")
                         .header("Cache-Control", "no-cache")
                         .header("Connection", "keep-alive")
@@ -153,7 +195,8 @@ pub async fn handle_chat_completions(
                         .header("X-Mapped-Model", &mapped_model)
                         .body(body)
                         .unwrap()
-                        .into_response());
+                        .into_response();
+                    return Ok(with_fallback_header(response, fallback_from.as_deref()));
                 } else {
                     // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                     use crate::proxy::mappers::openai::collect_openai_stream_to_json;
@@ -170,7 +213,8 @@ pub async fn handle_chat_completions(
                     match collect_openai_stream_to_json(sse_stream).await {
                         Ok(full_response) => {
                             info!("[OpenAI] ✓ Stream collected and converted to JSON");
-                            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response());
+                            let response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response();
+                            return Ok(with_fallback_header(response, fallback_from.as_deref()));
                         }
                         Err(e) => {
                             return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)));
@@ -185,7 +229,8 @@ pub async fn handle_chat_completions(
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
             let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
+            let response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response();
+            return Ok(with_fallback_header(response, fallback_from.as_deref()));
         }
 
         // 处理特定错误并重试
@@ -193,6 +238,9 @@ pub async fn handle_chat_completions(
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        if status.is_server_error() {
+            token_manager.mark_upstream_failure(&email, Some(&config.final_model)).await;
+        }
 
         // [New] 打印错误报文日志
         tracing::error!(
@@ -204,7 +252,7 @@ pub async fn handle_chat_completions(
         // 429/529/503 智能处理
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
             // 记录限流信息 (全局同步)
-            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
+            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text, Some(&config.final_model));
 
             // 1. 优先尝试解析 RetryInfo (由 Google Cloud 直接下发)
             if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(&error_text) {
@@ -282,6 +330,7 @@ pub async fn handle_chat_completions(
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     info!(
@@ -562,6 +611,10 @@ pub async fn handle_completions(
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
+    // 请求特征 (用于条件模型映射)
+    let features = RequestFeatures::from_openai(&openai_req)
+        .with_api_key(identity.as_deref().map(|id| id.name.as_str()));
+
     let mut last_error = String::new();
 
     for _attempt in 0..max_attempts {
@@ -569,7 +622,13 @@ pub async fn handle_completions(
         let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
             &openai_req.model,
             &*state.custom_mapping.read().await,
+            &features,
         );
+        // 账号池对该模型的剩余配额低于阈值时降级
+        let mapped_model = apply_quota_downgrade(&state.custom_mapping, &token_manager, identity.as_deref(), mapped_model).await;
+        if let Some(resp) = model_access_denied(identity.as_deref(), ApiProtocol::OpenAI, &mapped_model) {
+            return Ok(resp);
+        }
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
@@ -582,9 +641,12 @@ pub async fn handle_completions(
         );
 
         let (access_token, project_id, email) =
-            match token_manager.get_token(&config.request_type, false, None).await {
+            match token_manager.get_token(&config.request_type, false, None, &config.final_model).await {
                 Ok(t) => t,
                 Err(e) => {
+                    if let Some(retry_after) = token_manager.pool_retry_after(&config.final_model) {
+                        return Ok(client_error::rate_limit_response(ApiProtocol::OpenAI, &e, retry_after));
+                    }
                     return Err((
                         StatusCode::SERVICE_UNAVAILABLE,
                         format!("Token error: {}", e),
@@ -616,12 +678,14 @@ pub async fn handle_completions(
             Ok(r) => r,
             Err(e) => {
                 last_error = e.clone();
+                token_manager.mark_upstream_failure(&email, Some(&config.final_model)).await;
                 continue;
             }
         };
 
         let status = response.status();
         if status.is_success() {
+            token_manager.mark_account_success(&email, Some(&config.final_model));
             if list_response {
                 use axum::body::Body;
                 use axum::response::Response;
@@ -685,6 +749,9 @@ pub async fn handle_completions(
         let status_code = status.as_u16();
         let error_text = response.text().await.unwrap_or_default();
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        if status.is_server_error() {
+            token_manager.mark_upstream_failure(&email, Some(&config.final_model)).await;
+        }
 
         if status_code == 429 || status_code == 403 || status_code == 401 {
             continue;
@@ -699,18 +766,21 @@ pub async fn handle_completions(
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+    use crate::proxy::common::model_mapping::get_all_dynamic_model_descriptors;
 
-    let model_ids = get_all_dynamic_models(
+    let models = get_all_dynamic_model_descriptors(
         &state.custom_mapping,
     ).await;
 
-    let data: Vec<_> = model_ids.into_iter().map(|id| {
+    let data: Vec<_> = models.into_iter().map(|model| {
         json!({
-            "id": id,
+            "id": model.id,
             "object": "model",
             "created": 1706745600,
-            "owned_by": "antigravity"
+            "owned_by": "antigravity",
+            "context_window": model.context_window,
+            "max_output_tokens": model.max_output_tokens,
+            "capabilities": model.capabilities()
         })
     }).collect();
 
@@ -724,6 +794,7 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
 /// 处理图像生成请求，转换为 Gemini API 格式
 pub async fn handle_images_generations(
     State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 1. 解析请求参数
@@ -789,11 +860,16 @@ pub async fn handle_images_generations(
         _ => {}
     }
 
+    // 上游固定使用 gemini-3-pro-image，按该模型校验白名单
+    if let Some(resp) = model_access_denied(identity.as_deref(), ApiProtocol::OpenAI, "gemini-3-pro-image") {
+        return Ok(resp);
+    }
+
     // 3. 获取 Token
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
 
-    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None).await
+    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None, "gemini-3-pro-image").await
     {
         Ok(t) => t,
         Err(e) => {
@@ -958,6 +1034,7 @@ pub async fn handle_images_generations(
 
 pub async fn handle_images_edits(
     State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
     mut multipart: axum::extract::Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     tracing::info!("[Images] Received edit request");
@@ -1043,11 +1120,15 @@ pub async fn handle_images_edits(
     // But if users see raw text, it means client defaulted to 'url' or we defaulted to 'url'.
     // Let's keep the log to confirm.
 
+    if let Some(resp) = model_access_denied(identity.as_deref(), ApiProtocol::OpenAI, &model) {
+        return Ok(resp);
+    }
+
     // 1. 获取 Upstream
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     // Fix: Proper get_token call with correct signature and unwrap (using image_gen quota)
-    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None).await
+    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None, "gemini-3-pro-image").await
     {
         Ok(t) => t,
         Err(e) => {
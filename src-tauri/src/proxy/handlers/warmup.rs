@@ -39,48 +39,24 @@ pub struct WarmupResponse {
     pub error: Option<String>,
 }
 
-/// 处理预热请求
-pub async fn handle_warmup(
-    State(state): State<AppState>,
-    Json(req): Json<WarmupRequest>,
-) -> Response {
-    info!(
-        "[Warmup-API] ========== START: email={}, model={} ==========",
-        req.email, req.model
-    );
-
-    // ===== 步骤 1: 获取 Token =====
-    let (access_token, project_id) = if let (Some(at), Some(pid)) = (&req.access_token, &req.project_id) {
-        (at.clone(), pid.clone())
-    } else {
-        match state.token_manager.get_token_by_email(&req.email).await {
-            Ok((at, pid, _)) => (at, pid),
-            Err(e) => {
-                warn!(
-                    "[Warmup-API] Step 1 FAILED: Token error for {}: {}",
-                    req.email, e
-                );
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(WarmupResponse {
-                        success: false,
-                        message: format!("Failed to get token for {}", req.email),
-                        error: Some(e),
-                    }),
-                )
-                    .into_response();
-            }
-        }
-    };
+/// 预热请求的上游调用参数
+pub struct WarmupCall {
+    pub body: Value,
+    pub method: &'static str,
+    pub query: Option<&'static str>,
+}
 
-    // ===== 步骤 2: 根据模型类型构建请求体 =====
-    let is_claude = req.model.to_lowercase().contains("claude");
-    let is_image = req.model.to_lowercase().contains("image");
+/// 构建预热请求 (模型名不做映射)，熔断器的半开探测复用同一请求
+pub fn build_warmup_request(model: &str, project_id: &str) -> Result<WarmupCall, String> {
+    let model_lower = model.to_lowercase();
+    let descriptor = crate::proxy::model_registry::ModelRegistry::global().get(model);
+    let is_claude = descriptor.is_claude();
+    let is_image = descriptor.image_output;
 
     let body: Value = if is_claude {
         // Claude 模型：使用 transform_claude_request_in 转换
         let claude_request = crate::proxy::mappers::claude::models::ClaudeRequest {
-            model: req.model.clone(),
+            model: model.to_string(),
             messages: vec![crate::proxy::mappers::claude::models::Message {
                 role: "user".to_string(),
                 content: crate::proxy::mappers::claude::models::MessageContent::String(
@@ -99,29 +75,12 @@ pub async fn handle_warmup(
             output_config: None,
         };
 
-        match crate::proxy::mappers::claude::transform_claude_request_in(
-            &claude_request,
-            &project_id,
-        ) {
-            Ok(transformed) => transformed,
-            Err(e) => {
-                warn!("[Warmup-API] Step 2 FAILED: Claude transform error: {}", e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(WarmupResponse {
-                        success: false,
-                        message: format!("Transform error: {}", e),
-                        error: Some(e),
-                    }),
-                )
-                    .into_response();
-            }
-        }
+        crate::proxy::mappers::claude::transform_claude_request_in(&claude_request, project_id)?
     } else {
         // Gemini 模型：使用 wrap_request
         let base_request = if is_image {
             json!({
-                "model": req.model,
+                "model": model,
                 "contents": [{"role": "user", "parts": [{"text": "Say hi"}]}],
                 "generationConfig": {
                     "maxOutputTokens": 10,
@@ -130,24 +89,77 @@ pub async fn handle_warmup(
             })
         } else {
             json!({
-                "model": req.model,
+                "model": model,
                 "contents": [{"role": "user", "parts": [{"text": "Say hi"}]}]
             })
         };
 
-        wrap_request(&base_request, &project_id, &req.model)
+        wrap_request(&base_request, project_id, model)
     };
 
-    // ===== 步骤 3: 调用 UpstreamClient =====
-    let model_lower = req.model.to_lowercase();
     let prefer_non_stream = model_lower.contains("flash-lite") || model_lower.contains("2.5-pro");
-
     let (method, query) = if prefer_non_stream {
         ("generateContent", None)
     } else {
         ("streamGenerateContent", Some("alt=sse"))
     };
+    Ok(WarmupCall { body, method, query })
+}
+
+/// 处理预热请求
+pub async fn handle_warmup(
+    State(state): State<AppState>,
+    Json(req): Json<WarmupRequest>,
+) -> Response {
+    info!(
+        "[Warmup-API] ========== START: email={}, model={} ==========",
+        req.email, req.model
+    );
+
+    // ===== 步骤 1: 获取 Token =====
+    let (access_token, project_id) = if let (Some(at), Some(pid)) = (&req.access_token, &req.project_id) {
+        (at.clone(), pid.clone())
+    } else {
+        match state.token_manager.get_token_by_email(&req.email).await {
+            Ok((at, pid, _)) => (at, pid),
+            Err(e) => {
+                warn!(
+                    "[Warmup-API] Step 1 FAILED: Token error for {}: {}",
+                    req.email, e
+                );
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(WarmupResponse {
+                        success: false,
+                        message: format!("Failed to get token for {}", req.email),
+                        error: Some(e),
+                    }),
+                )
+                    .into_response();
+            }
+        }
+    };
 
+    // ===== 步骤 2: 根据模型类型构建请求体 =====
+    let call = match build_warmup_request(&req.model, &project_id) {
+        Ok(call) => call,
+        Err(e) => {
+            warn!("[Warmup-API] Step 2 FAILED: Claude transform error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(WarmupResponse {
+                    success: false,
+                    message: format!("Transform error: {}", e),
+                    error: Some(e),
+                }),
+            )
+                .into_response();
+        }
+    };
+    let prefer_non_stream = call.query.is_none();
+    let (method, query, body) = (call.method, call.query, call.body);
+
+    // ===== 步骤 3: 调用 UpstreamClient =====
     let mut result = state
         .upstream
         .call_v1_internal(method, &access_token, body.clone(), query)
@@ -0,0 +1,323 @@
+// API Key 用量预算
+// 按 Key 名称统计 每分钟请求数 / 并发数 / 日月 token 用量，超出时由 key_budget_middleware 返回 429
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, TimeZone, Utc};
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::ApiKeyLimits;
+
+const RPM_WINDOW: Duration = Duration::from_secs(60);
+
+/// 超出预算的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetExceeded {
+    RequestsPerMinute { limit: u32, retry_after_sec: u64 },
+    Concurrency { limit: u32 },
+    DailyTokens { limit: u64, retry_after_sec: u64 },
+    MonthlyTokens { limit: u64, retry_after_sec: u64 },
+}
+
+impl BudgetExceeded {
+    /// 建议客户端等待的秒数 (写入 retry-after 头)
+    pub fn retry_after_sec(&self) -> u64 {
+        match self {
+            BudgetExceeded::RequestsPerMinute { retry_after_sec, .. }
+            | BudgetExceeded::DailyTokens { retry_after_sec, .. }
+            | BudgetExceeded::MonthlyTokens { retry_after_sec, .. } => (*retry_after_sec).max(1),
+            BudgetExceeded::Concurrency { .. } => 1,
+        }
+    }
+
+    pub fn message(&self, key_name: &str) -> String {
+        match self {
+            BudgetExceeded::RequestsPerMinute { limit, .. } => format!(
+                "API key '{}' exceeded its limit of {} requests per minute",
+                key_name, limit
+            ),
+            BudgetExceeded::Concurrency { limit } => format!(
+                "API key '{}' exceeded its limit of {} concurrent requests",
+                key_name, limit
+            ),
+            BudgetExceeded::DailyTokens { limit, .. } => format!(
+                "API key '{}' exhausted its daily budget of {} tokens",
+                key_name, limit
+            ),
+            BudgetExceeded::MonthlyTokens { limit, .. } => format!(
+                "API key '{}' exhausted its monthly budget of {} tokens",
+                key_name, limit
+            ),
+        }
+    }
+}
+
+/// 并发占位，Drop 时释放 (需保持到响应体发送结束)
+pub struct InFlightGuard {
+    counter: Arc<AtomicU32>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct KeyBudgetTracker {
+    /// 最近一分钟内的请求时间 (key 名称 -> 时间队列)
+    request_windows: DashMap<String, VecDeque<Instant>>,
+    /// 进行中的请求数
+    in_flight: DashMap<String, Arc<AtomicU32>>,
+    /// token 用量缓存 (key 名称, 周期) -> tokens，首次访问时从数据库加载
+    usage: DashMap<(String, String), u64>,
+    /// 是否写入 proxy_logs.db
+    persist: bool,
+}
+
+impl KeyBudgetTracker {
+    pub fn new() -> Self {
+        Self {
+            request_windows: DashMap::new(),
+            in_flight: DashMap::new(),
+            usage: DashMap::new(),
+            persist: true,
+        }
+    }
+
+    #[cfg(test)]
+    fn in_memory() -> Self {
+        Self {
+            persist: false,
+            ..Self::new()
+        }
+    }
+
+    /// 检查预算并占用一个请求名额
+    /// 通过时返回并发占位 (未配置并发上限时为 None)
+    pub async fn try_acquire(
+        &self,
+        key_name: &str,
+        limits: &ApiKeyLimits,
+        now: Instant,
+        now_utc: DateTime<Utc>,
+    ) -> Result<Option<InFlightGuard>, BudgetExceeded> {
+        // 1. token 预算 (只读检查)
+        if let Some(limit) = limits.daily_tokens {
+            if self.usage_for(key_name, &day_period(now_utc)).await >= limit {
+                return Err(BudgetExceeded::DailyTokens {
+                    limit,
+                    retry_after_sec: seconds_until_next_day(now_utc),
+                });
+            }
+        }
+        if let Some(limit) = limits.monthly_tokens {
+            if self.usage_for(key_name, &month_period(now_utc)).await >= limit {
+                return Err(BudgetExceeded::MonthlyTokens {
+                    limit,
+                    retry_after_sec: seconds_until_next_month(now_utc),
+                });
+            }
+        }
+
+        // 2. 并发数: 检查与占用为同一原子操作，之后的检查失败时占位随 guard 释放
+        let guard = match limits.max_concurrency {
+            Some(limit) => {
+                let counter = self
+                    .in_flight
+                    .entry(key_name.to_string())
+                    .or_insert_with(|| Arc::new(AtomicU32::new(0)))
+                    .clone();
+                counter
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < limit).then_some(n + 1))
+                    .map_err(|_| BudgetExceeded::Concurrency { limit })?;
+                Some(InFlightGuard { counter })
+            }
+            None => None,
+        };
+
+        // 3. 每分钟请求数 (通过后记录本次请求)
+        if let Some(limit) = limits.requests_per_minute {
+            let mut window = self.request_windows.entry(key_name.to_string()).or_default();
+            while window
+                .front()
+                .is_some_and(|t| now.saturating_duration_since(*t) >= RPM_WINDOW)
+            {
+                window.pop_front();
+            }
+            if window.len() as u32 >= limit {
+                let oldest = *window.front().unwrap_or(&now);
+                let wait = RPM_WINDOW.saturating_sub(now.saturating_duration_since(oldest));
+                return Err(BudgetExceeded::RequestsPerMinute {
+                    limit,
+                    retry_after_sec: wait.as_secs_f64().ceil() as u64,
+                });
+            }
+            window.push_back(now);
+        }
+
+        Ok(guard)
+    }
+
+    /// 计入一次请求消耗的 token (input + output)
+    /// 内存计数为准，数据库写入在阻塞线程池中异步完成
+    pub async fn record_tokens(&self, key_name: &str, tokens: u64, now_utc: DateTime<Utc>) {
+        if tokens == 0 {
+            return;
+        }
+        for period in [day_period(now_utc), month_period(now_utc)] {
+            // 先确保缓存已从数据库加载，避免重启后覆盖历史用量
+            self.usage_for(key_name, &period).await;
+            *self
+                .usage
+                .entry((key_name.to_string(), period.clone()))
+                .or_insert(0) += tokens;
+
+            if self.persist {
+                let key_name = key_name.to_string();
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = crate::modules::proxy_db::add_key_usage(&key_name, &period, tokens) {
+                        tracing::error!("Failed to persist API key usage: {}", e);
+                    }
+                });
+            }
+        }
+    }
+
+    /// 当前周期内已使用的 token (缓存未命中时在阻塞线程池中读取数据库)
+    pub async fn usage_for(&self, key_name: &str, period: &str) -> u64 {
+        let cache_key = (key_name.to_string(), period.to_string());
+        if let Some(v) = self.usage.get(&cache_key).map(|v| *v) {
+            return v;
+        }
+        let loaded = if self.persist {
+            let (key_name, period) = cache_key.clone();
+            tokio::task::spawn_blocking(move || crate::modules::proxy_db::get_key_usage(&key_name, &period))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r)
+                .unwrap_or_else(|e| {
+                    tracing::error!("Failed to load API key usage: {}", e);
+                    0
+                })
+        } else {
+            0
+        };
+        *self.usage.entry(cache_key).or_insert(loaded)
+    }
+}
+
+impl Default for KeyBudgetTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn day_period(now: DateTime<Utc>) -> String {
+    format!("d:{}", now.format("%Y-%m-%d"))
+}
+
+fn month_period(now: DateTime<Utc>) -> String {
+    format!("m:{}", now.format("%Y-%m"))
+}
+
+fn seconds_until_next_day(now: DateTime<Utc>) -> u64 {
+    let next = (now.date_naive() + ChronoDuration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .map(|dt| Utc.from_utc_datetime(&dt));
+    next.map(|n| (n - now).num_seconds().max(1) as u64).unwrap_or(86400)
+}
+
+fn seconds_until_next_month(now: DateTime<Utc>) -> u64 {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+        .single()
+        .map(|n| (n - now).num_seconds().max(1) as u64)
+        .unwrap_or(86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> ApiKeyLimits {
+        ApiKeyLimits::default()
+    }
+
+    #[tokio::test]
+    async fn test_requests_per_minute() {
+        let tracker = KeyBudgetTracker::in_memory();
+        let l = ApiKeyLimits { requests_per_minute: Some(2), ..limits() };
+        let t0 = Instant::now();
+        let utc = Utc::now();
+
+        assert!(tracker.try_acquire("ci", &l, t0, utc).await.is_ok());
+        assert!(tracker.try_acquire("ci", &l, t0 + Duration::from_secs(10), utc).await.is_ok());
+        match tracker.try_acquire("ci", &l, t0 + Duration::from_secs(20), utc).await {
+            Err(BudgetExceeded::RequestsPerMinute { retry_after_sec, .. }) => assert_eq!(retry_after_sec, 40),
+            other => panic!("unexpected {:?}", other.map(|g| g.is_some())),
+        }
+        // 其他 key 不受影响
+        assert!(tracker.try_acquire("alice", &l, t0 + Duration::from_secs(20), utc).await.is_ok());
+        // 窗口滑出后恢复
+        assert!(tracker.try_acquire("ci", &l, t0 + Duration::from_secs(61), utc).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_guard_releases_on_drop() {
+        let tracker = KeyBudgetTracker::in_memory();
+        let l = ApiKeyLimits { max_concurrency: Some(1), ..limits() };
+        let now = Instant::now();
+        let utc = Utc::now();
+
+        let guard = tracker.try_acquire("ci", &l, now, utc).await.unwrap();
+        assert!(guard.is_some());
+        assert_eq!(
+            tracker.try_acquire("ci", &l, now, utc).await.err(),
+            Some(BudgetExceeded::Concurrency { limit: 1 })
+        );
+        drop(guard);
+        assert!(tracker.try_acquire("ci", &l, now, utc).await.is_ok());
+
+        // 每分钟请求数超限时释放已占用的并发名额
+        let l = ApiKeyLimits { max_concurrency: Some(2), requests_per_minute: Some(1), ..limits() };
+        let held = tracker.try_acquire("bot", &l, now, utc).await.unwrap();
+        assert!(matches!(
+            tracker.try_acquire("bot", &l, now, utc).await,
+            Err(BudgetExceeded::RequestsPerMinute { .. })
+        ));
+        assert_eq!(tracker.in_flight.get("bot").map(|c| c.load(Ordering::SeqCst)), Some(1));
+        drop(held);
+    }
+
+    #[tokio::test]
+    async fn test_token_budgets() {
+        let tracker = KeyBudgetTracker::in_memory();
+        let utc = Utc.with_ymd_and_hms(2026, 1, 31, 23, 0, 0).unwrap();
+        let now = Instant::now();
+        let daily = ApiKeyLimits { daily_tokens: Some(1000), ..limits() };
+
+        tracker.record_tokens("ci", 600, utc).await;
+        assert!(tracker.try_acquire("ci", &daily, now, utc).await.is_ok());
+        tracker.record_tokens("ci", 400, utc).await;
+        match tracker.try_acquire("ci", &daily, now, utc).await {
+            Err(BudgetExceeded::DailyTokens { retry_after_sec, .. }) => assert_eq!(retry_after_sec, 3600),
+            other => panic!("unexpected {:?}", other.map(|g| g.is_some())),
+        }
+        // 次日重新计数
+        let next_day = utc + ChronoDuration::hours(2);
+        assert!(tracker.try_acquire("ci", &daily, now, next_day).await.is_ok());
+
+        // 月度预算跨日累计
+        let monthly = ApiKeyLimits { monthly_tokens: Some(1500), ..limits() };
+        tracker.record_tokens("ci", 500, utc - ChronoDuration::days(1)).await;
+        assert!(matches!(
+            tracker.try_acquire("ci", &monthly, now, utc).await,
+            Err(BudgetExceeded::MonthlyTokens { retry_after_sec: 3600, .. })
+        ));
+    }
+}
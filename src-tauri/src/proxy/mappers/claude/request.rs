@@ -4,6 +4,7 @@
 use super::models::*;
 use crate::proxy::mappers::signature_store::get_thought_signature; // Deprecated, kept for fallback
 use crate::proxy::mappers::tool_result_compressor;
+use crate::proxy::model_registry::{ModelDescriptor, ModelRegistry};
 use crate::proxy::session_manager::SessionManager;
 use serde_json::{json, Value};
 use std::collections::HashMap;
@@ -309,10 +310,10 @@ pub fn transform_claude_request_in(
         });
 
     // [NEW FIX] Check if target model supports thinking
-    // Only models with "-thinking" suffix or Claude models support thinking
+    // 以模型能力注册表为准 (Claude 模型、Gemini 3 Pro 与 -thinking 模型)
     // Regular Gemini models (gemini-2.5-flash, gemini-2.5-pro) do NOT support thinking
-    let target_model_supports_thinking = mapped_model.contains("-thinking") 
-        || mapped_model.starts_with("claude-");
+    let target_model = ModelRegistry::global().get(&mapped_model);
+    let target_model_supports_thinking = target_model.thinking;
     
     if is_thinking_enabled && !target_model_supports_thinking {
         tracing::warn!(
@@ -382,7 +383,7 @@ pub fn transform_claude_request_in(
     }
 
     // 4. Generation Config & Thinking (Pass final is_thinking_enabled)
-    let generation_config = build_generation_config(claude_req, has_web_search_tool, is_thinking_enabled);
+    let generation_config = build_generation_config(claude_req, &target_model, is_thinking_enabled);
 
     // 2. Contents (Messages)
     let contents = build_contents(
@@ -514,23 +515,14 @@ fn should_disable_thinking_due_to_history(messages: &[Message]) -> bool {
 /// This function determines if the model should have thinking enabled
 /// when no explicit thinking configuration is provided.
 fn should_enable_thinking_by_default(model: &str) -> bool {
-    let model_lower = model.to_lowercase();
-
-    // Enable thinking by default for Opus 4.5 variants
-    if model_lower.contains("opus-4-5") || model_lower.contains("opus-4.5") {
-        tracing::debug!(
-            "[Thinking-Mode] Auto-enabling thinking for Opus 4.5 model: {}",
-            model
-        );
-        return true;
+    // 注册表中声明了默认思考预算的 Claude 模型 (Opus 4.5 等) 与显式的 -thinking 变体
+    // gemini-3-pro 的默认预算仅用于 OpenAI 协议，Claude 协议下需客户端显式开启
+    let descriptor = ModelRegistry::global().get(model);
+    let enabled = descriptor.thinking_by_default() && (descriptor.is_claude() || model.contains("-thinking"));
+    if enabled {
+        tracing::debug!("[Thinking-Mode] Auto-enabling thinking for model: {}", model);
     }
-
-    // Also enable for explicit thinking model variants
-    if model_lower.contains("-thinking") {
-        return true;
-    }
-
-    false
+    enabled
 }
 
 /// Minimum length for a valid thought_signature
@@ -1184,7 +1176,7 @@ fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option
 /// 构建 Generation Config
 fn build_generation_config(
     claude_req: &ClaudeRequest,
-    has_web_search: bool,
+    target_model: &ModelDescriptor,
     is_thinking_enabled: bool
 ) -> Value {
     let mut config = json!({});
@@ -1196,13 +1188,8 @@ fn build_generation_config(
             let mut thinking_config = json!({"includeThoughts": true});
 
             if let Some(budget_tokens) = thinking.budget_tokens {
-                let mut budget = budget_tokens;
-                // gemini-2.5-flash 上限 24576
-                let is_flash_model =
-                    has_web_search || claude_req.model.contains("gemini-2.5-flash");
-                if is_flash_model {
-                    budget = budget.min(24576);
-                }
+                // 按目标模型上限裁剪 (gemini-2.5-flash 上限 24576)
+                let budget = target_model.clamp_thinking_budget(budget_tokens);
                 thinking_config["thinkingBudget"] = json!(budget);
             }
 
@@ -1244,8 +1231,8 @@ fn build_generation_config(
         config["candidateCount"] = json!(1);
     }*/
 
-    // max_tokens 映射为 maxOutputTokens
-    config["maxOutputTokens"] = json!(64000);
+    // maxOutputTokens 取目标模型的输出上限
+    config["maxOutputTokens"] = json!(target_model.max_output_tokens);
 
     // [优化] 设置全局停止序列,防止流式输出冗余
     config["stopSequences"] = json!([
@@ -1307,6 +1294,35 @@ mod tests {
     use super::*;
     use crate::proxy::common::json_schema::clean_json_schema;
 
+    #[test]
+    fn test_default_thinking_and_max_output_follow_registry() {
+        assert!(should_enable_thinking_by_default("claude-opus-4-5-thinking"));
+        assert!(should_enable_thinking_by_default("gemini-2.5-flash-thinking"));
+        assert!(should_enable_thinking_by_default("claude-sonnet-4-thinking"));
+        assert!(!should_enable_thinking_by_default("claude-sonnet-4-5"));
+        // gemini-3-pro 支持思考，但 Claude 协议下不默认开启
+        assert!(!should_enable_thinking_by_default("gemini-3-pro-high"));
+
+        let req = ClaudeRequest {
+            model: "claude-sonnet-4-5".to_string(),
+            messages: vec![],
+            system: None,
+            tools: None,
+            stream: false,
+            max_tokens: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            thinking: None,
+            metadata: None,
+            output_config: None,
+        };
+        for (model, max_output) in [("gemini-2.0-flash", 8192), ("gemini-3-pro-high", 65536), ("claude-sonnet-4-5", 64000)] {
+            let config = build_generation_config(&req, &ModelRegistry::global().get(model), false);
+            assert_eq!(config["maxOutputTokens"], max_output, "{}", model);
+        }
+    }
+
     #[test]
     fn test_simple_request() {
         let req = ClaudeRequest {
@@ -3,6 +3,11 @@
 
 use serde_json::{json, Value};
 
+use crate::proxy::model_registry::ModelRegistry;
+
+/// 联网请求降级使用的模型
+const WEB_SEARCH_MODEL: &str = "gemini-2.5-flash";
+
 /// Request configuration after grounding resolution
 #[derive(Debug, Clone)]
 pub struct RequestConfig {
@@ -21,8 +26,10 @@ pub fn resolve_request_config(
     mapped_model: &str,
     tools: &Option<Vec<Value>>
 ) -> RequestConfig {
+    let registry = ModelRegistry::global();
+
     // 1. Image Generation Check (Priority)
-    if mapped_model.starts_with("gemini-3-pro-image") {
+    if registry.get(mapped_model).image_output {
         let (image_config, parsed_base_model) = parse_image_config(original_model);
         
         return RequestConfig {
@@ -41,19 +48,6 @@ pub fn resolve_request_config(
     // Strip -online suffix from original model if present (to detect networking intent)
     let is_online_suffix = original_model.ends_with("-online");
     
-    // High-quality grounding allowlist (Only for models known to support search and be relatively 'safe')
-    let _is_high_quality_model = mapped_model == "gemini-2.5-flash"
-        || mapped_model == "gemini-1.5-pro"
-        || mapped_model.starts_with("gemini-1.5-pro-")
-        || mapped_model.starts_with("gemini-2.5-flash-")
-        || mapped_model.starts_with("gemini-2.0-flash")
-        || mapped_model.starts_with("gemini-3-")
-        || mapped_model.contains("claude-3-5-sonnet")
-        || mapped_model.contains("claude-3-opus")
-        || mapped_model.contains("claude-sonnet")
-        || mapped_model.contains("claude-opus")
-        || mapped_model.contains("claude-4");
-
     // Determine if we should enable networking
     // [FIX] 禁用基于模型的自动联网逻辑，防止图像请求被联网搜索结果覆盖。
     // 仅在用户显式请求联网时启用：1) -online 后缀 2) 携带联网工具定义
@@ -64,14 +58,14 @@ pub fn resolve_request_config(
     // Force a stable search model for search requests.
     let mut final_model = mapped_model.trim_end_matches("-online").to_string();
     if enable_networking {
-        // [FIX] Only gemini-2.5-flash supports googleSearch tool
-        // All other models (including Gemini 3 Pro, thinking models, Claude aliases) must downgrade
-        if final_model != "gemini-2.5-flash" {
+        // [FIX] 只有注册表中声明 search 的模型 (默认仅 gemini-2.5-flash) 支持 googleSearch 工具
+        // 其它模型 (包括 Gemini 3 Pro、思考模型、Claude 别名) 必须降级
+        if !registry.get(&final_model).search {
             tracing::info!(
-                "[Common-Utils] Downgrading {} to gemini-2.5-flash for web search (only gemini-2.5-flash supports googleSearch)",
-                final_model
+                "[Common-Utils] Downgrading {} to {} for web search (model does not support googleSearch)",
+                final_model, WEB_SEARCH_MODEL
             );
-            final_model = "gemini-2.5-flash".to_string();
+            final_model = WEB_SEARCH_MODEL.to_string();
         }
     }
 
@@ -2,6 +2,7 @@
 use super::models::*;
 use serde_json::{json, Value};
 use super::streaming::get_thought_signature;
+use crate::proxy::model_registry::ModelRegistry;
 
 pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mapped_model: &str) -> Value {
     // 将 OpenAI 工具转为 Value 数组以便探测
@@ -218,15 +219,12 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     let contents = merged_contents;
 
     // 3. 构建请求体
-    // [FIX PR #368] 检测 Gemini 3 Pro thinking 模型，注入 thinkingBudget 配置
-    // 加入 Claude thinking 模型支援
-    let is_gemini_3_thinking = mapped_model.contains("gemini-3") && 
-        (mapped_model.ends_with("-high") || mapped_model.ends_with("-low") || mapped_model.contains("-pro"));
-    let is_claude_thinking = mapped_model.ends_with("-thinking");
-    let is_thinking_model = is_gemini_3_thinking || is_claude_thinking;
+    // [FIX PR #368] 检测 thinking 模型 (Gemini 3 Pro、Claude/Gemini -thinking)，注入 thinkingBudget 配置
+    // 以模型能力注册表中的默认思考预算为准
+    let target_model = ModelRegistry::global().get(mapped_model);
 
     let mut gen_config = json!({
-        "maxOutputTokens": request.max_tokens.unwrap_or(64000),
+        "maxOutputTokens": request.max_tokens.unwrap_or(target_model.max_output_tokens),
         "temperature": request.temperature.unwrap_or(1.0),
         "topP": request.top_p.unwrap_or(1.0), 
     });
@@ -237,12 +235,12 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     }
 
     // [FIX PR #368] 为 thinking 模型注入 thinkingConfig (使用 thinkingBudget 而非 thinkingLevel)
-    if is_thinking_model {
+    if let Some(budget) = target_model.default_thinking() {
         gen_config["thinkingConfig"] = json!({
             "includeThoughts": true,
-            "thinkingBudget": 16000
+            "thinkingBudget": budget
         });
-        tracing::debug!("[OpenAI-Request] Injected thinkingConfig for model {}: thinkingBudget=16000", mapped_model);
+        tracing::debug!("[OpenAI-Request] Injected thinkingConfig for model {}: thinkingBudget={}", mapped_model, budget);
     }
 
 
@@ -0,0 +1,318 @@
+// Prometheus 指标
+// 请求数/延迟直方图/token 计数由 monitor_middleware 在请求结束时写入，
+// 账号池、限流锁定与签名缓存等状态类指标在抓取时实时计算
+use dashmap::DashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::SignatureCache;
+use crate::proxy::TokenManager;
+
+/// 延迟直方图桶 (秒)，覆盖普通请求到长时间思考流
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RequestLabels {
+    route: String,
+    model: String,
+    mapped_model: String,
+    account: String,
+    status: u16,
+}
+
+#[derive(Debug, Default)]
+struct RequestStats {
+    count: u64,
+    sum_seconds: f64,
+    /// 非累计计数，导出时再累加
+    buckets: [u64; LATENCY_BUCKETS.len()],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TokenLabels {
+    direction: &'static str,
+    model: String,
+    mapped_model: String,
+    account: String,
+}
+
+pub struct ProxyMetrics {
+    enabled: AtomicBool,
+    requests: DashMap<RequestLabels, RequestStats>,
+    tokens: DashMap<TokenLabels, u64>,
+}
+
+impl ProxyMetrics {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+            requests: DashMap::new(),
+            tokens: DashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 记录一次已完成的请求 (流式请求在流结束时调用)
+    pub fn record(&self, route: &str, log: &ProxyRequestLog, elapsed_seconds: f64) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let model = log.model.clone().unwrap_or_default();
+        let mapped_model = log.mapped_model.clone().unwrap_or_default();
+        let account = log.account_email.clone().unwrap_or_default();
+
+        {
+            let mut stats = self
+                .requests
+                .entry(RequestLabels {
+                    route: route.to_string(),
+                    model: model.clone(),
+                    mapped_model: mapped_model.clone(),
+                    account: account.clone(),
+                    status: log.status,
+                })
+                .or_default();
+            stats.count += 1;
+            stats.sum_seconds += elapsed_seconds;
+            if let Some(idx) = LATENCY_BUCKETS.iter().position(|b| elapsed_seconds <= *b) {
+                stats.buckets[idx] += 1;
+            }
+        }
+
+        for (direction, tokens) in [("input", log.input_tokens), ("output", log.output_tokens)] {
+            if let Some(tokens) = tokens.filter(|t| *t > 0) {
+                *self
+                    .tokens
+                    .entry(TokenLabels {
+                        direction,
+                        model: model.clone(),
+                        mapped_model: mapped_model.clone(),
+                        account: account.clone(),
+                    })
+                    .or_insert(0) += tokens as u64;
+            }
+        }
+    }
+
+    /// 导出 Prometheus 文本格式
+    pub fn render(&self, token_manager: &TokenManager) -> String {
+        let mut out = String::new();
+
+        // 请求计数
+        out.push_str("# HELP antigravity_proxy_requests_total Total proxied requests.\n");
+        out.push_str("# TYPE antigravity_proxy_requests_total counter\n");
+        for entry in self.requests.iter() {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_requests_total{{{}}} {}",
+                request_labels(entry.key()),
+                entry.value().count
+            );
+        }
+
+        // 延迟直方图
+        out.push_str("# HELP antigravity_proxy_request_duration_seconds Request duration including streamed body.\n");
+        out.push_str("# TYPE antigravity_proxy_request_duration_seconds histogram\n");
+        for entry in self.requests.iter() {
+            let labels = request_labels(entry.key());
+            let stats = entry.value();
+            let mut cumulative = 0;
+            for (bucket, count) in LATENCY_BUCKETS.iter().zip(stats.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "antigravity_proxy_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bucket, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, stats.count
+            );
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_request_duration_seconds_sum{{{}}} {}",
+                labels, stats.sum_seconds
+            );
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_request_duration_seconds_count{{{}}} {}",
+                labels, stats.count
+            );
+        }
+
+        // token 计数
+        out.push_str("# HELP antigravity_proxy_tokens_total Tokens reported by upstream usage metadata.\n");
+        out.push_str("# TYPE antigravity_proxy_tokens_total counter\n");
+        for entry in self.tokens.iter() {
+            let key = entry.key();
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_tokens_total{{direction=\"{}\",model=\"{}\",mapped_model=\"{}\",account=\"{}\"}} {}",
+                key.direction,
+                escape_label(&key.model),
+                escape_label(&key.mapped_model),
+                escape_label(&key.account),
+                entry.value()
+            );
+        }
+
+        // 账号池
+        out.push_str("# HELP antigravity_proxy_pool_accounts Accounts loaded into the token pool.\n");
+        out.push_str("# TYPE antigravity_proxy_pool_accounts gauge\n");
+        let _ = writeln!(out, "antigravity_proxy_pool_accounts {}", token_manager.len());
+        out.push_str("# HELP antigravity_proxy_available_accounts Accounts in the pool that are not rate limited.\n");
+        out.push_str("# TYPE antigravity_proxy_available_accounts gauge\n");
+        let _ = writeln!(
+            out,
+            "antigravity_proxy_available_accounts {}",
+            token_manager.available_count()
+        );
+
+        // 限流锁定
+        out.push_str("# HELP antigravity_proxy_rate_limit_lockout_seconds Remaining lockout per rate-limited account and model (empty model = whole account).\n");
+        out.push_str("# TYPE antigravity_proxy_rate_limit_lockout_seconds gauge\n");
+        for (account, model, remaining) in token_manager.active_lockouts() {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_rate_limit_lockout_seconds{{account=\"{}\",model=\"{}\"}} {}",
+                escape_label(&account),
+                escape_label(model.as_deref().unwrap_or_default()),
+                remaining
+            );
+        }
+
+        // 熔断器 (仅输出非关闭状态的账号)
+        out.push_str("# HELP antigravity_proxy_circuit_breaker_state Accounts whose circuit breaker is open or half-open (value is always 1).\n");
+        out.push_str("# TYPE antigravity_proxy_circuit_breaker_state gauge\n");
+        for (account, state) in token_manager.breaker_states() {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_circuit_breaker_state{{account=\"{}\",state=\"{}\"}} 1",
+                escape_label(&account),
+                state.as_str()
+            );
+        }
+
+        // 准入排队
+        let queue = token_manager.admission_stats();
+        out.push_str("# HELP antigravity_proxy_queue_depth Requests waiting because every account is rate limited.\n");
+        out.push_str("# TYPE antigravity_proxy_queue_depth gauge\n");
+        let _ = writeln!(out, "antigravity_proxy_queue_depth {}", queue.depth);
+        out.push_str("# HELP antigravity_proxy_queue_eta_seconds Expected wait until the earliest lockout expires (0 when the queue is empty).\n");
+        out.push_str("# TYPE antigravity_proxy_queue_eta_seconds gauge\n");
+        let _ = writeln!(out, "antigravity_proxy_queue_eta_seconds {}", queue.eta_seconds);
+        out.push_str("# HELP antigravity_proxy_queue_admitted_total Queued requests that got an account.\n");
+        out.push_str("# TYPE antigravity_proxy_queue_admitted_total counter\n");
+        let _ = writeln!(out, "antigravity_proxy_queue_admitted_total {}", queue.admitted);
+        out.push_str("# HELP antigravity_proxy_queue_rejected_total Requests rejected with 429 by the admission queue.\n");
+        out.push_str("# TYPE antigravity_proxy_queue_rejected_total counter\n");
+        for (reason, count) in [("full", queue.rejected_full), ("timeout", queue.rejected_timeout)] {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_queue_rejected_total{{reason=\"{}\"}} {}",
+                reason, count
+            );
+        }
+
+        // 签名缓存
+        let (tool, family, session) = SignatureCache::global().sizes();
+        out.push_str("# HELP antigravity_proxy_signature_cache_entries Entries in each signature cache layer.\n");
+        out.push_str("# TYPE antigravity_proxy_signature_cache_entries gauge\n");
+        for (layer, size) in [("tool", tool), ("thinking_family", family), ("session", session)] {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_signature_cache_entries{{layer=\"{}\"}} {}",
+                layer, size
+            );
+        }
+
+        out
+    }
+}
+
+fn request_labels(labels: &RequestLabels) -> String {
+    format!(
+        "route=\"{}\",model=\"{}\",mapped_model=\"{}\",account=\"{}\",status=\"{}\"",
+        escape_label(&labels.route),
+        escape_label(&labels.model),
+        escape_label(&labels.mapped_model),
+        escape_label(&labels.account),
+        labels.status
+    )
+}
+
+/// Prometheus 标签值转义
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(status: u16, input: Option<u32>, output: Option<u32>) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: "1".to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status,
+            duration: 0,
+            model: Some("claude-sonnet-4-5".to_string()),
+            mapped_model: Some("claude-sonnet-4-5-thinking".to_string()),
+            account_email: Some("a@example.com".to_string()),
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: input,
+            output_tokens: output,
+            api_key_name: None,
+            scheduling_trace: None,
+        }
+    }
+
+    #[test]
+    fn test_render_counters_and_histogram() {
+        let metrics = ProxyMetrics::new(true);
+        metrics.record("/v1/messages", &log(200, Some(10), Some(5)), 0.3);
+        metrics.record("/v1/messages", &log(200, Some(1), None), 3.0);
+
+        let tm = TokenManager::new(std::env::temp_dir());
+        let text = metrics.render(&tm);
+
+        let labels = "route=\"/v1/messages\",model=\"claude-sonnet-4-5\",mapped_model=\"claude-sonnet-4-5-thinking\",account=\"a@example.com\",status=\"200\"";
+        assert!(text.contains(&format!("antigravity_proxy_requests_total{{{}}} 2", labels)));
+        assert!(text.contains(&format!("antigravity_proxy_request_duration_seconds_bucket{{{},le=\"0.5\"}} 1", labels)));
+        assert!(text.contains(&format!("antigravity_proxy_request_duration_seconds_bucket{{{},le=\"5\"}} 2", labels)));
+        assert!(text.contains("antigravity_proxy_tokens_total{direction=\"input\",model=\"claude-sonnet-4-5\",mapped_model=\"claude-sonnet-4-5-thinking\",account=\"a@example.com\"} 11"));
+        assert!(text.contains("antigravity_proxy_pool_accounts 0"));
+    }
+
+    #[test]
+    fn test_disabled_metrics_are_not_recorded() {
+        let metrics = ProxyMetrics::new(false);
+        metrics.record("/v1/messages", &log(200, Some(10), Some(5)), 0.3);
+        assert!(metrics.requests.is_empty());
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
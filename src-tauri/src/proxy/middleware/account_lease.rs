@@ -0,0 +1,176 @@
+// 账号占用中间件
+// 为 handler 建立请求作用域，get_token 选中的账号在响应体 (含流式输出) 发送结束前计入进行中请求
+// 同时记录调用方与协议路由，供 get_token 匹配账号分组路由规则，并收集选号决策记录
+// CacheFirst 模式下 get_token 等待绑定账号解除限流时，提前向流式客户端返回响应并发送保活数据
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::proxy::security::{scope_for_path, ApiKeyIdentity};
+use crate::proxy::scheduling_trace::SchedulingTraceSlot;
+use crate::proxy::server::AppState;
+use crate::proxy::sticky_config::SchedulingMode;
+use crate::proxy::token_manager::{
+    scope_account_lease, scope_request_route, scope_scheduling_trace, scope_sticky_wait, AccountLease,
+    RequestRoute,
+};
+
+const MAX_REQUEST_BODY_SIZE: usize = 100 * 1024 * 1024; // 与 DefaultBodyLimit 一致
+/// SSE 注释行，各协议的客户端解析时均会忽略
+const KEEPALIVE_CHUNK: &[u8] = b": keepalive\n\n";
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct StreamFlag {
+    #[serde(default)]
+    stream: bool,
+}
+
+pub async fn account_lease_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = RequestRoute {
+        api_key: request.extensions().get::<ApiKeyIdentity>().map(|id| id.name.clone()),
+        route: scope_for_path(request.uri().path()),
+    };
+    // 由监控中间件放入，未开启监控时不记录
+    let trace = request.extensions().get::<SchedulingTraceSlot>().cloned();
+    let cache_first = state.token_manager.get_sticky_config().await.mode == SchedulingMode::CacheFirst;
+    let (request, wants_stream) = if cache_first && may_stream(&request) {
+        peek_stream_flag(request).await
+    } else {
+        (request, false)
+    };
+    if !wants_stream {
+        let (response, lease) =
+            scope_account_lease(scope_scheduling_trace(trace, scope_request_route(route, next.run(request)))).await;
+        return hold_lease(response, lease);
+    }
+
+    let (notify, mut waits) = tokio::sync::mpsc::unbounded_channel();
+    let mut handler = Box::pin(scope_account_lease(scope_sticky_wait(
+        notify,
+        scope_scheduling_trace(trace, scope_request_route(route, next.run(request))),
+    )));
+    let wait = tokio::select! {
+        (response, lease) = &mut handler => return hold_lease(response, lease),
+        Some(wait) = waits.recv() => wait,
+    };
+    tracing::debug!(
+        "Cache First: sending keepalives while waiting {}s for {} ({})",
+        wait.wait_seconds, wait.email, wait.model
+    );
+
+    // 响应头已提前发出，handler 最终失败时以 SSE error 事件返回错误
+    // 账号与模型在 handler 完成前可能变化 (重试换号、降级)，不放入响应头，监控日志从选号决策记录中取
+    let stream = async_stream::stream! {
+        let mut ticker = tokio::time::interval(KEEPALIVE_INTERVAL);
+        let (response, lease) = loop {
+            tokio::select! {
+                output = &mut handler => break output,
+                _ = ticker.tick() => yield Ok(Bytes::from_static(KEEPALIVE_CHUNK)),
+            }
+        };
+        let _lease = lease;
+
+        let status = response.status();
+        if status.is_success() {
+            let mut body = response.into_body().into_data_stream();
+            while let Some(chunk) = body.next().await {
+                yield chunk;
+            }
+        } else {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap_or_default();
+            yield Ok::<Bytes, axum::Error>(Bytes::from(error_event(status, &bytes)));
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::CONNECTION, "keep-alive")
+        .body(Body::from_stream(stream))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// 客户端断开时响应体被丢弃，占用随之释放
+fn hold_lease(response: Response, lease: Option<AccountLease>) -> Response {
+    let Some(lease) = lease else {
+        return response;
+    };
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &lease;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// 只有对话类 POST 路由可能返回流式输出，其余路由 (管理 API、上传等) 不读取请求体
+fn may_stream(request: &Request) -> bool {
+    let path = request.uri().path();
+    request.method() == axum::http::Method::POST
+        && (matches!(path, "/v1/chat/completions" | "/v1/completions" | "/v1/responses" | "/v1/messages")
+            || path.contains("streamGenerateContent"))
+}
+
+/// 判断客户端是否请求流式输出 (Gemini 看路径，其余看请求体的 stream 字段)
+async fn peek_stream_flag(request: Request) -> (Request, bool) {
+    if request.uri().path().contains("streamGenerateContent") {
+        return (request, true);
+    }
+
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, MAX_REQUEST_BODY_SIZE).await {
+        Ok(bytes) => {
+            let stream = serde_json::from_slice::<StreamFlag>(&bytes)
+                .map(|f| f.stream)
+                .unwrap_or(false);
+            (Request::from_parts(parts, Body::from(bytes)), stream)
+        }
+        Err(_) => (Request::from_parts(parts, Body::empty()), false),
+    }
+}
+
+/// 将 handler 的错误响应转换为 SSE error 事件
+fn error_event(status: StatusCode, body: &[u8]) -> String {
+    let data = match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(value) => value,
+        Err(_) => json!({
+            "error": {
+                "code": status.as_u16(),
+                "message": String::from_utf8_lossy(body),
+            }
+        }),
+    };
+    format!("event: error\ndata: {}\n\n", data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_event_wraps_plain_text() {
+        let event = error_event(StatusCode::SERVICE_UNAVAILABLE, b"Token error: busy");
+        assert!(event.starts_with("event: error\ndata: {"));
+        assert!(event.contains("\"code\":503"));
+        assert!(event.ends_with("\n\n"));
+
+        let event = error_event(StatusCode::TOO_MANY_REQUESTS, b"{\"type\":\"error\"}");
+        assert_eq!(event, "event: error\ndata: {\"type\":\"error\"}\n\n");
+    }
+}
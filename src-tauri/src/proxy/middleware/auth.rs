@@ -9,12 +9,14 @@ use axum::{
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::proxy::common::client_error::{self, ApiProtocol};
+use crate::proxy::security::{KeyCheck, UnixSocketConnection};
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
 /// API Key 认证中间件
 pub async fn auth_middleware(
     State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
     let method = request.method().clone();
@@ -33,7 +35,31 @@ pub async fn auth_middleware(
     }
 
     let security = security.read().await.clone();
-    let effective_mode = security.effective_auth_mode();
+
+    // 管理 API 使用独立的 admin_key，不受 auth_mode 影响
+    if is_admin_path(&path) {
+        if security.admin_key.is_empty() {
+            // 未配置 admin_key 时管理 API 视为关闭
+            return Err(StatusCode::NOT_FOUND);
+        }
+        let admin_key = request
+            .headers()
+            .get("x-admin-key")
+            .and_then(|h| h.to_str().ok())
+            .or_else(|| extract_bearer(&request));
+        return if admin_key == Some(security.admin_key.as_str()) {
+            Ok(next.run(request).await)
+        } else {
+            Err(StatusCode::UNAUTHORIZED)
+        };
+    }
+
+    // socket 连接使用独立的鉴权策略
+    let effective_mode = if request.extensions().get::<UnixSocketConnection>().is_some() {
+        security.effective_unix_socket_auth_mode()
+    } else {
+        security.effective_auth_mode()
+    };
 
     if matches!(effective_mode, ProxyAuthMode::Off) {
         return Ok(next.run(request).await);
@@ -44,40 +70,73 @@ pub async fn auth_middleware(
     }
     
     // 从 header 中提取 API key
-    let api_key = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
+    let api_key = extract_bearer(&request)
         .or_else(|| {
             request
                 .headers()
                 .get("x-api-key")
                 .and_then(|h| h.to_str().ok())
-        });
+        })
+        .unwrap_or("")
+        .to_string();
 
-    if security.api_key.is_empty() {
-        tracing::error!("Proxy auth is enabled but api_key is empty; denying request");
+    if !security.has_any_key() {
+        tracing::error!("Proxy auth is enabled but no api_key is configured; denying request");
         return Err(StatusCode::UNAUTHORIZED);
     }
 
     // Constant-time compare is unnecessary here, but keep strict equality and avoid leaking values.
-    let authorized = api_key.map(|k| k == security.api_key).unwrap_or(false);
-
-    if authorized {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
+    match security.authenticate(&api_key, &path, chrono::Utc::now().timestamp()) {
+        KeyCheck::Granted(identity) => {
+            // 下游 handler 与监控中间件通过 extensions 获取调用方身份
+            request.extensions_mut().insert(identity);
+            Ok(next.run(request).await)
+        }
+        KeyCheck::ScopeDenied { name, scope } => {
+            tracing::warn!("[Auth] API key '{}' is not allowed to access {:?} routes", name, scope);
+            Ok(client_error::error_response(
+                ApiProtocol::from_path(&path),
+                StatusCode::FORBIDDEN,
+                &format!("API key '{}' is not allowed to access {:?} endpoints", name, scope),
+            ))
+        }
+        KeyCheck::InternalDenied { name } => {
+            tracing::warn!("[Auth] API key '{}' is not allowed to access internal routes", name);
+            Err(StatusCode::FORBIDDEN)
+        }
+        KeyCheck::Rejected => Err(StatusCode::UNAUTHORIZED),
     }
 }
 
+/// 是否为管理 API 路径 (`/admin` 及其子路径)
+pub fn is_admin_path(path: &str) -> bool {
+    path == "/admin" || path.starts_with("/admin/")
+}
+
+/// 从 Authorization 头中提取密钥 (兼容不带 Bearer 前缀的写法)
+fn extract_bearer(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
+}
+
 #[cfg(test)]
 mod tests {
-    // 移除未使用的 use super::*;
+    use super::*;
 
     #[test]
     fn test_auth_placeholder() {
         // Placeholder test
         assert!(true);
     }
+
+    #[test]
+    fn test_is_admin_path() {
+        assert!(is_admin_path("/admin"));
+        assert!(is_admin_path("/admin/accounts"));
+        assert!(!is_admin_path("/administrator"));
+        assert!(!is_admin_path("/v1/admin/accounts"));
+    }
 }
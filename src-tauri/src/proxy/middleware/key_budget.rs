@@ -0,0 +1,64 @@
+// API Key 用量预算中间件
+// 位于 auth_middleware 之后，根据 ApiKeyIdentity 中的 limits 拒绝超额请求
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+
+use crate::proxy::common::client_error::{self, ApiProtocol};
+use crate::proxy::security::{scope_for_path, ApiKeyIdentity};
+use crate::proxy::server::AppState;
+
+pub async fn key_budget_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(identity) = request.extensions().get::<ApiKeyIdentity>().cloned() else {
+        return next.run(request).await;
+    };
+
+    // 仅统计协议请求 (不计入心跳、遥测与健康检查)
+    let path = request.uri().path().to_string();
+    if identity.limits.is_unlimited() || scope_for_path(&path).is_none() {
+        return next.run(request).await;
+    }
+
+    let acquired = state
+        .key_budget
+        .try_acquire(
+            &identity.name,
+            &identity.limits,
+            std::time::Instant::now(),
+            chrono::Utc::now(),
+        )
+        .await;
+    match acquired {
+        Err(exceeded) => {
+            let retry_after = exceeded.retry_after_sec();
+            tracing::warn!(
+                "[KeyBudget] {} (retry after {}s)",
+                exceeded.message(&identity.name),
+                retry_after
+            );
+            client_error::rate_limit_response(
+                ApiProtocol::from_path(&path),
+                &exceeded.message(&identity.name),
+                retry_after,
+            )
+        }
+        Ok(None) => next.run(request).await,
+        Ok(Some(guard)) => {
+            // 并发占位需保持到响应体 (含流式输出) 发送结束
+            let (parts, body) = next.run(request).await.into_parts();
+            let stream = body.into_data_stream().map(move |chunk| {
+                let _ = &guard;
+                chunk
+            });
+            Response::from_parts(parts, Body::from_stream(stream))
+        }
+    }
+}
@@ -1,7 +1,9 @@
 // Middleware 模块 - Axum 中间件
 
+pub mod account_lease;
 pub mod auth;
 pub mod cors;
+pub mod key_budget;
 pub mod logging;
 pub mod monitor;
 
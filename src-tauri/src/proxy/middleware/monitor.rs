@@ -1,12 +1,15 @@
 use axum::{
-    extract::{Request, State},
+    extract::{MatchedPath, Request, State},
     middleware::Next,
     response::Response,
     body::Body,
 };
 use std::time::Instant;
 use crate::proxy::server::AppState;
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::key_budget::KeyBudgetTracker;
+use crate::proxy::metrics::ProxyMetrics;
+use crate::proxy::monitor::{ProxyMonitor, ProxyRequestLog};
+use crate::proxy::scheduling_trace::SchedulingTraceSlot;
 use serde_json::Value;
 use futures::StreamExt;
 
@@ -18,7 +21,15 @@ pub async fn monitor_middleware(
     request: Request,
     next: Next,
 ) -> Response {
-    if !state.monitor.is_enabled() {
+    // 配置了 token 预算的 API Key 即使关闭监控也需要统计用量
+    let api_key_name = request
+        .extensions()
+        .get::<crate::proxy::security::ApiKeyIdentity>()
+        .map(|identity| (identity.name.clone(), identity.limits.has_token_budget()));
+    let track_usage = api_key_name.as_ref().is_some_and(|(_, budget)| *budget);
+    let api_key_name = api_key_name.map(|(name, _)| name);
+
+    if !state.monitor.is_enabled() && !track_usage && !state.metrics.is_enabled() {
         return next.run(request).await;
     }
 
@@ -26,9 +37,20 @@ pub async fn monitor_middleware(
     let method = request.method().to_string();
     let uri = request.uri().to_string();
     
-    if uri.contains("event_logging") {
+    // 管理 API 的请求/响应包含账号凭据，不写入监控日志
+    if uri.contains("event_logging")
+        || request.uri().path() == "/metrics"
+        || crate::proxy::middleware::auth::is_admin_path(request.uri().path())
+    {
         return next.run(request).await;
     }
+
+    // 指标按路由模板聚合，避免 Gemini 路径中的模型名导致标签爆炸
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
     
     let mut model = if uri.contains("/v1beta/models/") {
         uri.split("/v1beta/models/")
@@ -39,6 +61,13 @@ pub async fn monitor_middleware(
         None
     };
 
+    // 选号决策记录由 account_lease 中间件在 handler 作用域内收集
+    let trace = SchedulingTraceSlot::default();
+    let mut request = request;
+    if state.monitor.is_enabled() {
+        request.extensions_mut().insert(trace.clone());
+    }
+
     let request_body_str;
     let request = if method == "POST" {
         let (parts, body) = request.into_parts();
@@ -91,6 +120,8 @@ pub async fn monitor_middleware(
         .map(|s| s.to_string());
 
     let monitor = state.monitor.clone();
+    let key_budget = state.key_budget.clone();
+    let metrics = state.metrics.clone();
     let mut log = ProxyRequestLog {
         id: uuid::Uuid::new_v4().to_string(),
         timestamp: chrono::Utc::now().timestamp_millis(),
@@ -106,6 +137,8 @@ pub async fn monitor_middleware(
         response_body: None,
         input_tokens: None,
         output_tokens: None,
+        api_key_name,
+        scheduling_trace: None,
     };
 
     if content_type.contains("text/event-stream") {
@@ -166,7 +199,7 @@ pub async fn monitor_middleware(
             if log.status >= 400 {
                 log.error = Some("Stream Error or Failed".to_string());
             }
-            monitor.log_request(log).await;
+            finish_request(&monitor, &key_budget, &metrics, &route, start, &trace, log).await;
         });
 
         Response::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
@@ -205,18 +238,49 @@ pub async fn monitor_middleware(
                 if log.status >= 400 {
                     log.error = log.response_body.clone();
                 }
-                monitor.log_request(log).await;
+                finish_request(&monitor, &key_budget, &metrics, &route, start, &trace, log).await;
                 Response::from_parts(parts, Body::from(bytes))
             }
             Err(_) => {
                 log.response_body = Some("[Response too large (>100MB)]".to_string());
-                monitor.log_request(log).await;
+                finish_request(&monitor, &key_budget, &metrics, &route, start, &trace, log).await;
                 Response::from_parts(parts, Body::empty())
             }
         }
     } else {
         log.response_body = Some(format!("[{}]", content_type));
-        monitor.log_request(log).await;
+        finish_request(&monitor, &key_budget, &metrics, &route, start, &trace, log).await;
         response
     }
 }
+
+/// 写入监控日志，并把 token 用量计入 API Key 预算与指标
+async fn finish_request(
+    monitor: &ProxyMonitor,
+    key_budget: &KeyBudgetTracker,
+    metrics: &ProxyMetrics,
+    route: &str,
+    start: Instant,
+    trace: &SchedulingTraceSlot,
+    mut log: ProxyRequestLog,
+) {
+    // CacheFirst 保活时 handler 晚于响应头完成，决策记录在请求结束时才收集
+    let decisions = trace.take();
+    // 保活响应的响应头早于选号发出，不含账号与模型，以最后一次选中的结果为准
+    if log.account_email.is_none() {
+        if let Some(last) = decisions.iter().rev().find(|d| d.selected.is_some()) {
+            log.account_email = last.selected.clone();
+            log.mapped_model.get_or_insert_with(|| last.target_model.clone());
+        }
+    }
+    if !decisions.is_empty() {
+        log.scheduling_trace = Some(decisions);
+    }
+    // 流式响应在流结束时才调用，耗时包含整个响应体
+    metrics.record(route, &log, start.elapsed().as_secs_f64());
+    if let Some(name) = log.api_key_name.as_deref() {
+        let tokens = log.input_tokens.unwrap_or(0) as u64 + log.output_tokens.unwrap_or(0) as u64;
+        key_budget.record_tokens(name, tokens, chrono::Utc::now()).await;
+    }
+    monitor.log_request(log).await;
+}
@@ -18,10 +18,18 @@ pub mod zai_vision_mcp;    // Built-in Vision MCP server state
 pub mod zai_vision_tools;  // Built-in Vision MCP tools (z.ai vision API)
 pub mod monitor;           // 监控
 pub mod rate_limit;        // 限流跟踪
+pub mod admission;         // 账号池全部限流时的准入排队
+pub mod circuit_breaker;   // 账号熔断与半开探测
+pub mod scheduler_state;   // 限流锁定与会话绑定持久化
+pub mod scheduling_trace;  // 选号调度决策记录
+pub mod key_budget;        // API Key 用量预算
+pub mod metrics;           // Prometheus 指标
+pub mod tls;               // 监听端 TLS
 pub mod sticky_config;     // 粘性调度配置
 pub mod session_manager;   // 会话指纹管理
 pub mod audio;             // 音频处理模块 (PR #311)
 pub mod signature_cache;   // Signature Cache (v3.3.16)
+pub mod model_registry;    // 模型能力注册表
 
 
 pub use config::ProxyConfig;
@@ -0,0 +1,250 @@
+// 模型能力注册表
+// 统一描述上游模型的上下文窗口、最大输出与能力 (思考/工具/视觉/音频/图片输出/联网)，
+// 供各协议 mapper、预热与 /models 端点查询；可通过 proxy.model_registry 覆盖或补充内置条目
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{OnceLock, RwLock};
+
+use crate::proxy::common::model_mapping::glob_match;
+
+const GEMINI_CONTEXT: u32 = 1_048_576;
+const GEMINI_OUTPUT: u32 = 65_536;
+const CLAUDE_CONTEXT: u32 = 200_000;
+const CLAUDE_OUTPUT: u32 = 64_000;
+/// 思考模型未指定预算时使用的默认值
+const DEFAULT_THINKING_BUDGET: u32 = 16_000;
+/// gemini-2.5-flash 系列的思考预算上限
+const FLASH_THINKING_BUDGET_LIMIT: u32 = 24_576;
+/// 未登记模型的默认上下文窗口与最大输出
+const UNKNOWN_CONTEXT: u32 = 128_000;
+const UNKNOWN_OUTPUT: u32 = 64_000;
+
+/// 模型描述
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelDescriptor {
+    /// 模型名 (映射后的上游名称)，支持 glob，如 `gemini-3-pro-image*`
+    pub id: String,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    #[serde(default)]
+    pub thinking: bool,
+    /// 客户端未指定时使用的思考预算，设置后默认开启思考
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_thinking_budget: Option<u32>,
+    /// 思考预算上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_thinking_budget: Option<u32>,
+    #[serde(default)]
+    pub tools: bool,
+    #[serde(default)]
+    pub vision: bool,
+    #[serde(default)]
+    pub audio: bool,
+    #[serde(default)]
+    pub image_output: bool,
+    /// 支持 googleSearch 联网
+    #[serde(default)]
+    pub search: bool,
+}
+
+impl ModelDescriptor {
+    fn new(id: &str, context_window: u32, max_output_tokens: u32) -> Self {
+        Self {
+            id: id.to_string(),
+            context_window,
+            max_output_tokens,
+            tools: true,
+            ..Default::default()
+        }
+    }
+
+    fn gemini(id: &str) -> Self {
+        Self {
+            vision: true,
+            audio: true,
+            ..Self::new(id, GEMINI_CONTEXT, GEMINI_OUTPUT)
+        }
+    }
+
+    fn claude(id: &str) -> Self {
+        Self {
+            thinking: true,
+            vision: true,
+            ..Self::new(id, CLAUDE_CONTEXT, CLAUDE_OUTPUT)
+        }
+    }
+
+    fn with_default_thinking(self) -> Self {
+        Self {
+            thinking: true,
+            default_thinking_budget: Some(DEFAULT_THINKING_BUDGET),
+            ..self
+        }
+    }
+
+    /// 默认开启思考时使用的预算 (客户端未携带思考配置时)
+    pub fn default_thinking(&self) -> Option<u32> {
+        self.default_thinking_budget.filter(|_| self.thinking)
+    }
+
+    pub fn thinking_by_default(&self) -> bool {
+        self.default_thinking().is_some()
+    }
+
+    /// 上游为 Claude 模型 (预热与 Claude 协议的默认思考据此区分)
+    /// 按 `get` 返回的 id (即查询的模型名) 判断，不受命中条目 glob 的影响
+    pub fn is_claude(&self) -> bool {
+        self.id.to_lowercase().contains("claude")
+    }
+
+    /// /models 端点返回的能力字段
+    pub fn capabilities(&self) -> Value {
+        json!({
+            "thinking": self.thinking,
+            "tools": self.tools,
+            "vision": self.vision,
+            "audio": self.audio,
+            "image_output": self.image_output,
+            "search": self.search,
+        })
+    }
+
+    /// 按模型上限裁剪思考预算
+    pub fn clamp_thinking_budget(&self, budget: u32) -> u32 {
+        self.max_thinking_budget.map_or(budget, |max| budget.min(max))
+    }
+}
+
+/// 内置条目，按顺序匹配，首条命中生效
+static BUILTIN_MODELS: Lazy<Vec<ModelDescriptor>> = Lazy::new(|| {
+    vec![
+        ModelDescriptor {
+            tools: false,
+            audio: false,
+            image_output: true,
+            ..ModelDescriptor::new("gemini-3-pro-image*", 65_536, 32_768)
+        },
+        ModelDescriptor::gemini("gemini-3-pro*").with_default_thinking(),
+        ModelDescriptor::gemini("gemini-3-flash*"),
+        ModelDescriptor {
+            max_thinking_budget: Some(FLASH_THINKING_BUDGET_LIMIT),
+            ..ModelDescriptor::gemini("gemini-2.5-flash-thinking").with_default_thinking()
+        },
+        ModelDescriptor {
+            search: true,
+            max_thinking_budget: Some(FLASH_THINKING_BUDGET_LIMIT),
+            ..ModelDescriptor::gemini("gemini-2.5-flash")
+        },
+        ModelDescriptor {
+            max_thinking_budget: Some(FLASH_THINKING_BUDGET_LIMIT),
+            ..ModelDescriptor::gemini("gemini-2.5-flash*")
+        },
+        ModelDescriptor::gemini("gemini-2.5-pro*"),
+        ModelDescriptor {
+            max_output_tokens: 8_192,
+            ..ModelDescriptor::gemini("gemini-2.0-flash*")
+        },
+        ModelDescriptor::claude("claude-sonnet-4-5"),
+        ModelDescriptor::claude("claude-sonnet-4-5-thinking").with_default_thinking(),
+        ModelDescriptor::claude("claude-opus-4-5*").with_default_thinking(),
+        ModelDescriptor::new("*thinking*", UNKNOWN_CONTEXT, UNKNOWN_OUTPUT).with_default_thinking(),
+        ModelDescriptor::gemini("gemini-*"),
+    ]
+});
+
+pub struct ModelRegistry {
+    /// 配置中的条目，优先于内置条目
+    custom: RwLock<Vec<ModelDescriptor>>,
+}
+
+impl ModelRegistry {
+    pub fn new(custom: Vec<ModelDescriptor>) -> Self {
+        Self { custom: RwLock::new(custom) }
+    }
+
+    /// 全局实例 (mapper 为无状态函数，通过全局实例查询)
+    pub fn global() -> &'static ModelRegistry {
+        static INSTANCE: OnceLock<ModelRegistry> = OnceLock::new();
+        INSTANCE.get_or_init(|| ModelRegistry::new(Vec::new()))
+    }
+
+    /// 替换配置中的条目 (服务启动与配置热更新时调用)
+    pub fn set_custom(&self, models: Vec<ModelDescriptor>) {
+        if let Ok(mut custom) = self.custom.write() {
+            *custom = models;
+        }
+    }
+
+    /// 查找已登记的模型 (配置条目优先)，未登记时返回 None
+    pub fn lookup(&self, model: &str) -> Option<ModelDescriptor> {
+        let custom = self.custom.read().ok()?;
+        custom
+            .iter()
+            .chain(BUILTIN_MODELS.iter())
+            .find(|d| glob_match(&d.id, model))
+            .cloned()
+    }
+
+    /// 模型描述 (id 为查询的模型名)，未登记的模型使用保守的默认值 (支持工具，无其它能力)
+    pub fn get(&self, model: &str) -> ModelDescriptor {
+        match self.lookup(model) {
+            Some(descriptor) => ModelDescriptor { id: model.to_string(), ..descriptor },
+            None => ModelDescriptor::new(model, UNKNOWN_CONTEXT, UNKNOWN_OUTPUT),
+        }
+    }
+}
+
+/// 校验配置中的模型描述
+pub fn validate_model_descriptors(models: &[ModelDescriptor]) -> Result<(), String> {
+    for (i, model) in models.iter().enumerate() {
+        if model.id.trim().is_empty() {
+            return Err(format!("model_registry[{}] 的 id 为空", i));
+        }
+        if model.context_window == 0 || model.max_output_tokens == 0 {
+            return Err(format!("model_registry[{}] ({}) 的 context_window 与 max_output_tokens 需大于 0", i, model.id));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_lookup_and_override() {
+        let registry = ModelRegistry::new(Vec::new());
+        let image = registry.get("gemini-3-pro-image-4k-16x9");
+        assert!(image.image_output && !image.thinking);
+        let pro = registry.get("gemini-3-pro-high");
+        assert!(pro.thinking_by_default() && !pro.image_output);
+        assert!(registry.get("gemini-2.5-flash").search);
+        assert!(!registry.get("gemini-2.5-flash-lite").search);
+        assert_eq!(registry.get("gemini-2.5-flash-lite").clamp_thinking_budget(32_000), 24_576);
+        assert!(registry.get("claude-sonnet-4-5").thinking);
+        assert!(!registry.get("claude-sonnet-4-5").thinking_by_default());
+        assert!(registry.get("claude-opus-4-5-thinking").thinking_by_default());
+        assert!(registry.get("claude-opus-4-5-thinking").is_claude() && !pro.is_claude());
+        // 只命中 *thinking* 条目的 Claude 模型
+        let sonnet4 = registry.get("claude-sonnet-4-thinking");
+        assert_eq!(sonnet4.id, "claude-sonnet-4-thinking");
+        assert!(sonnet4.is_claude() && sonnet4.thinking_by_default());
+
+        // 未登记的模型
+        assert!(registry.lookup("claude-3-opus-20240229").is_none());
+        let unknown = registry.get("claude-3-opus-20240229");
+        assert_eq!((unknown.context_window, unknown.thinking), (128_000, false));
+
+        // 配置条目优先于内置条目
+        registry.set_custom(vec![ModelDescriptor {
+            context_window: 2_000_000,
+            ..ModelDescriptor::gemini("gemini-3-pro-high")
+        }]);
+        assert_eq!(registry.get("gemini-3-pro-high").context_window, 2_000_000);
+        assert!(!registry.get("gemini-3-pro-high").thinking);
+        assert!(registry.get("gemini-3-pro-low").thinking);
+
+        assert!(validate_model_descriptors(&[ModelDescriptor::default()]).is_err());
+    }
+}
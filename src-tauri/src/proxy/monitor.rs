@@ -1,8 +1,9 @@
 use serde::{Serialize, Deserialize};
 use std::collections::VecDeque;
 use tokio::sync::RwLock;
-use tauri::Emitter;
+use crate::modules::events::SharedEventSink;
 use std::sync::atomic::{AtomicBool, Ordering};
+use crate::proxy::scheduling_trace::SchedulingDecision;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
@@ -20,6 +21,10 @@ pub struct ProxyRequestLog {
     pub response_body: Option<String>,
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    #[serde(default)]
+    pub api_key_name: Option<String>, // 发起请求的 API Key 名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduling_trace: Option<Vec<SchedulingDecision>>, // 选号决策记录 (仅日志详情返回)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -34,11 +39,11 @@ pub struct ProxyMonitor {
     pub stats: RwLock<ProxyStats>,
     pub max_logs: usize,
     pub enabled: AtomicBool,
-    app_handle: Option<tauri::AppHandle>,
+    event_sink: Option<SharedEventSink>,
 }
 
 impl ProxyMonitor {
-    pub fn new(max_logs: usize, app_handle: Option<tauri::AppHandle>) -> Self {
+    pub fn new(max_logs: usize, event_sink: Option<SharedEventSink>) -> Self {
         // Initialize DB
         if let Err(e) = crate::modules::proxy_db::init_db() {
             tracing::error!("Failed to initialize proxy DB: {}", e);
@@ -63,7 +68,7 @@ impl ProxyMonitor {
             stats: RwLock::new(ProxyStats::default()),
             max_logs,
             enabled: AtomicBool::new(false), // Default to disabled
-            app_handle,
+            event_sink,
         }
     }
 
@@ -109,7 +114,7 @@ impl ProxyMonitor {
         });
 
         // Emit event (send summary only, without body to reduce memory)
-        if let Some(app) = &self.app_handle {
+        if let Some(sink) = &self.event_sink {
             let log_summary = ProxyRequestLog {
                 id: log.id.clone(),
                 timestamp: log.timestamp,
@@ -125,8 +130,12 @@ impl ProxyMonitor {
                 response_body: None, // Don't send body in event
                 input_tokens: log.input_tokens,
                 output_tokens: log.output_tokens,
+                api_key_name: log.api_key_name.clone(),
+                scheduling_trace: None,
             };
-            let _ = app.emit("proxy://request", &log_summary);
+            if let Ok(payload) = serde_json::to_value(&log_summary) {
+                sink.emit("proxy://request", payload);
+            }
         }
     }
 
@@ -1,9 +1,10 @@
 use dashmap::DashMap;
-use std::time::{SystemTime, Duration};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
 use regex::Regex;
 
 /// 限流原因类型
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum RateLimitReason {
     /// 配额耗尽 (QUOTA_EXHAUSTED)
     QuotaExhausted,
@@ -34,15 +35,55 @@ pub struct RateLimitInfo {
     pub reason: RateLimitReason,
     /// 关联的模型 (用于模型级别限流)
     /// None 表示账号级别限流,Some(model) 表示特定模型限流
-    #[allow(dead_code)] // Used for model-level rate limiting
     pub model: Option<String>,
 }
 
+/// 持久化的锁定记录 (时间均为 Unix 秒)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersistedLockout {
+    pub account: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub reset_at: u64,
+    pub detected_at: u64,
+    pub retry_after_sec: u64,
+    pub reason: RateLimitReason,
+}
+
+/// 持久化的连续失败计数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersistedFailureCount {
+    pub account: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub count: u32,
+}
+
+fn to_unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// 锁定记录的键: (账号, 模型)，模型为 None 表示整个账号被锁定
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct LockoutKey {
+    account: String,
+    model: Option<String>,
+}
+
+impl LockoutKey {
+    fn new(account: &str, model: Option<&str>) -> Self {
+        Self {
+            account: account.to_string(),
+            model: model.map(|m| m.to_string()),
+        }
+    }
+}
+
 /// 限流跟踪器
 pub struct RateLimitTracker {
-    limits: DashMap<String, RateLimitInfo>,
-    /// 连续失败计数（用于智能指数退避）
-    failure_counts: DashMap<String, u32>,
+    limits: DashMap<LockoutKey, RateLimitInfo>,
+    /// 连续失败计数（用于智能指数退避），与锁定记录使用相同的键
+    failure_counts: DashMap<LockoutKey, u32>,
 }
 
 impl RateLimitTracker {
@@ -53,27 +94,30 @@ impl RateLimitTracker {
         }
     }
     
-    /// 获取账号剩余的等待时间(秒)
-    pub fn get_remaining_wait(&self, account_id: &str) -> u64 {
-        if let Some(info) = self.limits.get(account_id) {
-            let now = SystemTime::now();
-            if info.reset_time > now {
-                return info.reset_time.duration_since(now).unwrap_or(Duration::from_secs(0)).as_secs();
-            }
-        }
-        0
+    /// 获取账号(对指定模型)剩余的等待时间(秒)
+    /// 账号级别锁定与该模型的锁定取较长者；model 为 None 时只看账号级别锁定
+    #[allow(dead_code)]
+    pub fn get_remaining_wait(&self, account_id: &str, model: Option<&str>) -> u64 {
+        self.get_reset_seconds(account_id, model).unwrap_or(0)
     }
     
     /// 标记账号请求成功，重置连续失败计数
     /// 
     /// 当账号成功完成请求后调用此方法，将其失败计数归零，
     /// 这样下次失败时会从最短的锁定时间（60秒）开始。
-    pub fn mark_success(&self, account_id: &str) {
-        if self.failure_counts.remove(account_id).is_some() {
-            tracing::debug!("账号 {} 请求成功，已重置失败计数", account_id);
+    /// 传入 model 时同时清除该模型的锁定，其它模型的锁定保持不变。
+    pub fn mark_success(&self, account_id: &str, model: Option<&str>) {
+        let mut keys = vec![LockoutKey::new(account_id, None)];
+        if model.is_some() {
+            keys.push(LockoutKey::new(account_id, model));
+        }
+        for key in &keys {
+            if self.failure_counts.remove(key).is_some() {
+                tracing::debug!("账号 {} 请求成功，已重置失败计数", account_id);
+            }
+            // 同时清除限流记录（如果有）
+            self.limits.remove(key);
         }
-        // 同时清除限流记录（如果有）
-        self.limits.remove(account_id);
     }
     
     /// 精确锁定账号到指定时间点
@@ -98,7 +142,7 @@ impl RateLimitTracker {
             model: model.clone(),  // 🆕 支持模型级别限流
         };
         
-        self.limits.insert(account_id.to_string(), info);
+        self.limits.insert(LockoutKey::new(account_id, model.as_deref()), info);
         
         if let Some(m) = &model {
             tracing::info!(
@@ -192,7 +236,10 @@ impl RateLimitTracker {
             None => {
                 // 获取连续失败次数，用于指数退避
                 let failure_count = {
-                    let mut count = self.failure_counts.entry(account_id.to_string()).or_insert(0);
+                    let mut count = self
+                        .failure_counts
+                        .entry(LockoutKey::new(account_id, model.as_deref()))
+                        .or_insert(0);
                     *count += 1;
                     *count
                 };
@@ -246,6 +293,7 @@ impl RateLimitTracker {
             }
         };
         
+        let key = LockoutKey::new(account_id, model.as_deref());
         let info = RateLimitInfo {
             reset_time: SystemTime::now() + Duration::from_secs(retry_sec),
             retry_after_sec: retry_sec,
@@ -254,14 +302,15 @@ impl RateLimitTracker {
             model,
         };
         
-        // 存储
-        self.limits.insert(account_id.to_string(), info.clone());
+        // 存储 (已知模型时只锁定该模型)
+        self.limits.insert(key, info.clone());
         
         tracing::warn!(
-            "账号 {} [{}] 限流类型: {:?}, 重置延时: {}秒",
+            "账号 {} [{}] 限流类型: {:?}, 模型: {}, 重置延时: {}秒",
             account_id,
             status,
             reason,
+            info.model.as_deref().unwrap_or("*"),
             retry_sec
         );
         
@@ -438,32 +487,116 @@ impl RateLimitTracker {
         None
     }
     
-    /// 获取账号的限流信息
-    pub fn get(&self, account_id: &str) -> Option<RateLimitInfo> {
-        self.limits.get(account_id).map(|r| r.clone())
+    /// 获取账号(对指定模型)生效中的限流信息
+    /// 账号级别锁定与该模型的锁定同时存在时返回较晚解除的一条
+    pub fn get(&self, account_id: &str, model: Option<&str>) -> Option<RateLimitInfo> {
+        let now = SystemTime::now();
+        let account_level = self.limits.get(&LockoutKey::new(account_id, None)).map(|r| r.clone());
+        let model_level = model.and_then(|_| {
+            self.limits
+                .get(&LockoutKey::new(account_id, model))
+                .map(|r| r.clone())
+        });
+        account_level
+            .into_iter()
+            .chain(model_level)
+            .filter(|info| info.reset_time > now)
+            .max_by_key(|info| info.reset_time)
     }
     
-    /// 检查账号是否仍在限流中
-    pub fn is_rate_limited(&self, account_id: &str) -> bool {
-        if let Some(info) = self.get(account_id) {
-            info.reset_time > SystemTime::now()
-        } else {
-            false
-        }
+    /// 检查账号(对指定模型)是否仍在限流中
+    pub fn is_rate_limited(&self, account_id: &str, model: Option<&str>) -> bool {
+        self.get(account_id, model).is_some()
     }
     
     /// 获取距离限流重置还有多少秒
-    pub fn get_reset_seconds(&self, account_id: &str) -> Option<u64> {
-        if let Some(info) = self.get(account_id) {
+    pub fn get_reset_seconds(&self, account_id: &str, model: Option<&str>) -> Option<u64> {
+        self.get(account_id, model).map(|info| {
             info.reset_time
                 .duration_since(SystemTime::now())
-                .ok()
                 .map(|d| d.as_secs())
-        } else {
-            None
-        }
+                .unwrap_or(0)
+        })
     }
     
+    /// 当前仍在生效的锁定 (账号, 模型, 剩余秒数)，供监控指标导出
+    pub fn active_lockouts(&self) -> Vec<(String, Option<String>, u64)> {
+        let now = SystemTime::now();
+        self.limits
+            .iter()
+            .filter_map(|entry| {
+                let key = entry.key();
+                entry
+                    .value()
+                    .reset_time
+                    .duration_since(now)
+                    .ok()
+                    .map(|d| (key.account.clone(), key.model.clone(), d.as_secs()))
+            })
+            .collect()
+    }
+
+    /// 导出仍在生效的锁定与连续失败计数 (按键排序，便于比较快照是否变化)
+    pub fn export_state(&self) -> (Vec<PersistedLockout>, Vec<PersistedFailureCount>) {
+        let now = SystemTime::now();
+        let mut lockouts: Vec<PersistedLockout> = self
+            .limits
+            .iter()
+            .filter(|entry| entry.value().reset_time > now)
+            .map(|entry| {
+                let info = entry.value();
+                PersistedLockout {
+                    account: entry.key().account.clone(),
+                    model: entry.key().model.clone(),
+                    reset_at: to_unix_secs(info.reset_time),
+                    detected_at: to_unix_secs(info.detected_at),
+                    retry_after_sec: info.retry_after_sec,
+                    reason: info.reason,
+                }
+            })
+            .collect();
+        lockouts.sort_by(|a, b| (&a.account, &a.model).cmp(&(&b.account, &b.model)));
+
+        let mut failure_counts: Vec<PersistedFailureCount> = self
+            .failure_counts
+            .iter()
+            .map(|entry| PersistedFailureCount {
+                account: entry.key().account.clone(),
+                model: entry.key().model.clone(),
+                count: *entry.value(),
+            })
+            .collect();
+        failure_counts.sort_by(|a, b| (&a.account, &a.model).cmp(&(&b.account, &b.model)));
+
+        (lockouts, failure_counts)
+    }
+
+    /// 恢复持久化的状态，已过期的锁定被丢弃，内存中已有的记录优先
+    /// 返回恢复的锁定数
+    pub fn import_state(&self, lockouts: Vec<PersistedLockout>, failure_counts: Vec<PersistedFailureCount>) -> usize {
+        let now = to_unix_secs(SystemTime::now());
+        let mut restored = 0;
+        for lockout in lockouts.into_iter().filter(|l| l.reset_at > now) {
+            let key = LockoutKey::new(&lockout.account, lockout.model.as_deref());
+            self.limits.entry(key).or_insert_with(|| {
+                restored += 1;
+                RateLimitInfo {
+                    reset_time: UNIX_EPOCH + Duration::from_secs(lockout.reset_at),
+                    retry_after_sec: lockout.retry_after_sec,
+                    detected_at: UNIX_EPOCH + Duration::from_secs(lockout.detected_at),
+                    reason: lockout.reason,
+                    model: lockout.model.clone(),
+                }
+            });
+        }
+        for failure in failure_counts {
+            self.failure_counts
+                .entry(LockoutKey::new(&failure.account, failure.model.as_deref()))
+                .or_insert(failure.count);
+        }
+        restored
+    }
+
     /// 清除过期的限流记录
     #[allow(dead_code)]
     pub fn cleanup_expired(&self) -> usize {
@@ -486,20 +619,28 @@ impl RateLimitTracker {
         count
     }
     
-    /// 清除指定账号的限流记录
+    /// 清除指定账号的限流记录 (含所有模型级别锁定)
     #[allow(dead_code)]
     pub fn clear(&self, account_id: &str) -> bool {
-        self.limits.remove(account_id).is_some()
+        let before = self.limits.len();
+        self.limits.retain(|k, _| k.account != account_id);
+        self.limits.len() != before
     }
     
-    /// 清除所有限流记录 (乐观重置策略)
+    /// 清除账号级别与指定模型的限流记录 (乐观重置策略)
     /// 
     /// 用于乐观重置机制,当所有账号都被限流但等待时间很短时,
-    /// 清除所有限流记录以解决时序竞争条件
-    pub fn clear_all(&self) {
-        let count = self.limits.len();
-        self.limits.clear();
-        tracing::warn!("🔄 Optimistic reset: Cleared all {} rate limit record(s)", count);
+    /// 清除相关限流记录以解决时序竞争条件；其它模型的锁定 (如长时间的配额耗尽) 保持不变。
+    /// model 为 None 时只清除账号级别锁定
+    pub fn clear_for_model(&self, model: Option<&str>) {
+        let before = self.limits.len();
+        self.limits
+            .retain(|k, _| k.model.is_some() && k.model.as_deref() != model);
+        tracing::warn!(
+            "🔄 Optimistic reset: Cleared {} rate limit record(s) for model {:?}",
+            before - self.limits.len(),
+            model
+        );
     }
 }
 
@@ -551,7 +692,7 @@ mod tests {
     fn test_get_remaining_wait() {
         let tracker = RateLimitTracker::new();
         tracker.parse_from_error("acc1", 429, Some("30"), "", None);
-        let wait = tracker.get_remaining_wait("acc1");
+        let wait = tracker.get_remaining_wait("acc1", None);
         assert!(wait > 25 && wait <= 30);
     }
 
@@ -560,7 +701,7 @@ mod tests {
         let tracker = RateLimitTracker::new();
         // 如果 API 返回 1s，我们强制设为 2s
         tracker.parse_from_error("acc1", 429, Some("1"), "", None);
-        let wait = tracker.get_remaining_wait("acc1");
+        let wait = tracker.get_remaining_wait("acc1", None);
         // Due to time passing, it might be 1 or 2
         assert!(wait >= 1 && wait <= 2);
     }
@@ -574,4 +715,60 @@ mod tests {
         // 应该被识别为 RateLimitExceeded，而不是 QuotaExhausted
         assert_eq!(reason, RateLimitReason::RateLimitExceeded);
     }
+
+    #[test]
+    fn test_model_lockout_does_not_block_other_models() {
+        let tracker = RateLimitTracker::new();
+        let body = r#"{"error":{"details":[{"reason":"MODEL_CAPACITY_EXHAUSTED"}]}}"#;
+        tracker.parse_from_error("acc1", 429, Some("30"), body, Some("gemini-3-pro-high".to_string()));
+
+        assert!(tracker.is_rate_limited("acc1", Some("gemini-3-pro-high")));
+        assert!(!tracker.is_rate_limited("acc1", Some("claude-sonnet-4-5")));
+        assert!(!tracker.is_rate_limited("acc1", None));
+
+        // 账号级别锁定对所有模型生效
+        tracker.parse_from_error("acc1", 500, Some("60"), "", None);
+        assert!(tracker.is_rate_limited("acc1", Some("claude-sonnet-4-5")));
+        assert!(tracker.get_remaining_wait("acc1", Some("gemini-3-pro-high")) > 30);
+
+        // 成功只解除账号级别与该模型的锁定
+        tracker.parse_from_error("acc1", 429, Some("30"), body, Some("claude-sonnet-4-5".to_string()));
+        tracker.mark_success("acc1", Some("gemini-3-pro-high"));
+        assert!(!tracker.is_rate_limited("acc1", Some("gemini-3-pro-high")));
+        assert!(tracker.is_rate_limited("acc1", Some("claude-sonnet-4-5")));
+    }
+
+    #[test]
+    fn test_optimistic_reset_keeps_other_model_lockouts() {
+        let tracker = RateLimitTracker::new();
+        let capacity = r#"{"error":{"details":[{"reason":"MODEL_CAPACITY_EXHAUSTED"}]}}"#;
+        tracker.parse_from_error("acc1", 429, Some("2"), capacity, Some("claude-sonnet-4-5".to_string()));
+        tracker.parse_from_error("acc1", 429, None, "QUOTA_EXHAUSTED", Some("gemini-3-pro-high".to_string()));
+        tracker.parse_from_error("acc2", 500, Some("2"), "", None);
+
+        tracker.clear_for_model(Some("claude-sonnet-4-5"));
+        assert!(!tracker.is_rate_limited("acc1", Some("claude-sonnet-4-5")));
+        assert!(!tracker.is_rate_limited("acc2", Some("claude-sonnet-4-5")));
+        assert!(tracker.is_rate_limited("acc1", Some("gemini-3-pro-high")));
+    }
+
+    #[test]
+    fn test_export_import_skips_expired() {
+        let tracker = RateLimitTracker::new();
+        tracker.parse_from_error("acc1", 429, None, "QUOTA_EXHAUSTED", Some("claude-sonnet-4-5".to_string()));
+        let (mut lockouts, failures) = tracker.export_state();
+        assert_eq!(lockouts.len(), 1);
+        assert_eq!(failures[0].count, 1);
+
+        let mut expired = lockouts[0].clone();
+        expired.account = "acc2".to_string();
+        expired.reset_at = to_unix_secs(SystemTime::now()) - 1;
+        lockouts.push(expired);
+
+        let restored = RateLimitTracker::new();
+        assert_eq!(restored.import_state(lockouts, failures), 1);
+        assert!(restored.is_rate_limited("acc1", Some("claude-sonnet-4-5")));
+        assert!(!restored.is_rate_limited("acc2", Some("claude-sonnet-4-5")));
+        assert_eq!(restored.export_state().1[0].count, 1);
+    }
 }
@@ -0,0 +1,54 @@
+// 调度状态持久化
+// 限流锁定、连续失败计数与会话绑定写入数据目录，重启后恢复未过期的部分，
+// 避免重启后立即重新命中仍处于 QUOTA_EXHAUSTED 锁定中的账号
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::proxy::rate_limit::{PersistedFailureCount, PersistedLockout};
+
+const STATE_FILE: &str = "scheduler_state.json";
+
+/// 快照保存超过该时长后不再恢复连续失败计数 (与最长的退避锁定一致)
+pub const FAILURE_COUNT_TTL_SECS: i64 = 7200;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerSnapshot {
+    /// 保存时间 (Unix 秒)
+    #[serde(default)]
+    pub saved_at: i64,
+    #[serde(default)]
+    pub lockouts: Vec<PersistedLockout>,
+    #[serde(default)]
+    pub failure_counts: Vec<PersistedFailureCount>,
+    /// SessionID -> AccountID
+    #[serde(default)]
+    pub session_bindings: BTreeMap<String, String>,
+}
+
+fn state_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(STATE_FILE)
+}
+
+/// 读取快照，文件不存在或损坏时返回 None
+pub fn load(data_dir: &Path) -> Option<SchedulerSnapshot> {
+    let path = state_path(data_dir);
+    let content = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            tracing::warn!("调度状态文件 {} 解析失败，已忽略: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// 写入快照 (先写临时文件再重命名，避免中途退出留下半个文件)
+pub fn save(data_dir: &Path, snapshot: &SchedulerSnapshot) -> Result<(), String> {
+    let path = state_path(data_dir);
+    let tmp = path.with_extension("json.tmp");
+    let content = serde_json::to_string(snapshot).map_err(|e| format!("序列化调度状态失败: {}", e))?;
+    fs::write(&tmp, content).map_err(|e| format!("写入调度状态失败: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("保存调度状态失败: {}", e))
+}
@@ -0,0 +1,93 @@
+// 调度决策记录
+// get_token 每次选号生成一条结构化记录 (候选账号及跳过原因、选中规则、耗时)，
+// 由监控中间件写入 ProxyRequestLog，可通过日志详情或 /admin/logs/:id/scheduling 查询
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::proxy::sticky_config::SchedulingMode;
+
+/// 选中账号所依据的规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionRule {
+    /// 复用会话绑定的账号
+    StickySession,
+    /// 60 秒内复用上一次使用的账号
+    LastUsedWindow,
+    /// 轮询
+    RoundRobin,
+    /// 强制轮换 (重试换号)
+    ForceRotate,
+    /// 最少负载
+    LeastLoaded,
+    /// 全部限流时短暂等待后重新选择
+    BufferRetry,
+    /// 清除限流记录后选择
+    OptimisticReset,
+}
+
+/// 候选账号的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateStatus {
+    Selected,
+    /// 可用但未被选中
+    Available,
+    RateLimited,
+    /// 本次请求中已尝试失败 (如 token 刷新失败)
+    Attempted,
+    AtCapacity,
+    /// 不在路由规则指定的分组内
+    OutsideGroup,
+    CircuitOpen,
+    Disabled,
+    ProxyDisabled,
+    QuotaProtected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CandidateTrace {
+    pub email: String,
+    pub status: CandidateStatus,
+}
+
+/// 一次 get_token 调用的决策记录
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchedulingDecision {
+    pub quota_group: String,
+    pub target_model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub mode: SchedulingMode,
+    /// 路由规则解析出的账号分组
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub candidates: Vec<CandidateTrace>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selected: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<SelectionRule>,
+    /// 轮询的起始下标
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub round_robin_start: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 选号耗时 (含 CacheFirst 等待与排队)
+    pub duration_ms: u64,
+}
+
+/// 请求级别的决策记录收集槽，由监控中间件创建并放入 request extensions
+#[derive(Debug, Clone, Default)]
+pub struct SchedulingTraceSlot(Arc<Mutex<Vec<SchedulingDecision>>>);
+
+impl SchedulingTraceSlot {
+    pub fn push(&self, decision: SchedulingDecision) {
+        if let Ok(mut decisions) = self.0.lock() {
+            decisions.push(decision);
+        }
+    }
+
+    pub fn take(&self) -> Vec<SchedulingDecision> {
+        self.0.lock().map(|mut d| std::mem::take(&mut *d)).unwrap_or_default()
+    }
+}
@@ -1,10 +1,103 @@
-use crate::proxy::config::{ProxyAuthMode, ProxyConfig};
+use axum::{http::StatusCode, response::Response};
+
+use crate::proxy::common::client_error::{self, ApiProtocol};
+use crate::proxy::config::{ApiKeyConfig, ApiKeyLimits, ApiKeyScope, ProxyAuthMode, ProxyConfig};
+
+/// 旧版单一 api_key 在日志中显示的名称
+pub const DEFAULT_KEY_NAME: &str = "default";
 
 #[derive(Debug, Clone)]
 pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
     pub api_key: String,
+    pub admin_key: String,
+    pub api_keys: Vec<ApiKeyConfig>,
     pub allow_lan_access: bool,
+    /// Unix domain socket 连接使用的鉴权模式
+    pub unix_socket_auth_mode: ProxyAuthMode,
+}
+
+/// 请求来自 Unix domain socket 连接 (由监听端写入 request extensions)
+#[derive(Debug, Clone, Copy)]
+pub struct UnixSocketConnection;
+
+/// 通过认证的调用方身份 (由 auth_middleware 写入 request extensions)
+#[derive(Debug, Clone)]
+pub struct ApiKeyIdentity {
+    pub name: String,
+    pub allowed_models: Vec<String>,
+    pub limits: ApiKeyLimits,
+}
+
+impl ApiKeyIdentity {
+    /// 检查映射后的模型是否在白名单内 (白名单为空表示不限制)
+    pub fn allows_model(&self, mapped_model: &str) -> bool {
+        self.allowed_models.is_empty()
+            || self
+                .allowed_models
+                .iter()
+                .any(|p| crate::proxy::common::model_mapping::glob_match(p, mapped_model))
+    }
+}
+
+/// 模型白名单校验 (在 resolve_model_route 之后调用)
+/// identity 为 None (未开启鉴权) 时不做限制；不通过时返回协议对应的 403 响应
+pub fn model_access_denied(
+    identity: Option<&ApiKeyIdentity>,
+    protocol: ApiProtocol,
+    mapped_model: &str,
+) -> Option<Response> {
+    match identity {
+        Some(id) if !id.allows_model(mapped_model) => {
+            tracing::warn!("[Auth] API key '{}' is not allowed to use model {}", id.name, mapped_model);
+            Some(client_error::error_response(
+                protocol,
+                StatusCode::FORBIDDEN,
+                &format!("API key '{}' is not allowed to use model '{}'", id.name, mapped_model),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// API Key 校验结果
+#[derive(Debug)]
+pub enum KeyCheck {
+    Granted(ApiKeyIdentity),
+    /// 未知 key 或 key 已禁用/过期
+    Rejected,
+    /// key 有效但无权访问该协议
+    ScopeDenied { name: String, scope: ApiKeyScope },
+    /// 命名 key 无权访问内部端点 (/internal/*)
+    InternalDenied { name: String },
+}
+
+/// 是否为内部端点 (预热等，仅旧版 api_key 或未鉴权的本机调用可访问)
+pub fn is_internal_path(path: &str) -> bool {
+    path == "/internal" || path.starts_with("/internal/")
+}
+
+/// 根据请求路径判断所属协议范围 (None 表示不受 scope 限制的公共路由)
+pub fn scope_for_path(path: &str) -> Option<ApiKeyScope> {
+    if path.starts_with("/v1beta/") {
+        Some(ApiKeyScope::Gemini)
+    } else if path.starts_with("/mcp/") {
+        Some(ApiKeyScope::Mcp)
+    } else if path.starts_with("/v1/images/") {
+        Some(ApiKeyScope::Images)
+    } else if path.starts_with("/v1/audio/") {
+        Some(ApiKeyScope::Audio)
+    } else if path.starts_with("/v1/messages") || path == "/v1/models/claude" {
+        Some(ApiKeyScope::Claude)
+    } else if path == "/v1/chat/completions"
+        || path == "/v1/completions"
+        || path == "/v1/responses"
+        || path == "/v1/models"
+    {
+        Some(ApiKeyScope::Openai)
+    } else {
+        None
+    }
 }
 
 impl ProxySecurityConfig {
@@ -12,7 +105,18 @@ impl ProxySecurityConfig {
         Self {
             auth_mode: config.auth_mode.clone(),
             api_key: config.api_key.clone(),
+            admin_key: config.admin_key.clone(),
+            api_keys: config.api_keys.clone(),
             allow_lan_access: config.allow_lan_access,
+            unix_socket_auth_mode: config.unix_socket.auth_mode.clone(),
+        }
+    }
+
+    /// socket 连接的实际鉴权模式 (auto 视为 off，访问已由文件权限限制)
+    pub fn effective_unix_socket_auth_mode(&self) -> ProxyAuthMode {
+        match self.unix_socket_auth_mode {
+            ProxyAuthMode::Auto => ProxyAuthMode::Off,
+            ref other => other.clone(),
         }
     }
 
@@ -28,6 +132,61 @@ impl ProxySecurityConfig {
             ref other => other.clone(),
         }
     }
+
+    /// 是否配置了任何可用于认证的 key
+    pub fn has_any_key(&self) -> bool {
+        !self.api_key.is_empty() || !self.api_keys.is_empty()
+    }
+
+    /// 校验请求携带的 key
+    /// `now` 为当前 Unix 秒，用于判断过期
+    pub fn authenticate(&self, key: &str, path: &str, now: i64) -> KeyCheck {
+        if key.is_empty() {
+            return KeyCheck::Rejected;
+        }
+
+        // 旧版单一 api_key: 不受 scope / 模型限制
+        if !self.api_key.is_empty() && key == self.api_key {
+            return KeyCheck::Granted(ApiKeyIdentity {
+                name: DEFAULT_KEY_NAME.to_string(),
+                allowed_models: Vec::new(),
+                limits: ApiKeyLimits::default(),
+            });
+        }
+
+        let Some(entry) = self.api_keys.iter().find(|k| k.key == key) else {
+            return KeyCheck::Rejected;
+        };
+
+        if !entry.enabled {
+            tracing::warn!("[Auth] API key '{}' is disabled", entry.name);
+            return KeyCheck::Rejected;
+        }
+        if entry.expires_at.is_some_and(|exp| now >= exp) {
+            tracing::warn!("[Auth] API key '{}' has expired", entry.name);
+            return KeyCheck::Rejected;
+        }
+        // 内部端点可指定任意账号与模型，不对受限的命名 key 开放
+        if is_internal_path(path) {
+            return KeyCheck::InternalDenied {
+                name: entry.name.clone(),
+            };
+        }
+        if let Some(scope) = scope_for_path(path) {
+            if !entry.scopes.is_empty() && !entry.scopes.contains(&scope) {
+                return KeyCheck::ScopeDenied {
+                    name: entry.name.clone(),
+                    scope,
+                };
+            }
+        }
+
+        KeyCheck::Granted(ApiKeyIdentity {
+            name: entry.name.clone(),
+            allowed_models: entry.allowed_models.clone(),
+            limits: entry.limits.clone(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -39,7 +198,10 @@ mod tests {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
+            admin_key: String::new(),
+            api_keys: Vec::new(),
             allow_lan_access: false,
+            unix_socket_auth_mode: ProxyAuthMode::Off,
         };
         assert!(matches!(s.effective_auth_mode(), ProxyAuthMode::Off));
     }
@@ -49,12 +211,95 @@ mod tests {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
+            admin_key: String::new(),
+            api_keys: Vec::new(),
             allow_lan_access: true,
+            unix_socket_auth_mode: ProxyAuthMode::Auto,
         };
         assert!(matches!(
             s.effective_auth_mode(),
             ProxyAuthMode::AllExceptHealth
         ));
+        // socket 连接不受 allow_lan_access 影响
+        assert!(matches!(
+            s.effective_unix_socket_auth_mode(),
+            ProxyAuthMode::Off
+        ));
+    }
+
+    fn named_key(name: &str, key: &str) -> ApiKeyConfig {
+        ApiKeyConfig {
+            name: name.to_string(),
+            key: key.to_string(),
+            enabled: true,
+            expires_at: None,
+            scopes: Vec::new(),
+            allowed_models: Vec::new(),
+            limits: ApiKeyLimits::default(),
+        }
+    }
+
+    fn security_with(keys: Vec<ApiKeyConfig>) -> ProxySecurityConfig {
+        ProxySecurityConfig {
+            auth_mode: ProxyAuthMode::Strict,
+            api_key: "sk-legacy".to_string(),
+            admin_key: String::new(),
+            api_keys: keys,
+            allow_lan_access: false,
+            unix_socket_auth_mode: ProxyAuthMode::Off,
+        }
+    }
+
+    #[test]
+    fn legacy_key_is_unrestricted_default() {
+        let s = security_with(Vec::new());
+        match s.authenticate("sk-legacy", "/v1beta/models/x", 0) {
+            KeyCheck::Granted(id) => {
+                assert_eq!(id.name, DEFAULT_KEY_NAME);
+                assert!(id.allows_model("anything"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(s.authenticate("sk-other", "/v1/messages", 0), KeyCheck::Rejected));
     }
-}
 
+    #[test]
+    fn named_key_enabled_and_expiry() {
+        let mut disabled = named_key("ci", "sk-ci");
+        disabled.enabled = false;
+        let mut expiring = named_key("temp", "sk-temp");
+        expiring.expires_at = Some(1_000);
+        let s = security_with(vec![disabled, expiring]);
+
+        assert!(matches!(s.authenticate("sk-ci", "/v1/messages", 0), KeyCheck::Rejected));
+        assert!(matches!(s.authenticate("sk-temp", "/v1/messages", 999), KeyCheck::Granted(_)));
+        assert!(matches!(s.authenticate("sk-temp", "/v1/messages", 1_000), KeyCheck::Rejected));
+    }
+
+    #[test]
+    fn named_key_scopes_and_models() {
+        let mut key = named_key("alice", "sk-alice");
+        key.scopes = vec![ApiKeyScope::Claude];
+        key.allowed_models = vec!["claude-*".to_string()];
+        let s = security_with(vec![key]);
+
+        assert!(matches!(
+            s.authenticate("sk-alice", "/v1/chat/completions", 0),
+            KeyCheck::ScopeDenied { scope: ApiKeyScope::Openai, .. }
+        ));
+        // 不属于任何协议的公共路由不受 scope 限制
+        assert!(matches!(s.authenticate("sk-alice", "/v1/models/detect", 0), KeyCheck::Granted(_)));
+        // 内部端点只对旧版 api_key 开放
+        assert!(matches!(s.authenticate("sk-alice", "/internal/warmup", 0), KeyCheck::InternalDenied { .. }));
+        assert!(matches!(s.authenticate("sk-legacy", "/internal/warmup", 0), KeyCheck::Granted(_)));
+
+        match s.authenticate("sk-alice", "/v1/messages", 0) {
+            KeyCheck::Granted(id) => {
+                assert_eq!(id.name, "alice");
+                assert!(id.allows_model("claude-sonnet-4-5"));
+                assert!(!id.allows_model("gemini-3-pro-high"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
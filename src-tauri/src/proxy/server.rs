@@ -1,9 +1,10 @@
 use crate::proxy::TokenManager;
+use crate::proxy::common::model_mapping::ModelRouter;
 use axum::{
     extract::DefaultBodyLimit,
     http::StatusCode,
     response::{IntoResponse, Json, Response},
-    routing::{any, get, post},
+    routing::{any, delete, get, post, put},
     Router,
 };
 use std::sync::Arc;
@@ -13,11 +14,14 @@ use tracing::{debug, error};
 use tokio::sync::RwLock;
 use std::sync::atomic::AtomicUsize;
 
+/// TLS 握手超时，避免只建立 TCP 的客户端占住连接任务 (并拖住停机)
+const TLS_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Axum 应用状态
 #[derive(Clone)]
 pub struct AppState {
     pub token_manager: Arc<TokenManager>,
-    pub custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    pub custom_mapping: Arc<tokio::sync::RwLock<ModelRouter>>,
     #[allow(dead_code)]
     pub request_timeout: u64, // API 请求超时(秒)
     #[allow(dead_code)]
@@ -30,24 +34,38 @@ pub struct AppState {
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
     pub experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    pub key_budget: Arc<crate::proxy::key_budget::KeyBudgetTracker>,
+    pub metrics: Arc<crate::proxy::metrics::ProxyMetrics>,
+}
+
+/// 停机结果 (连接数)
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+pub struct ShutdownReport {
+    /// 在截止时间前自然结束的连接
+    pub drained: usize,
+    /// 超过截止时间被强制中断的连接
+    pub aborted: usize,
 }
 
 /// Axum 服务器实例
 pub struct AxumServer {
-    shutdown_tx: Option<oneshot::Sender<()>>,
-    custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    /// 发送停机信号，携带排空截止时间
+    shutdown_tx: Option<oneshot::Sender<std::time::Duration>>,
+    custom_mapping: Arc<tokio::sync::RwLock<ModelRouter>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
     experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    metrics: Arc<crate::proxy::metrics::ProxyMetrics>,
 }
 
 impl AxumServer {
     pub async fn update_mapping(&self, config: &crate::proxy::config::ProxyConfig) {
         {
             let mut m = self.custom_mapping.write().await;
-            *m = config.custom_mapping.clone();
+            *m = ModelRouter::from_config(config);
         }
+        crate::proxy::model_registry::ModelRegistry::global().set_custom(config.model_registry.clone());
         tracing::debug!("模型映射 (Custom) 已全量热更新");
     }
 
@@ -75,21 +93,28 @@ impl AxumServer {
         *exp = config.experimental.clone();
         tracing::info!("实验性配置已热更新");
     }
+
+    pub fn update_metrics(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.metrics.set_enabled(config.enable_metrics);
+        tracing::info!("指标端点开关已热更新: {}", config.enable_metrics);
+    }
     /// 启动 Axum 服务器
     pub async fn start(
         host: String,
         port: u16,
         token_manager: Arc<TokenManager>,
-        custom_mapping: std::collections::HashMap<String, String>,
+        model_router: ModelRouter,
         _request_timeout: u64,
         upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
         security_config: crate::proxy::ProxySecurityConfig,
         zai_config: crate::proxy::ZaiConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
         experimental_config: crate::proxy::config::ExperimentalConfig,
-
-    ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
-        let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
+        enable_metrics: bool,
+        tls_config: &crate::proxy::config::ProxyTlsConfig,
+        unix_socket_config: &crate::proxy::config::ProxyUnixSocketConfig,
+    ) -> Result<(Self, tokio::task::JoinHandle<ShutdownReport>), String> {
+        let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(model_router));
 	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
 	        let security_state = Arc::new(RwLock::new(security_config));
 	        let zai_state = Arc::new(RwLock::new(zai_config));
@@ -97,6 +122,7 @@ impl AxumServer {
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
 	        let experimental_state = Arc::new(RwLock::new(experimental_config));
+	        let metrics = Arc::new(crate::proxy::metrics::ProxyMetrics::new(enable_metrics));
 
 	        let state = AppState {
 	            token_manager: token_manager.clone(),
@@ -114,7 +140,13 @@ impl AxumServer {
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
             experimental: experimental_state.clone(),
+            key_budget: Arc::new(crate::proxy::key_budget::KeyBudgetTracker::new()),
+            metrics: metrics.clone(),
         };
+        // 熔断账号的半开探测
+        crate::proxy::circuit_breaker::spawn_prober(Arc::downgrade(&token_manager), state.upstream.clone());
+        // 后台主动刷新 access token
+        token_manager.spawn_token_refresher();
 
 
         // 构建路由 - 使用新架构的 handlers！
@@ -183,7 +215,69 @@ impl AxumServer {
             .route("/v1/api/event_logging/batch", post(silent_ok_handler))
             .route("/v1/api/event_logging", post(silent_ok_handler))
             .route("/healthz", get(health_check_handler))
+            .route("/metrics", get(metrics_handler))
+            // 管理 API (独立 admin_key 鉴权)
+            .route("/admin/status", get(handlers::admin::handle_status))
+            .route("/admin/stats", get(handlers::admin::handle_get_stats))
+            .route(
+                "/admin/accounts",
+                get(handlers::admin::handle_list_accounts).post(handlers::admin::handle_add_account),
+            )
+            .route(
+                "/admin/accounts/:account_id",
+                delete(handlers::admin::handle_delete_account),
+            )
+            .route(
+                "/admin/accounts/:account_id/quota",
+                post(handlers::admin::handle_refresh_account_quota),
+            )
+            .route(
+                "/admin/accounts/:account_id/tags",
+                put(handlers::admin::handle_update_account_tags),
+            )
+            .route(
+                "/admin/accounts/batch-delete",
+                post(handlers::admin::handle_delete_accounts),
+            )
+            .route(
+                "/admin/accounts/reorder",
+                post(handlers::admin::handle_reorder_accounts),
+            )
+            .route(
+                "/admin/accounts/refresh-quotas",
+                post(handlers::admin::handle_refresh_all_quotas),
+            )
+            .route(
+                "/admin/accounts/reload",
+                post(handlers::admin::handle_reload_accounts),
+            )
+            .route(
+                "/admin/model-mapping",
+                get(handlers::admin::handle_get_model_mapping)
+                    .put(handlers::admin::handle_update_model_mapping),
+            )
+            .route(
+                "/admin/scheduling",
+                get(handlers::admin::handle_get_scheduling)
+                    .put(handlers::admin::handle_update_scheduling),
+            )
+            .route(
+                "/admin/sessions",
+                delete(handlers::admin::handle_clear_session_bindings),
+            )
+            .route(
+                "/admin/logs",
+                get(handlers::admin::handle_get_logs).delete(handlers::admin::handle_clear_logs),
+            )
+            .route("/admin/logs/:log_id", get(handlers::admin::handle_get_log_detail))
+            .route(
+                "/admin/logs/:log_id/scheduling",
+                get(handlers::admin::handle_get_log_scheduling),
+            )
+            .route("/admin/monitor", put(handlers::admin::handle_set_monitor_enabled))
+            .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::account_lease::account_lease_middleware))
             .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
+            .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::key_budget::key_budget_middleware))
             .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
             .layer(TraceLayer::new_for_http())
             .layer(axum::middleware::from_fn_with_state(
@@ -193,16 +287,48 @@ impl AxumServer {
             .layer(crate::proxy::middleware::cors_layer())
             .with_state(state);
 
+        // TLS (证书问题在绑定端口前报错)
+        let tls_acceptor = if tls_config.enabled {
+            Some(tokio_rustls::TlsAcceptor::from(
+                crate::proxy::tls::build_server_config(tls_config)?,
+            ))
+        } else {
+            None
+        };
+
+        // Unix domain socket (先于 TCP 绑定，失败时不占用端口)
+        let socket = if unix_socket_config.enabled {
+            let path = unix_socket_config.socket_path()?;
+            let listener = unix_socket::bind(&path, unix_socket_config.parsed_mode()?)?;
+            tracing::info!("反代服务器监听 socket: {}", path.display());
+            Some((listener, path))
+        } else {
+            None
+        };
+        // socket 连接打上标记，auth_middleware 据此使用独立的鉴权策略
+        let socket_app = app
+            .clone()
+            .layer(axum::Extension(crate::proxy::security::UnixSocketConnection));
+
         // 绑定地址
-        let addr = format!("{}:{}", host, port);
-        let listener = tokio::net::TcpListener::bind(&addr)
-            .await
-            .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
+        let listener = if unix_socket_config.enabled && unix_socket_config.disable_tcp {
+            None
+        } else {
+            let addr = format!("{}:{}", host, port);
+            let listener = tokio::net::TcpListener::bind(&addr)
+                .await
+                .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
 
-        tracing::info!("反代服务器启动在 http://{}", addr);
+            if tls_acceptor.is_some() {
+                tracing::info!("反代服务器启动在 https://{} (HTTP/1.1, h2)", addr);
+            } else {
+                tracing::info!("反代服务器启动在 http://{} (HTTP/1.1, h2c)", addr);
+            }
+            Some(listener)
+        };
 
         // 创建关闭通道
-        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<std::time::Duration>();
 
         let server_instance = Self {
             shutdown_tx: Some(shutdown_tx),
@@ -211,56 +337,228 @@ impl AxumServer {
             security_state,
             zai_state,
             experimental: experimental_state.clone(),
+            metrics,
         };
 
         // 在新任务中启动服务器
         let handle = tokio::spawn(async move {
-            use hyper::server::conn::http1;
-            use hyper_util::rt::TokioIo;
-            use hyper_util::service::TowerToHyperService;
+            // 连接任务集合 + 优雅停机信号
+            let mut connections = tokio::task::JoinSet::new();
+            let graceful = hyper_util::server::graceful::GracefulShutdown::new();
 
-            loop {
+            let grace = loop {
                 tokio::select! {
-                    res = listener.accept() => {
+                    res = accept_tcp(listener.as_ref()) => {
                         match res {
-                            Ok((stream, _)) => {
-                                let io = TokioIo::new(stream);
-                                let service = TowerToHyperService::new(app.clone());
-
-                                tokio::task::spawn(async move {
-                                    if let Err(err) = http1::Builder::new()
-                                        .serve_connection(io, service)
-                                        .with_upgrades() // 支持 WebSocket (如果以后需要)
-                                        .await
-                                    {
-                                        debug!("连接处理结束或出错: {:?}", err);
+                            Ok((stream, peer)) => {
+                                let app = app.clone();
+                                let watcher = graceful.watcher();
+                                match tls_acceptor.clone() {
+                                    Some(acceptor) => {
+                                        connections.spawn(async move {
+                                            // 握手放在连接任务内，避免慢客户端阻塞 accept；超时关闭只建 TCP 不握手的连接
+                                            match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                                                Ok(Ok(tls_stream)) => serve_connection(tls_stream, app, watcher).await,
+                                                Ok(Err(e)) => debug!("TLS 握手失败 ({}): {:?}", peer, e),
+                                                Err(_) => debug!("TLS 握手超时 ({})", peer),
+                                            }
+                                        });
                                     }
-                                });
+                                    None => {
+                                        connections.spawn(serve_connection(stream, app, watcher));
+                                    }
+                                }
                             }
                             Err(e) => {
                                 error!("接收连接失败: {:?}", e);
                             }
                         }
                     }
-                    _ = &mut shutdown_rx => {
-                        tracing::info!("反代服务器停止监听");
-                        break;
+                    res = accept_unix(socket.as_ref().map(|(l, _)| l)) => {
+                        match res {
+                            // socket 仅供本机使用，不做 TLS
+                            Ok(stream) => {
+                                connections.spawn(serve_connection(stream, socket_app.clone(), graceful.watcher()));
+                            }
+                            Err(e) => {
+                                error!("接收 socket 连接失败: {:?}", e);
+                            }
+                        }
+                    }
+                    // 回收已结束的连接任务
+                    Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                    grace = &mut shutdown_rx => {
+                        break grace.unwrap_or_default();
                     }
                 }
+            };
+
+            // 停止接收新连接
+            drop(listener);
+            if let Some((unix_listener, path)) = socket {
+                drop(unix_listener);
+                let _ = std::fs::remove_file(&path);
             }
+            while connections.try_join_next().is_some() {}
+            let active = connections.len();
+            tracing::info!(
+                "反代服务器停止监听，等待 {} 个连接结束 (最长 {} 秒)",
+                active,
+                grace.as_secs()
+            );
+
+            // HTTP/1 在当前响应结束后关闭连接，HTTP/2 发送 GOAWAY，进行中的 SSE 流继续发送直到结束
+            let deadline = tokio::time::Instant::now() + grace;
+            let _ = tokio::time::timeout_at(deadline, graceful.shutdown()).await;
+            // 握手中的 TLS 连接不受 graceful 跟踪，同样只等到截止时间
+            let _ = tokio::time::timeout_at(deadline, async {
+                while connections.join_next().await.is_some() {}
+            })
+            .await;
+            while connections.try_join_next().is_some() {}
+            let aborted = connections.len();
+            connections.abort_all();
+            while connections.join_next().await.is_some() {}
+
+            let report = ShutdownReport {
+                drained: active.saturating_sub(aborted),
+                aborted,
+            };
+            tracing::info!(
+                "反代服务器已停止: {} 个连接正常结束, {} 个连接被中断",
+                report.drained,
+                report.aborted
+            );
+            report
         });
 
         Ok((server_instance, handle))
     }
 
     /// 停止服务器
-    pub fn stop(mut self) {
+    /// 立即停止接收新连接，已有连接最多等待 grace 后强制中断 (结果由 start 返回的 JoinHandle 给出)
+    pub fn stop(mut self, grace: std::time::Duration) {
         if let Some(tx) = self.shutdown_tx.take() {
-            let _ = tx.send(());
+            let _ = tx.send(grace);
+        }
+    }
+}
+
+async fn accept_tcp(
+    listener: Option<&tokio::net::TcpListener>,
+) -> std::io::Result<(tokio::net::TcpStream, std::net::SocketAddr)> {
+    match listener {
+        Some(l) => l.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn accept_unix(
+    listener: Option<&unix_socket::Listener>,
+) -> std::io::Result<unix_socket::Stream> {
+    match listener {
+        Some(l) => unix_socket::accept(l).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(unix)]
+mod unix_socket {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::path::Path;
+
+    pub type Listener = tokio::net::UnixListener;
+    pub type Stream = tokio::net::UnixStream;
+
+    /// 绑定 socket 并设置文件权限 (清理上次异常退出遗留的 socket 文件)
+    pub fn bind(path: &Path, mode: u32) -> Result<Listener, String> {
+        if let Ok(meta) = std::fs::symlink_metadata(path) {
+            if !meta.file_type().is_socket() {
+                return Err(format!("{} 已存在且不是 socket 文件", path.display()));
+            }
+            std::fs::remove_file(path)
+                .map_err(|e| format!("清理旧 socket {} 失败: {}", path.display(), e))?;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("创建 socket 目录失败: {}", e))?;
+        }
+
+        let listener = Listener::bind(path)
+            .map_err(|e| format!("socket {} 绑定失败: {}", path.display(), e))?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| format!("设置 socket 权限失败: {}", e))?;
+        Ok(listener)
+    }
+
+    pub async fn accept(listener: &Listener) -> std::io::Result<Stream> {
+        listener.accept().await.map(|(stream, _)| stream)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_bind_sets_mode_and_replaces_stale_socket() {
+            let dir = std::env::temp_dir().join(format!("ag-uds-{}", uuid::Uuid::new_v4().simple()));
+            let path = dir.join("proxy.sock");
+
+            let first = bind(&path, 0o660).unwrap();
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode, 0o660);
+
+            // 模拟异常退出后遗留的 socket 文件
+            drop(first);
+            assert!(bind(&path, 0o600).is_ok());
+
+            // 普通文件不会被覆盖
+            let regular = dir.join("not-a-socket");
+            std::fs::write(&regular, b"x").unwrap();
+            assert!(bind(&regular, 0o600).is_err());
+
+            let _ = std::fs::remove_dir_all(&dir);
         }
     }
 }
 
+#[cfg(not(unix))]
+mod unix_socket {
+    use std::path::Path;
+
+    pub enum Listener {}
+    pub type Stream = tokio::net::TcpStream;
+
+    pub fn bind(_path: &Path, _mode: u32) -> Result<Listener, String> {
+        Err("当前平台不支持 Unix domain socket".to_string())
+    }
+
+    pub async fn accept(listener: &Listener) -> std::io::Result<Stream> {
+        match *listener {}
+    }
+}
+
+/// 处理单个连接
+/// 自动识别 HTTP/1.1 与 HTTP/2 (明文 h2c prior knowledge / TLS ALPN)，HTTP/1 连接仍支持 Upgrade
+async fn serve_connection<I>(
+    io: I,
+    app: Router,
+    watcher: hyper_util::server::graceful::Watcher,
+) where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use hyper_util::server::conn::auto;
+    use hyper_util::service::TowerToHyperService;
+
+    let service = TowerToHyperService::new(app);
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let conn = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+    if let Err(err) = watcher.watch(conn).await {
+        debug!("连接处理结束或出错: {:?}", err);
+    }
+}
+
 // ===== API 处理器 (旧代码已移除，由 src/proxy/handlers/* 接管) =====
 
 /// 健康检查处理器
@@ -271,6 +569,21 @@ async fn health_check_handler() -> Response {
     .into_response()
 }
 
+/// Prometheus 指标 (未启用时返回 404)
+async fn metrics_handler(axum::extract::State(state): axum::extract::State<AppState>) -> Response {
+    if !state.metrics.is_enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        state.metrics.render(&state.token_manager),
+    )
+        .into_response()
+}
+
 /// 静默成功处理器 (用于拦截遥测日志等)
 async fn silent_ok_handler() -> Response {
     StatusCode::OK.into_response()
@@ -207,6 +207,18 @@ impl SignatureCache {
         None
     }
 
+    /// Entry counts of the three layers: (tool, thinking_family, session)
+    pub fn sizes(&self) -> (usize, usize, usize) {
+        let len = |cache: &Mutex<HashMap<String, CacheEntry<String>>>| {
+            cache.lock().map(|c| c.len()).unwrap_or(0)
+        };
+        (
+            len(&self.tool_signatures),
+            len(&self.thinking_families),
+            len(&self.session_signatures),
+        )
+    }
+
     /// Clear all caches (for testing or manual reset)
     #[allow(dead_code)] // Used in tests
     pub fn clear(&self) {
@@ -9,6 +9,15 @@ pub enum SchedulingMode {
     Balance,
     /// 性能优先 (Performance-first): 纯轮询模式 (Round-robin)，账号负载最均衡，但不利用缓存
     PerformanceFirst,
+    /// 最少负载 (Least-loaded): 不绑定会话，每次选择进行中请求最少的账号，适合大量并发流式请求
+    LeastLoaded,
+}
+
+impl SchedulingMode {
+    /// 是否使用会话粘性绑定
+    pub fn uses_sticky_sessions(&self) -> bool {
+        matches!(self, Self::CacheFirst | Self::Balance)
+    }
 }
 
 impl Default for SchedulingMode {
@@ -24,6 +33,37 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 单个账号的最大并发请求数 (0 表示不限制)，对所有调度模式生效
+    #[serde(default)]
+    pub max_concurrency_per_account: u32,
+    /// 账号池全部限流时允许排队等待的请求数 (0 表示不排队，直接返回 429；默认关闭)
+    #[serde(default = "default_queue_max_depth")]
+    pub queue_max_depth: u32,
+    /// 排队请求的最长等待时间 (秒)，最早的锁定解除时间超出该值时直接返回 429
+    #[serde(default = "default_queue_max_wait_seconds")]
+    pub queue_max_wait_seconds: u64,
+    /// 连续失败 (5xx/网络错误/空响应流) 多少次后熔断账号 (0 表示关闭熔断，默认关闭)
+    #[serde(default = "default_breaker_failure_threshold")]
+    pub breaker_failure_threshold: u32,
+    /// 熔断后的冷却时间 (秒)，半开探测失败时翻倍 (最长 600 秒)
+    #[serde(default = "default_breaker_open_seconds")]
+    pub breaker_open_seconds: u64,
+}
+
+fn default_queue_max_depth() -> u32 {
+    0
+}
+
+fn default_queue_max_wait_seconds() -> u64 {
+    60
+}
+
+fn default_breaker_failure_threshold() -> u32 {
+    0
+}
+
+fn default_breaker_open_seconds() -> u64 {
+    30
 }
 
 impl Default for StickySessionConfig {
@@ -31,6 +71,11 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            max_concurrency_per_account: 0,
+            queue_max_depth: default_queue_max_depth(),
+            queue_max_wait_seconds: default_queue_max_wait_seconds(),
+            breaker_failure_threshold: default_breaker_failure_threshold(),
+            breaker_open_seconds: default_breaker_open_seconds(),
         }
     }
 }
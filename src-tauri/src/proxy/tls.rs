@@ -0,0 +1,152 @@
+// 监听端 TLS (rustls)
+// 局域网模式下 API Key 与对话内容以明文经过办公网络，开启后在 AxumServer 内终止 TLS
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_rustls::rustls::{self, ServerConfig};
+
+use crate::proxy::config::ProxyTlsConfig;
+
+const SELF_SIGNED_DIR: &str = "tls";
+const SELF_SIGNED_CERT: &str = "proxy-cert.pem";
+const SELF_SIGNED_KEY: &str = "proxy-key.pem";
+/// 生成证书时使用的 SAN 列表 (每行一个)，配置变化后据此重新生成
+const SELF_SIGNED_SANS: &str = "proxy-cert.sans";
+
+/// 根据配置构建 rustls ServerConfig (ALPN: h2, http/1.1)
+pub fn build_server_config(tls: &ProxyTlsConfig) -> Result<Arc<ServerConfig>, String> {
+    let (cert_path, key_path) = match (tls.cert_path.as_deref(), tls.key_path.as_deref()) {
+        (Some(cert), Some(key)) if !cert.trim().is_empty() && !key.trim().is_empty() => {
+            (PathBuf::from(cert), PathBuf::from(key))
+        }
+        _ => {
+            let dir = crate::modules::account::get_data_dir()?.join(SELF_SIGNED_DIR);
+            ensure_self_signed(&dir, &tls.subject_alt_names)?
+        }
+    };
+    load_server_config(&cert_path, &key_path)
+}
+
+/// 读取 PEM 证书与私钥
+fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>, String> {
+    let cert_file = fs::File::open(cert_path)
+        .map_err(|e| format!("读取证书 {} 失败: {}", cert_path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("解析证书 {} 失败: {}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("证书文件 {} 中没有证书", cert_path.display()));
+    }
+
+    let key_file = fs::File::open(key_path)
+        .map_err(|e| format!("读取私钥 {} 失败: {}", key_path.display(), e))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+        .map_err(|e| format!("解析私钥 {} 失败: {}", key_path.display(), e))?
+        .ok_or_else(|| format!("私钥文件 {} 中没有私钥", key_path.display()))?;
+
+    // 显式指定 ring，避免依赖进程级默认 CryptoProvider
+    let mut config = ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| format!("TLS 协议配置失败: {}", e))?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .map_err(|e| format!("证书与私钥不匹配: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+/// 自签名证书的 SAN 列表 (固定的本机地址 + 配置中的附加项，去重保序)
+fn self_signed_sans(extra_sans: &[String]) -> Vec<String> {
+    let mut sans = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        "::1".to_string(),
+    ];
+    for san in extra_sans {
+        let san = san.trim();
+        if !san.is_empty() && !sans.iter().any(|s| s == san) {
+            sans.push(san.to_string());
+        }
+    }
+    sans
+}
+
+/// 确保自签名证书存在且 SAN 与配置一致 (持久化复用，SAN 变化或删除文件后重新生成)
+fn ensure_self_signed(dir: &Path, extra_sans: &[String]) -> Result<(PathBuf, PathBuf), String> {
+    let cert_path = dir.join(SELF_SIGNED_CERT);
+    let key_path = dir.join(SELF_SIGNED_KEY);
+    let sans_path = dir.join(SELF_SIGNED_SANS);
+    let sans = self_signed_sans(extra_sans);
+    let sans_text = sans.join("\n");
+
+    if cert_path.exists() && key_path.exists() {
+        match fs::read_to_string(&sans_path) {
+            Ok(existing) if existing.trim() == sans_text => return Ok((cert_path, key_path)),
+            _ => tracing::info!("自签名证书的 SAN 与配置不一致，重新生成"),
+        }
+    }
+
+    let certified = rcgen::generate_simple_self_signed(sans)
+        .map_err(|e| format!("生成自签名证书失败: {}", e))?;
+
+    fs::create_dir_all(dir).map_err(|e| format!("创建证书目录失败: {}", e))?;
+    write_private_key(&key_path, &certified.key_pair.serialize_pem())
+        .map_err(|e| format!("写入私钥失败: {}", e))?;
+    fs::write(&cert_path, certified.cert.pem()).map_err(|e| format!("写入证书失败: {}", e))?;
+    fs::write(&sans_path, sans_text).map_err(|e| format!("写入证书 SAN 失败: {}", e))?;
+
+    tracing::info!("已生成自签名证书: {}", cert_path.display());
+    Ok((cert_path, key_path))
+}
+
+/// 写入私钥 (Unix 下创建时即为 0600，不留可读窗口)
+fn write_private_key(path: &Path, pem: &str) -> std::io::Result<()> {
+    use std::io::Write;
+
+    // 旧文件可能带有更宽的权限，先删除再以新权限创建
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(pem.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_signed_is_reused_until_sans_change() {
+        let dir = std::env::temp_dir().join(format!("ag-tls-{}", uuid::Uuid::new_v4()));
+        let (cert, key) = ensure_self_signed(&dir, &["192.168.1.10".to_string()]).unwrap();
+        let first = fs::read_to_string(&cert).unwrap();
+
+        // SAN 不变时复用已有证书
+        ensure_self_signed(&dir, &[" 192.168.1.10 ".to_string()]).unwrap();
+        assert_eq!(fs::read_to_string(&cert).unwrap(), first);
+
+        // SAN 变化后重新生成
+        ensure_self_signed(&dir, &[]).unwrap();
+        assert_ne!(fs::read_to_string(&cert).unwrap(), first);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&key).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let config = load_server_config(&cert, &key).unwrap();
+        assert_eq!(config.alpn_protocols[0], b"h2".to_vec());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
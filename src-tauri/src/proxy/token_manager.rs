@@ -1,11 +1,18 @@
 // 移除冗余的顶层导入，因为这些在代码中已由 full path 或局部导入处理
 use dashmap::DashMap;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
 
+use crate::models::quota::ModelQuota;
+use crate::proxy::config::{AccountRoutingRule, ApiKeyScope, TokenRefreshConfig};
+use crate::proxy::admission::{AdmissionQueue, AdmissionStats, QueueTicket};
+use crate::proxy::circuit_breaker::{BreakerState, CircuitBreaker};
 use crate::proxy::rate_limit::RateLimitTracker;
+use crate::proxy::scheduler_state::{self, SchedulerSnapshot};
+use crate::proxy::scheduling_trace::{CandidateStatus, CandidateTrace, SchedulingDecision, SchedulingTraceSlot, SelectionRule};
 use crate::proxy::sticky_config::StickySessionConfig;
 
 #[derive(Debug, Clone)]
@@ -20,8 +27,119 @@ pub struct ProxyToken {
     pub project_id: Option<String>,
     pub subscription_tier: Option<String>, // "FREE" | "PRO" | "ULTRA"
     pub remaining_quota: Option<i32>, // [FIX #563] Remaining quota for priority sorting
+    pub model_quotas: Vec<ModelQuota>, // 按模型的剩余配额 (来自 quota.models)
+    pub tags: Vec<String>, // 账号标签 (路由规则中的分组)
 }
 
+impl ProxyToken {
+    /// 目标模型的剩余配额百分比
+    /// 优先精确匹配模型名，其次取同系列 (claude/gemini) 中最低的一项；
+    /// 有按模型数据但无匹配项时返回 None，避免其它系列的满额配额抬高排序
+    pub fn quota_for_model(&self, model: &str) -> Option<i32> {
+        if model.is_empty() || self.model_quotas.is_empty() {
+            return self.remaining_quota;
+        }
+        let model = model.to_lowercase();
+        if let Some(q) = self.model_quotas.iter().find(|q| q.name.eq_ignore_ascii_case(&model)) {
+            return Some(q.percentage);
+        }
+        let family = ["claude", "gemini"].into_iter().find(|f| model.starts_with(f))?;
+        self.model_quotas
+            .iter()
+            .filter(|q| q.name.to_lowercase().starts_with(family))
+            .map(|q| q.percentage)
+            .min()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+}
+
+/// 账号占用 (进行中请求计数)，Drop 时释放
+/// 由 account_lease_middleware 保持到响应体 (含流式输出) 发送结束或连接被中断
+pub struct AccountLease {
+    counter: Arc<AtomicU32>,
+}
+
+impl Drop for AccountLease {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// CacheFirst 模式下 get_token 开始等待绑定账号解除限流
+/// account_lease_middleware 收到后向流式客户端发送保活数据
+#[derive(Debug, Clone)]
+pub struct StickyWait {
+    pub email: String,
+    pub model: String,
+    pub wait_seconds: u64,
+}
+
+/// 当前请求的调用方与协议路由，用于匹配账号分组路由规则
+#[derive(Debug, Clone, Default)]
+pub struct RequestRoute {
+    pub api_key: Option<String>,
+    pub route: Option<ApiKeyScope>,
+}
+
+tokio::task_local! {
+    /// 当前请求持有的账号占用 (重试换号时旧的占用随之释放)
+    static REQUEST_LEASE: RefCell<Option<AccountLease>>;
+    /// 当前请求的等待通知通道
+    static STICKY_WAIT_NOTIFY: tokio::sync::mpsc::UnboundedSender<StickyWait>;
+    /// 当前请求的调用方与协议路由
+    static REQUEST_ROUTE: RequestRoute;
+    /// 当前请求的调度决策记录 (未开启监控时为 None)
+    static REQUEST_TRACE: Option<SchedulingTraceSlot>;
+}
+
+/// 在请求作用域内执行 handler，返回其结果与最终选中账号的占用
+pub async fn scope_account_lease<F: std::future::Future>(fut: F) -> (F::Output, Option<AccountLease>) {
+    REQUEST_LEASE
+        .scope(RefCell::new(None), async move {
+            let output = fut.await;
+            let lease = REQUEST_LEASE.with(|slot| slot.borrow_mut().take());
+            (output, lease)
+        })
+        .await
+}
+
+/// 在请求作用域内执行 handler，get_token 开始等待绑定账号时通过 `notify` 通知
+pub async fn scope_sticky_wait<F: std::future::Future>(
+    notify: tokio::sync::mpsc::UnboundedSender<StickyWait>,
+    fut: F,
+) -> F::Output {
+    STICKY_WAIT_NOTIFY.scope(notify, fut).await
+}
+
+/// 在请求作用域内执行 handler，get_token 据此匹配 API Key 与路由条件
+pub async fn scope_request_route<F: std::future::Future>(route: RequestRoute, fut: F) -> F::Output {
+    REQUEST_ROUTE.scope(route, fut).await
+}
+
+/// 在请求作用域内执行 handler，get_token 的每次选号决策写入 `slot`
+pub async fn scope_scheduling_trace<F: std::future::Future>(slot: Option<SchedulingTraceSlot>, fut: F) -> F::Output {
+    REQUEST_TRACE.scope(slot, fut).await
+}
+
+/// get_token_internal 选号过程中记录的信息
+#[derive(Default)]
+struct SelectionTrace {
+    rule: Option<SelectionRule>,
+    round_robin_start: Option<usize>,
+    /// 本次调用中尝试失败的账号 Email (可能已被移出账号池)
+    attempted: Vec<String>,
+    /// 选号时各候选账号的状态 (Email -> 状态)，反映选号当时而非返回后的限流与并发情况
+    statuses: BTreeMap<String, CandidateStatus>,
+}
+
+impl SelectionTrace {
+    fn record(&mut self, email: &str, status: CandidateStatus) {
+        self.statuses.insert(email.to_string(), status);
+    }
+}
 
 pub struct TokenManager {
     tokens: Arc<DashMap<String, ProxyToken>>,  // account_id -> ProxyToken
@@ -31,6 +149,13 @@ pub struct TokenManager {
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
     session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    in_flight: Arc<DashMap<String, Arc<AtomicU32>>>, // 进行中请求数 (AccountID -> count)
+    admission: Arc<AdmissionQueue>, // 账号池全部限流时的准入排队
+    state_restored: Arc<AtomicBool>, // 调度状态仅在首次加载账号时从磁盘恢复
+    routing_rules: Arc<std::sync::RwLock<Vec<AccountRoutingRule>>>, // 账号分组路由规则
+    circuit_breaker: Arc<CircuitBreaker>, // 账号熔断 (Email -> 状态)
+    token_refresh: Arc<std::sync::RwLock<TokenRefreshConfig>>, // 后台 token 刷新配置
+    excluded_accounts: Arc<DashMap<String, CandidateStatus>>, // 加载时被跳过的账号 (Email -> 原因)，用于调度决策记录
 }
 
 impl TokenManager {
@@ -44,6 +169,13 @@ impl TokenManager {
             rate_limit_tracker: Arc::new(RateLimitTracker::new()),
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
             session_accounts: Arc::new(DashMap::new()),
+            in_flight: Arc::new(DashMap::new()),
+            admission: Arc::new(AdmissionQueue::new()),
+            state_restored: Arc::new(AtomicBool::new(false)),
+            routing_rules: Arc::new(std::sync::RwLock::new(Vec::new())),
+            circuit_breaker: Arc::new(CircuitBreaker::new()),
+            token_refresh: Arc::new(std::sync::RwLock::new(TokenRefreshConfig::default())),
+            excluded_accounts: Arc::new(DashMap::new()),
         }
     }
     
@@ -57,6 +189,7 @@ impl TokenManager {
 
         // Reload should reflect current on-disk state (accounts can be added/removed/disabled).
         self.tokens.clear();
+        self.excluded_accounts.clear();
         self.current_index.store(0, Ordering::SeqCst);
         {
             let mut last_used = self.last_used_account.lock().await;
@@ -91,14 +224,104 @@ impl TokenManager {
                 }
             }
         }
+
+        // 已删除或禁用的账号不再保留熔断状态
+        let emails: HashSet<String> = self.tokens.iter().map(|t| t.email.clone()).collect();
+        self.circuit_breaker.retain_accounts(&emails);
+
+        // 首次加载: 恢复上次运行的限流锁定与会话绑定，并开始定期保存
+        if !self.state_restored.swap(true, Ordering::SeqCst) {
+            self.restore_scheduler_state();
+            self.spawn_state_flusher();
+        }
         
         Ok(count)
     }
 
+    /// 当前调度状态快照 (不含保存时间)
+    fn scheduler_snapshot(tracker: &RateLimitTracker, sessions: &DashMap<String, String>) -> SchedulerSnapshot {
+        let (lockouts, failure_counts) = tracker.export_state();
+        SchedulerSnapshot {
+            saved_at: 0,
+            lockouts,
+            failure_counts,
+            session_bindings: sessions
+                .iter()
+                .map(|e| (e.key().clone(), e.value().clone()))
+                .collect(),
+        }
+    }
+
+    /// 立即保存调度状态 (停止服务时调用)
+    pub fn save_scheduler_state(&self) -> Result<(), String> {
+        let mut snapshot = Self::scheduler_snapshot(&self.rate_limit_tracker, &self.session_accounts);
+        snapshot.saved_at = chrono::Utc::now().timestamp();
+        scheduler_state::save(&self.data_dir, &snapshot)
+    }
+
+    /// 从磁盘恢复调度状态，丢弃已过期的锁定与已不在账号池中的账号
+    fn restore_scheduler_state(&self) {
+        let Some(snapshot) = scheduler_state::load(&self.data_dir) else {
+            return;
+        };
+        let known = |key: &str| self.tokens.iter().any(|t| t.account_id == key || t.email == key);
+
+        let lockouts = snapshot.lockouts.into_iter().filter(|l| known(&l.account)).collect();
+        // 失败计数没有过期时间，快照过旧时整体丢弃
+        let failure_counts = if chrono::Utc::now().timestamp() - snapshot.saved_at <= scheduler_state::FAILURE_COUNT_TTL_SECS {
+            snapshot.failure_counts.into_iter().filter(|f| known(&f.account)).collect()
+        } else {
+            Vec::new()
+        };
+        let restored_lockouts = self.rate_limit_tracker.import_state(lockouts, failure_counts);
+
+        let mut restored_sessions = 0;
+        for (session_id, account_id) in snapshot.session_bindings {
+            if self.tokens.contains_key(&account_id) {
+                self.session_accounts.entry(session_id).or_insert(account_id);
+                restored_sessions += 1;
+            }
+        }
+
+        tracing::info!(
+            "已恢复调度状态: {} 个限流锁定, {} 个会话绑定",
+            restored_lockouts, restored_sessions
+        );
+    }
+
+    /// 后台定期保存调度状态 (内容无变化时不写盘)，TokenManager 释放后自动退出
+    fn spawn_state_flusher(&self) {
+        const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+        let tracker: Weak<RateLimitTracker> = Arc::downgrade(&self.rate_limit_tracker);
+        let sessions: Weak<DashMap<String, String>> = Arc::downgrade(&self.session_accounts);
+        let data_dir = self.data_dir.clone();
+        tokio::spawn(async move {
+            let mut last: Option<SchedulerSnapshot> = None;
+            loop {
+                tokio::time::sleep(FLUSH_INTERVAL).await;
+                let (Some(tracker), Some(sessions)) = (tracker.upgrade(), sessions.upgrade()) else {
+                    break;
+                };
+                let snapshot = Self::scheduler_snapshot(&tracker, &sessions);
+                if last.as_ref() == Some(&snapshot) {
+                    continue;
+                }
+                let mut to_save = snapshot.clone();
+                to_save.saved_at = chrono::Utc::now().timestamp();
+                match scheduler_state::save(&data_dir, &to_save) {
+                    Ok(()) => last = Some(snapshot),
+                    Err(e) => tracing::warn!("{}", e),
+                }
+            }
+        });
+    }
+
     /// 重新加载指定账号（用于配额更新后的实时同步）
     pub async fn reload_account(&self, account_id: &str) -> Result<(), String> {
         let path = self.data_dir.join("accounts").join(format!("{}.json", account_id));
         if !path.exists() {
+            self.remove_account(account_id);
             return Err(format!("账号文件不存在: {:?}", path));
         }
 
@@ -107,11 +330,96 @@ impl TokenManager {
                 self.tokens.insert(account_id.to_string(), token);
                 Ok(())
             }
-            Ok(None) => Err("账号加载失败".to_string()),
+            Ok(None) => {
+                // 账号已被禁用
+                self.remove_account(account_id);
+                Err("账号加载失败".to_string())
+            }
             Err(e) => Err(format!("同步账号失败: {}", e)),
         }
     }
 
+    /// 从账号池移除账号，同时清除其熔断状态
+    fn remove_account(&self, account_id: &str) {
+        if let Some((_, token)) = self.tokens.remove(account_id) {
+            self.circuit_breaker.remove(&token.email);
+        }
+    }
+
+    /// 启动后台 token 刷新任务: 在 access token 过期前 margin_seconds 主动刷新，
+    /// 避免请求路径上的同步 OAuth 刷新；TokenManager 释放后自动退出
+    pub fn spawn_token_refresher(self: &Arc<Self>) {
+        const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+        let manager: Weak<TokenManager> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let Some(tm) = manager.upgrade() else {
+                    break;
+                };
+                let config = tm.token_refresh.read().map(|c| c.clone()).unwrap_or_default();
+                if !config.enabled {
+                    continue;
+                }
+                let due = tm.tokens_due_for_refresh(config.margin_seconds, chrono::Utc::now().timestamp());
+                if due.is_empty() {
+                    continue;
+                }
+                tracing::debug!("Background refresh: {} account token(s) expiring within {}s", due.len(), config.margin_seconds);
+                futures::StreamExt::for_each_concurrent(
+                    futures::stream::iter(due),
+                    config.max_concurrency.max(1),
+                    |token| {
+                        let tm = tm.clone();
+                        async move { tm.refresh_token_in_background(&token).await }
+                    },
+                )
+                .await;
+            }
+        });
+    }
+
+    /// access token 将在 margin_seconds 内过期的账号
+    fn tokens_due_for_refresh(&self, margin_seconds: u64, now: i64) -> Vec<ProxyToken> {
+        let margin = i64::try_from(margin_seconds).unwrap_or(i64::MAX);
+        self.tokens
+            .iter()
+            .filter(|e| now >= e.value().timestamp.saturating_sub(margin))
+            .map(|e| e.value().clone())
+            .collect()
+    }
+
+    async fn refresh_token_in_background(&self, token: &ProxyToken) {
+        match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
+            Ok(token_response) => {
+                let now = chrono::Utc::now().timestamp();
+                if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                    entry.access_token = token_response.access_token.clone();
+                    entry.expires_in = token_response.expires_in;
+                    entry.timestamp = now + token_response.expires_in;
+                }
+                if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
+                    tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
+                }
+                tracing::debug!("Background refresh: token refreshed for {}", token.email);
+            }
+            Err(e) if e.contains("invalid_grant") => {
+                tracing::error!(
+                    "Background refresh: disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
+                    token.email
+                );
+                let _ = self
+                    .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
+                    .await;
+                self.remove_account(&token.account_id);
+            }
+            // 其它错误留给请求路径上的同步刷新重试
+            Err(e) => tracing::warn!("Background refresh failed ({}): {}", token.email, e),
+        }
+    }
+
     /// 重新加载所有账号
     pub async fn reload_all_accounts(&self) -> Result<usize, String> {
         self.load_accounts().await
@@ -125,6 +433,17 @@ impl TokenManager {
         let account: serde_json::Value = serde_json::from_str(&content)
             .map_err(|e| format!("解析 JSON 失败: {}", e))?;
 
+        // 记录被跳过的账号及原因，供调度决策记录展示
+        let exclude = |status: CandidateStatus| {
+            if let Some(email) = account.get("email").and_then(|v| v.as_str()) {
+                self.excluded_accounts.insert(email.to_string(), status);
+            }
+        };
+        let proxy_disabled = account
+            .get("proxy_disabled")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
         if account
             .get("disabled")
             .and_then(|v| v.as_bool())
@@ -135,6 +454,7 @@ impl TokenManager {
                 path,
                 account.get("email").and_then(|v| v.as_str()).unwrap_or("<unknown>")
             );
+            exclude(CandidateStatus::Disabled);
             return Ok(None);
         }
 
@@ -146,20 +466,23 @@ impl TokenManager {
                 path,
                 account.get("email").and_then(|v| v.as_str()).unwrap_or("<unknown>")
             );
+            let by_quota = !proxy_disabled
+                || account
+                    .get("proxy_disabled_reason")
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|r| r.contains("quota_protection"));
+            exclude(if by_quota { CandidateStatus::QuotaProtected } else { CandidateStatus::ProxyDisabled });
             return Ok(None);
         }
 
         // 检查主动禁用状态
-        if account
-            .get("proxy_disabled")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false)
-        {
+        if proxy_disabled {
             tracing::debug!(
                 "Skipping proxy-disabled account file: {:?} (email={})",
                 path,
                 account.get("email").and_then(|v| v.as_str()).unwrap_or("<unknown>")
             );
+            exclude(CandidateStatus::ProxyDisabled);
             return Ok(None);
         }
 
@@ -204,7 +527,18 @@ impl TokenManager {
         let remaining_quota = account.get("quota")
             .and_then(|q| self.calculate_quota_stats(q));
             // .filter(|&r| r > 0); // 移除 >0 过滤，因为 0% 也是有效数据，只是优先级低
-        
+
+        let model_quotas: Vec<ModelQuota> = account.get("quota")
+            .and_then(|q| q.get("models"))
+            .and_then(|m| serde_json::from_value(m.clone()).ok())
+            .unwrap_or_default();
+
+        let tags: Vec<String> = account.get("tags")
+            .and_then(|t| serde_json::from_value(t.clone()).ok())
+            .unwrap_or_default();
+
+        self.excluded_accounts.remove(&email);
+
         Ok(Some(ProxyToken {
             account_id,
             access_token,
@@ -216,6 +550,8 @@ impl TokenManager {
             project_id,
             subscription_tier,
             remaining_quota,
+            model_quotas,
+            tags,
         }))
     }
 
@@ -421,23 +757,325 @@ impl TokenManager {
     /// 参数 `quota_group` 用于区分 "claude" vs "gemini" 组
     /// 参数 `force_rotate` 为 true 时将忽略锁定，强制切换账号
     /// 参数 `session_id` 用于跨请求维持会话粘性
-    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
-        // 【优化 Issue #284】添加 5 秒超时，防止死锁
-        let timeout_duration = std::time::Duration::from_secs(5);
-        match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id)).await {
-            Ok(result) => result,
-            Err(_) => Err("Token acquisition timeout (5s) - system too busy or deadlock detected".to_string()),
+    /// 参数 `target_model` 为映射后的上游模型，按该模型的剩余配额排序 (空串则使用整体配额)
+    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>, target_model: &str) -> Result<(String, String, String), String> {
+        self.get_token_queued(quota_group, force_rotate, session_id, target_model, true).await
+    }
+
+    /// 同 get_token，`allow_queue` 为 false 时账号池全部限流也不进入准入排队而是直接返回错误
+    /// (模型配置了降级链时由 handler 使用，以便立即尝试替代模型)
+    pub async fn get_token_queued(
+        &self,
+        quota_group: &str,
+        force_rotate: bool,
+        session_id: Option<&str>,
+        target_model: &str,
+        allow_queue: bool,
+    ) -> Result<(String, String, String), String> {
+        let started = std::time::Instant::now();
+        let mut trace = SelectionTrace::default();
+        let result = self.acquire_token(quota_group, force_rotate, session_id, target_model, allow_queue, &mut trace).await;
+
+        // 仅在请求作用域内 (经监控中间件) 记录决策，避免后台调用的额外开销
+        if let Some(slot) = REQUEST_TRACE.try_with(|slot| slot.clone()).ok().flatten() {
+            let scheduling = self.sticky_config.read().await.clone();
+            let mut decision = self.build_decision(target_model, &scheduling, &trace, &result);
+            decision.quota_group = quota_group.to_string();
+            decision.session_id = session_id.map(|s| s.to_string());
+            decision.duration_ms = started.elapsed().as_millis() as u64;
+            slot.push(decision);
         }
+        result
+    }
+
+    async fn acquire_token(
+        &self,
+        quota_group: &str,
+        force_rotate: bool,
+        session_id: Option<&str>,
+        target_model: &str,
+        allow_queue: bool,
+        trace: &mut SelectionTrace,
+    ) -> Result<(String, String, String), String> {
+        // CacheFirst: 绑定账号短暂限流时先等待其解除，等待时间不计入下方超时
+        if !force_rotate {
+            self.wait_for_bound_account(session_id, target_model).await;
+        }
+
+        let (max_depth, max_wait) = {
+            let config = self.sticky_config.read().await;
+            (config.queue_max_depth, config.queue_max_wait_seconds)
+        };
+        let mut ticket: Option<QueueTicket> = None;
+        let queued_at = std::time::Instant::now();
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(max_wait);
+        let mut last_error = String::new();
+
+        // 该模型已有请求在排队: 新请求先排到队尾再选号，锁定解除后释放的账号优先交给等待最久的队首
+        if allow_queue && self.admission.has_waiters(target_model) {
+            let eta = self.admission.eta();
+            match self.admission.enter(target_model, max_depth, eta) {
+                Some(t) => {
+                    tracing::info!(
+                        "Admission queue: requests already waiting for {}, queued at position {} (depth {})",
+                        target_model, t.position, self.admission.stats().depth
+                    );
+                    ticket = Some(t);
+                    last_error = format!("All accounts are currently limited. Request was not admitted within {}s.", max_wait);
+                }
+                None => {
+                    tracing::warn!("Admission queue is full (max {}), rejecting", max_depth);
+                    return Err(format!("Admission queue is full ({} waiting). Please retry shortly.", max_depth));
+                }
+            }
+        }
+
+        loop {
+            // 排在后面的请求等待前面的请求被放行或离开，不与队首同时重试
+            if let Some(t) = &ticket {
+                if tokio::time::timeout_at(deadline, t.wait_turn()).await.is_err() {
+                    self.admission.record_timeout();
+                    tracing::warn!(
+                        "Admission queue: not admitted within {}s (entered at position {}), rejecting",
+                        max_wait, t.position
+                    );
+                    return Err(last_error);
+                }
+            }
+
+            // 【优化 Issue #284】添加 5 秒超时，防止死锁 (排队等待不计入)
+            let timeout_duration = std::time::Duration::from_secs(5);
+            *trace = SelectionTrace::default();
+            let error = match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id, target_model, trace)).await {
+                Ok(Ok(token)) => {
+                    if ticket.is_some() {
+                        self.admission.record_admitted();
+                        tracing::info!("Admission queue: request admitted after {}s", queued_at.elapsed().as_secs());
+                    }
+                    return Ok(token);
+                }
+                Ok(Err(e)) => e,
+                Err(_) => return Err("Token acquisition timeout (5s) - system too busy or deadlock detected".to_string()),
+            };
+
+            // 仅在账号池对该模型全部限流时排队，其它错误直接返回
+            if !allow_queue {
+                return Err(error);
+            }
+            let Some(retry_after) = self.pool_retry_after(target_model) else {
+                return Err(error);
+            };
+            if queued_at.elapsed().as_secs() + retry_after > max_wait {
+                self.admission.record_timeout();
+                tracing::warn!("Admission queue: earliest reset in {}s exceeds max wait {}s, rejecting", retry_after, max_wait);
+                return Err(error);
+            }
+            match &ticket {
+                Some(_) => self.admission.set_eta(retry_after),
+                None => match self.admission.enter(target_model, max_depth, retry_after) {
+                    Some(t) => {
+                        tracing::info!(
+                            "Admission queue: all accounts rate-limited, queued at position {} (depth {}), ETA {}s",
+                            t.position, self.admission.stats().depth, retry_after
+                        );
+                        ticket = Some(t);
+                    }
+                    None => {
+                        tracing::warn!("Admission queue is full (max {}), rejecting", max_depth);
+                        return Err(error);
+                    }
+                },
+            }
+            // 只有队首等待最早的锁定解除后重试，其余请求在下一轮等待轮到自己
+            if ticket.as_ref().is_some_and(|t| t.is_head()) {
+                tokio::time::sleep(std::time::Duration::from_secs(retry_after)).await;
+            }
+            last_error = error;
+        }
+    }
+
+    /// 账号池对指定模型全部处于限流锁定时，距最早解除的秒数 (向上取整，用于 retry-after)
+    /// 仍有未限流账号或账号池为空时返回 None
+    pub fn pool_retry_after(&self, target_model: &str) -> Option<u64> {
+        let model = Some(target_model).filter(|m| !m.is_empty());
+        let group = self.routing_group(target_model);
+        let mut earliest: Option<u64> = None;
+        for entry in self.tokens.iter() {
+            if group.as_deref().is_some_and(|g| !entry.value().has_tag(g)) {
+                continue;
+            }
+            let remaining = self.token_reset_seconds(entry.value(), model)?;
+            earliest = Some(earliest.map_or(remaining, |e| e.min(remaining)));
+        }
+        earliest.map(|s| s + 1)
+    }
+
+    /// 账号池 (路由规则限定的分组内) 对目标模型的平均剩余配额百分比
+    /// 账号无按模型配额时使用整体配额，均无数据时返回 None
+    pub fn pool_average_quota(&self, target_model: &str) -> Option<f64> {
+        let group = self.routing_group(target_model);
+        let quotas: Vec<i32> = self
+            .tokens
+            .iter()
+            .filter(|e| group.as_deref().is_none_or(|g| e.value().has_tag(g)))
+            .filter_map(|e| e.value().quota_for_model(target_model))
+            .collect();
+        if quotas.is_empty() {
+            return None;
+        }
+        Some(quotas.iter().map(|&q| q as f64).sum::<f64>() / quotas.len() as f64)
+    }
+
+    /// 按路由规则解析当前请求应使用的账号分组 (None 表示不限制)
+    fn routing_group(&self, target_model: &str) -> Option<String> {
+        let route = REQUEST_ROUTE.try_with(|r| r.clone()).unwrap_or_default();
+        let rules = self.routing_rules.read().ok()?;
+        crate::proxy::config::resolve_account_group(&rules, target_model, route.api_key.as_deref(), route.route)
+            .map(|g| g.to_string())
+    }
+
+    /// 根据选号结果生成决策记录: 候选账号使用选号时记录的状态，并补充加载时被跳过的账号
+    fn build_decision(
+        &self,
+        target_model: &str,
+        scheduling: &StickySessionConfig,
+        trace: &SelectionTrace,
+        result: &Result<(String, String, String), String>,
+    ) -> SchedulingDecision {
+        let selected = result.as_ref().ok().map(|(_, _, email)| email.clone());
+
+        let mut statuses = trace.statuses.clone();
+        // 已尝试的账号 (含 invalid_grant 等原因已被移出账号池的账号)
+        for email in &trace.attempted {
+            statuses.insert(email.clone(), CandidateStatus::Attempted);
+        }
+        if let Some(email) = &selected {
+            statuses.insert(email.clone(), CandidateStatus::Selected);
+        }
+        let mut candidates: Vec<CandidateTrace> = statuses
+            .into_iter()
+            .map(|(email, status)| CandidateTrace { email, status })
+            .collect();
+        let mut excluded: Vec<CandidateTrace> = self
+            .excluded_accounts
+            .iter()
+            .filter(|e| !candidates.iter().any(|c| &c.email == e.key()))
+            .map(|e| CandidateTrace { email: e.key().clone(), status: *e.value() })
+            .collect();
+        excluded.sort_by(|a, b| a.email.cmp(&b.email));
+        candidates.extend(excluded);
+
+        SchedulingDecision {
+            target_model: target_model.to_string(),
+            mode: scheduling.mode,
+            group: self.routing_group(target_model),
+            candidates,
+            selected,
+            rule: result.as_ref().ok().and(trace.rule),
+            round_robin_start: trace.round_robin_start,
+            error: result.as_ref().err().cloned(),
+            ..Default::default()
+        }
+    }
+
+    /// 准入排队统计
+    pub fn admission_stats(&self) -> AdmissionStats {
+        self.admission.stats()
+    }
+
+    /// CacheFirst 模式下等待会话绑定账号的限流解除 (不超过 max_wait_seconds)
+    /// 超出等待预算时直接返回，由 get_token_internal 解绑并切换账号
+    async fn wait_for_bound_account(&self, session_id: Option<&str>, target_model: &str) {
+        use crate::proxy::sticky_config::SchedulingMode;
+
+        let Some(sid) = session_id else {
+            return;
+        };
+        let max_wait = {
+            let config = self.sticky_config.read().await;
+            if config.mode != SchedulingMode::CacheFirst {
+                return;
+            }
+            config.max_wait_seconds
+        };
+        let Some(bound) = self
+            .session_accounts
+            .get(sid)
+            .and_then(|id| self.tokens.get(id.value()).map(|t| t.value().clone()))
+        else {
+            return;
+        };
+        // 绑定账号不在规则指定的分组内时由 get_token_internal 另选账号
+        if self.routing_group(target_model).is_some_and(|g| !bound.has_tag(&g)) {
+            return;
+        }
+        let model = Some(target_model).filter(|m| !m.is_empty());
+        let Some(remaining) = self.token_reset_seconds(&bound, model) else {
+            return;
+        };
+
+        // 剩余秒数向下取整，多等 1 秒确保锁定已解除
+        let wait_seconds = remaining + 1;
+        if wait_seconds > max_wait {
+            return;
+        }
+
+        tracing::info!(
+            "Cache First: session {} bound account {} is rate-limited, waiting {}s instead of switching",
+            sid, bound.email, wait_seconds
+        );
+        let _ = STICKY_WAIT_NOTIFY.try_with(|notify| {
+            notify.send(StickyWait {
+                email: bound.email.clone(),
+                model: target_model.to_string(),
+                wait_seconds,
+            })
+        });
+        tokio::time::sleep(std::time::Duration::from_secs(wait_seconds)).await;
     }
 
     /// 内部实现：获取 Token 的核心逻辑
-    async fn get_token_internal(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
+    async fn get_token_internal(
+        &self,
+        quota_group: &str,
+        force_rotate: bool,
+        session_id: Option<&str>,
+        target_model: &str,
+        trace: &mut SelectionTrace,
+    ) -> Result<(String, String, String), String> {
         let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
-        let total = tokens_snapshot.len();
-        if total == 0 {
+        if tokens_snapshot.is_empty() {
             return Err("Token pool is empty".to_string());
         }
 
+        // 路由规则将请求限定到指定标签的账号分组
+        if let Some(group) = self.routing_group(target_model) {
+            tokens_snapshot.retain(|t| {
+                let inside = t.has_tag(&group);
+                if !inside {
+                    trace.record(&t.email, CandidateStatus::OutsideGroup);
+                }
+                inside
+            });
+            if tokens_snapshot.is_empty() {
+                return Err(format!("No accounts in group '{}' for model {}", group, target_model));
+            }
+            tracing::debug!("Routing rule: model {} limited to group '{}' ({} accounts)", target_model, group, tokens_snapshot.len());
+        }
+
+        // 熔断中 (open/half-open) 的账号在半开探测成功前不接收真实流量
+        tokens_snapshot.retain(|t| {
+            let allowed = self.circuit_breaker.allows(&t.email);
+            if !allowed {
+                trace.record(&t.email, CandidateStatus::CircuitOpen);
+            }
+            allowed
+        });
+        if tokens_snapshot.is_empty() {
+            return Err("All accounts are unhealthy (circuit breaker open). Please retry shortly.".to_string());
+        }
+        let total = tokens_snapshot.len();
+
         // ===== 【优化】根据订阅等级和剩余配额排序 =====
         // [FIX #563] 优先级: ULTRA > PRO > FREE, 同tier内优先高配额账号
         // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
@@ -458,10 +1096,10 @@ impl TokenManager {
                 return tier_cmp;
             }
             
-            // [FIX #563] Second: compare by remaining quota percentage (higher is better)
+            // [FIX #563] Second: compare by remaining quota of the target model (higher is better)
             // Accounts with unknown/zero percentage go last within their tier
-            let quota_a = a.remaining_quota.unwrap_or(0);
-            let quota_b = b.remaining_quota.unwrap_or(0);
+            let quota_a = a.quota_for_model(target_model).unwrap_or(0);
+            let quota_b = b.quota_for_model(target_model).unwrap_or(0);
             quota_b.cmp(&quota_a)  // Descending: higher percentage first
         });
 
@@ -469,6 +1107,9 @@ impl TokenManager {
         // 0. 读取当前调度配置
         let scheduling = self.sticky_config.read().await.clone();
         use crate::proxy::sticky_config::SchedulingMode;
+        let max_concurrency = scheduling.max_concurrency_per_account;
+        // 模型级别锁定只影响同一模型的请求
+        let model = Some(target_model).filter(|m| !m.is_empty());
 
         // 【优化 Issue #284】将锁操作移到循环外，避免重复获取锁
         // 预先获取 last_used_account 的快照，避免在循环中多次加锁
@@ -486,11 +1127,23 @@ impl TokenManager {
         for attempt in 0..total {
             let rotate = force_rotate || attempt > 0;
 
+            // 记录本轮选号时各候选账号的状态 (乐观重置与占用账号之前)
+            for t in tokens_snapshot.iter().filter(|t| !attempted.contains(&t.account_id)) {
+                let status = if self.is_token_limited(t, model) {
+                    CandidateStatus::RateLimited
+                } else if self.is_at_capacity(&t.account_id, max_concurrency) {
+                    CandidateStatus::AtCapacity
+                } else {
+                    CandidateStatus::Available
+                };
+                trace.record(&t.email, status);
+            }
+
             // ===== 【核心】粘性会话与智能调度逻辑 =====
             let mut target_token: Option<ProxyToken> = None;
             
             // 模式 A: 粘性会话处理 (CacheFirst 或 Balance 且有 session_id)
-            if !rotate && session_id.is_some() && scheduling.mode != SchedulingMode::PerformanceFirst {
+            if !rotate && session_id.is_some() && scheduling.mode.uses_sticky_sessions() {
                 let sid = session_id.unwrap();
                 
                 // 1. 检查会话是否已绑定账号
@@ -499,7 +1152,7 @@ impl TokenManager {
                     // 因为限流记录是以 email 为 key 存储的
                     if let Some(bound_token) = tokens_snapshot.iter().find(|t| t.account_id == bound_id) {
                         // 2. 使用 email 检查绑定的账号是否限流
-                        let reset_sec = self.rate_limit_tracker.get_remaining_wait(&bound_token.email);
+                        let reset_sec = self.token_reset_seconds(bound_token, model).unwrap_or(0);
                         if reset_sec > 0 {
                             // 【修复 Issue #284】立即解绑并切换账号，不再阻塞等待
                             // 原因：阻塞等待会导致并发请求时客户端 socket 超时 (UND_ERR_SOCKET)
@@ -508,11 +1161,18 @@ impl TokenManager {
                                 sid, bound_token.email, reset_sec
                             );
                             self.session_accounts.remove(sid);
+                        } else if self.is_at_capacity(&bound_id, max_concurrency) {
+                            // 达到并发上限时本次借用其他账号，保留绑定
+                            tracing::debug!("Sticky Session: bound account {} is at max concurrency, borrowing another account", bound_token.email);
                         } else if !attempted.contains(&bound_id) {
                             // 3. 账号可用且未被标记为尝试失败，优先复用
                             tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
                             target_token = Some(bound_token.clone());
+                            trace.rule = Some(SelectionRule::StickySession);
                         }
+                    } else if self.tokens.contains_key(&bound_id) {
+                        // 绑定的账号不在本次请求的分组内或正在熔断，保留绑定
+                        tracing::debug!("Sticky Session: bound account {} is outside the routed group or circuit-open, skipping", bound_id);
                     } else {
                         // 绑定的账号已不存在（可能被删除），解绑
                         tracing::warn!("Session {} bound to non-existent account {}, unbinding.", sid, bound_id);
@@ -521,16 +1181,33 @@ impl TokenManager {
                 }
             }
 
-            // 模式 B: 原子化 60s 全局锁定 (针对无 session_id 情况的默认保护)
-            if target_token.is_none() && !rotate && quota_group != "image_gen" {
+            // 模式 L: 最少负载，选择进行中请求最少的账号 (同负载时保持 tier/配额排序)
+            if target_token.is_none() && scheduling.mode == SchedulingMode::LeastLoaded {
+                target_token = tokens_snapshot
+                    .iter()
+                    .filter(|t| !attempted.contains(&t.account_id))
+                    .filter(|t| !self.is_token_limited(t, model))
+                    .filter(|t| !self.is_at_capacity(&t.account_id, max_concurrency))
+                    .min_by_key(|t| self.in_flight(&t.account_id))
+                    .cloned();
+                if let Some(t) = &target_token {
+                    tracing::debug!("Least Loaded: selected {} ({} in flight)", t.email, self.in_flight(&t.account_id));
+                    trace.rule = Some(SelectionRule::LeastLoaded);
+                }
+            } else if target_token.is_none() && !rotate && quota_group != "image_gen" {
+                // 模式 B: 原子化 60s 全局锁定 (针对无 session_id 情况的默认保护)
                 // 【优化】使用预先获取的快照，不再在循环内加锁
                 if let Some((account_id, last_time)) = &last_used_account_id {
-                    if last_time.elapsed().as_secs() < 60 && !attempted.contains(account_id) {
+                    if last_time.elapsed().as_secs() < 60
+                        && !attempted.contains(account_id)
+                        && !self.is_at_capacity(account_id, max_concurrency)
+                    {
                         if let Some(found) = tokens_snapshot.iter().find(|t| &t.account_id == account_id) {
                             // 【修复】检查限流状态，避免复用已被锁定的账号
-                            if !self.is_rate_limited(&found.email) {
+                            if !self.is_token_limited(found, model) {
                                 tracing::debug!("60s Window: Force reusing last account: {}", found.email);
                                 target_token = Some(found.clone());
+                                trace.rule = Some(SelectionRule::LastUsedWindow);
                             } else {
                                 tracing::debug!("60s Window: Last account {} is rate-limited, skipping", found.email);
                             }
@@ -541,6 +1218,7 @@ impl TokenManager {
                 // 若无锁定，则轮询选择新账号
                 if target_token.is_none() {
                     let start_idx = self.current_index.fetch_add(1, Ordering::SeqCst) % total;
+                    trace.round_robin_start = Some(start_idx);
                     for offset in 0..total {
                         let idx = (start_idx + offset) % total;
                         let candidate = &tokens_snapshot[idx];
@@ -549,17 +1227,22 @@ impl TokenManager {
                         }
 
                         // 【新增】主动避开限流或 5xx 锁定的账号 (来自 PR #28 的高可用思路)
-                        if self.is_rate_limited(&candidate.account_id) {
+                        if self.is_token_limited(candidate, model) {
+                            continue;
+                        }
+
+                        if self.is_at_capacity(&candidate.account_id, max_concurrency) {
                             continue;
                         }
 
                         target_token = Some(candidate.clone());
+                        trace.rule = Some(SelectionRule::RoundRobin);
                         // 【优化】标记需要更新，稍后统一写回
                         need_update_last_used = Some((candidate.account_id.clone(), std::time::Instant::now()));
                         
                         // 如果是会话首次分配且需要粘性，在此建立绑定
                         if let Some(sid) = session_id {
-                            if scheduling.mode != SchedulingMode::PerformanceFirst {
+                            if scheduling.mode.uses_sticky_sessions() {
                                 self.session_accounts.insert(sid.to_string(), candidate.account_id.clone());
                                 tracing::debug!("Sticky Session: Bound new account {} to session {}", candidate.email, sid);
                             }
@@ -570,6 +1253,7 @@ impl TokenManager {
             } else if target_token.is_none() {
                 // 模式 C: 纯轮询模式 (Round-robin) 或强制轮换
                 let start_idx = self.current_index.fetch_add(1, Ordering::SeqCst) % total;
+                trace.round_robin_start = Some(start_idx);
                 for offset in 0..total {
                     let idx = (start_idx + offset) % total;
                     let candidate = &tokens_snapshot[idx];
@@ -578,12 +1262,17 @@ impl TokenManager {
                     }
 
                     // 【新增】主动避开限流或 5xx 锁定的账号
-                    if self.is_rate_limited(&candidate.account_id) {
+                    if self.is_token_limited(candidate, model) {
+                        continue;
+                    }
+
+                    if self.is_at_capacity(&candidate.account_id, max_concurrency) {
                         continue;
                     }
 
                     target_token = Some(candidate.clone());
-                    
+                    trace.rule = Some(if rotate { SelectionRule::ForceRotate } else { SelectionRule::RoundRobin });
+
                     if rotate {
                         tracing::debug!("Force Rotation: Switched to account: {}", candidate.email);
                     }
@@ -593,13 +1282,25 @@ impl TokenManager {
             
             let mut token = match target_token {
                 Some(t) => t,
+                None if tokens_snapshot.iter().any(|t| {
+                    !attempted.contains(&t.account_id)
+                        && !self.is_token_limited(t, model)
+                        && self.is_at_capacity(&t.account_id, max_concurrency)
+                }) =>
+                {
+                    // 有可用账号但均已达到并发上限，不触发乐观重置
+                    return Err(format!(
+                        "All available accounts are at max concurrency ({} per account). Please retry shortly.",
+                        max_concurrency
+                    ));
+                }
                 None => {
                     // 乐观重置策略: 双层防护机制
                     // 当所有账号都无法选择时,可能是时序竞争导致的状态不同步
                     
                     // 计算最短等待时间
                     let min_wait = tokens_snapshot.iter()
-                        .filter_map(|t| self.rate_limit_tracker.get_reset_seconds(&t.account_id))
+                        .filter_map(|t| self.token_reset_seconds(t, model))
                         .min();
                     
                     // Layer 1: 如果最短等待时间 <= 2秒,执行缓冲延迟
@@ -615,10 +1316,11 @@ impl TokenManager {
                             
                             // 重新尝试选择账号
                             let retry_token = tokens_snapshot.iter()
-                                .find(|t| !attempted.contains(&t.account_id) && !self.is_rate_limited(&t.account_id));
+                                .find(|t| !attempted.contains(&t.account_id) && !self.is_token_limited(t, model));
                             
                             if let Some(t) = retry_token {
                                 tracing::info!("✅ Buffer delay successful! Found available account: {}", t.email);
+                                trace.rule = Some(SelectionRule::BufferRetry);
                                 t.clone()
                             } else {
                                 // Layer 2: 缓冲后仍无可用账号,执行乐观重置
@@ -627,8 +1329,8 @@ impl TokenManager {
                                     tokens_snapshot.len()
                                 );
                                 
-                                // 清除所有限流记录
-                                self.rate_limit_tracker.clear_all();
+                                // 清除账号级别与目标模型的限流记录 (其它模型的锁定保留)
+                                self.rate_limit_tracker.clear_for_model(model);
                                 
                                 // 再次尝试选择账号
                                 let final_token = tokens_snapshot.iter()
@@ -636,6 +1338,7 @@ impl TokenManager {
                                 
                                 if let Some(t) = final_token {
                                     tracing::info!("✅ Optimistic reset successful! Using account: {}", t.email);
+                                    trace.rule = Some(SelectionRule::OptimisticReset);
                                     t.clone()
                                 } else {
                                     // 所有策略都失败,返回错误
@@ -655,7 +1358,20 @@ impl TokenManager {
                 }
             };
 
-        
+            // 选中即占用并发槽位 (原子检查上限)，避免并发请求在刷新 token 等 await 期间同时落到同一账号；
+            // 占用失败说明槽位已被并发请求抢先占满，换下一个候选账号
+            let Some(lease) = self.try_acquire_lease(&token.account_id, max_concurrency) else {
+                tracing::debug!("Account {} reached max concurrency while being selected, trying next", token.email);
+                trace.record(&token.email, CandidateStatus::AtCapacity);
+                last_error = Some(format!(
+                    "All available accounts are at max concurrency ({} per account). Please retry shortly.",
+                    max_concurrency
+                ));
+                continue;
+            };
+            // 立即交给请求作用域持有，后续失败换号时释放
+            Self::hold_lease(Some(lease));
+
             // 3. 检查 token 是否过期（提前5分钟刷新）
             let now = chrono::Utc::now().timestamp();
             if now >= token.timestamp - 300 {
@@ -693,11 +1409,14 @@ impl TokenManager {
                             let _ = self
                                 .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
                                 .await;
-                            self.tokens.remove(&token.account_id);
+                            self.remove_account(&token.account_id);
                         }
                         // Avoid leaking account emails to API clients; details are still in logs.
                         last_error = Some(format!("Token refresh failed: {}", e));
                         attempted.insert(token.account_id.clone());
+                        trace.attempted.push(token.email.clone());
+
+                        Self::hold_lease(None);
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
                         if quota_group != "image_gen" {
@@ -727,6 +1446,9 @@ impl TokenManager {
                         tracing::error!("Failed to fetch project_id for {}: {}", token.email, e);
                         last_error = Some(format!("Failed to fetch project_id for {}: {}", token.email, e));
                         attempted.insert(token.account_id.clone());
+                        trace.attempted.push(token.email.clone());
+
+                        Self::hold_lease(None);
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
                         if quota_group != "image_gen" {
@@ -780,6 +1502,9 @@ impl TokenManager {
         std::fs::write(&path, serde_json::to_string_pretty(&content).unwrap())
             .map_err(|e| format!("写入文件失败: {}", e))?;
 
+        if let Some(email) = content.get("email").and_then(|v| v.as_str()) {
+            self.excluded_accounts.insert(email.to_string(), CandidateStatus::Disabled);
+        }
         tracing::warn!("Account disabled: {} ({:?})", account_id, path);
         Ok(())
     }
@@ -832,6 +1557,69 @@ impl TokenManager {
         self.tokens.len()
     }
 
+    /// 当前未被限流的账号数
+    pub fn available_count(&self) -> usize {
+        self.tokens
+            .iter()
+            .filter(|entry| {
+                let token = entry.value();
+                !self.is_token_limited(token, None)
+            })
+            .count()
+    }
+
+    /// 账号进行中的请求数
+    pub fn in_flight(&self, account_id: &str) -> u32 {
+        self.in_flight
+            .get(account_id)
+            .map(|c| c.load(Ordering::SeqCst))
+            .unwrap_or(0)
+    }
+
+    /// 账号是否已达到并发上限 (max_concurrency 为 0 表示不限制)
+    fn is_at_capacity(&self, account_id: &str, max_concurrency: u32) -> bool {
+        max_concurrency > 0 && self.in_flight(account_id) >= max_concurrency
+    }
+
+    /// 原子地占用账号的一个并发槽位，已达上限时返回 None (max_concurrency 为 0 表示不限制)
+    fn try_acquire_lease(&self, account_id: &str, max_concurrency: u32) -> Option<AccountLease> {
+        let counter = self
+            .in_flight
+            .entry(account_id.to_string())
+            .or_insert_with(|| Arc::new(AtomicU32::new(0)))
+            .clone();
+        counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (max_concurrency == 0 || n < max_concurrency).then_some(n + 1)
+            })
+            .ok()?;
+        Some(AccountLease { counter })
+    }
+
+    /// 替换当前请求持有的占用，旧的占用随之释放 (不在请求作用域内时仅短暂计数)
+    fn hold_lease(lease: Option<AccountLease>) {
+        let _ = REQUEST_LEASE.try_with(|slot| *slot.borrow_mut() = lease);
+    }
+
+    /// 账号对指定模型是否处于限流锁定
+    /// 限流记录通常以 email 为键 (handler 传入)，同时兼容以 account_id 记录的锁定
+    fn is_token_limited(&self, token: &ProxyToken, model: Option<&str>) -> bool {
+        self.rate_limit_tracker.is_rate_limited(&token.email, model)
+            || self.rate_limit_tracker.is_rate_limited(&token.account_id, model)
+    }
+
+    /// 账号对指定模型的锁定剩余秒数 (未锁定时为 None)
+    fn token_reset_seconds(&self, token: &ProxyToken, model: Option<&str>) -> Option<u64> {
+        self.rate_limit_tracker
+            .get_reset_seconds(&token.email, model)
+            .max(self.rate_limit_tracker.get_reset_seconds(&token.account_id, model))
+    }
+
+    /// 当前生效的限流锁定 (账号, 模型, 剩余秒数)
+    pub fn active_lockouts(&self) -> Vec<(String, Option<String>, u64)> {
+        self.rate_limit_tracker.active_lockouts()
+    }
+
     /// 通过 email 获取指定账号的 Token（用于预热等需要指定账号的场景）
     /// 此方法会自动刷新过期的 token
     pub async fn get_token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
@@ -903,31 +1691,34 @@ impl TokenManager {
     // ===== 限流管理方法 =====
     
     /// 标记账号限流(从外部调用,通常在 handler 中)
+    /// 传入 model 时只锁定该模型，账号的其它模型仍可调度
     pub fn mark_rate_limited(
         &self,
         account_id: &str,
         status: u16,
         retry_after_header: Option<&str>,
         error_body: &str,
+        model: Option<&str>,
     ) {
         self.rate_limit_tracker.parse_from_error(
             account_id,
             status,
             retry_after_header,
             error_body,
-            None,
+            model.map(|s| s.to_string()),
         );
     }
     
-    /// 检查账号是否在限流中
-    pub fn is_rate_limited(&self, account_id: &str) -> bool {
-        self.rate_limit_tracker.is_rate_limited(account_id)
+    /// 检查账号(对指定模型)是否在限流中
+    #[allow(dead_code)]
+    pub fn is_rate_limited(&self, account_id: &str, model: Option<&str>) -> bool {
+        self.rate_limit_tracker.is_rate_limited(account_id, model)
     }
     
     /// 获取距离限流重置还有多少秒
     #[allow(dead_code)]
-    pub fn get_rate_limit_reset_seconds(&self, account_id: &str) -> Option<u64> {
-        self.rate_limit_tracker.get_reset_seconds(account_id)
+    pub fn get_rate_limit_reset_seconds(&self, account_id: &str, model: Option<&str>) -> Option<u64> {
+        self.rate_limit_tracker.get_reset_seconds(account_id, model)
     }
     
     /// 清除过期的限流记录
@@ -946,14 +1737,45 @@ impl TokenManager {
     /// 
     /// 在请求成功完成后调用，将该账号的失败计数归零，
     /// 下次失败时从最短的锁定时间开始（智能限流）。
-    pub fn mark_account_success(&self, account_id: &str) {
-        self.rate_limit_tracker.mark_success(account_id);
+    pub fn mark_account_success(&self, account_id: &str, model: Option<&str>) {
+        self.rate_limit_tracker.mark_success(account_id, model);
+        self.circuit_breaker.record_success(account_id);
+    }
+
+    /// 记录上游 5xx、网络错误或空响应流，连续失败达到阈值时熔断账号
+    pub async fn mark_upstream_failure(&self, email: &str, model: Option<&str>) {
+        let (threshold, open_seconds) = {
+            let config = self.sticky_config.read().await;
+            (config.breaker_failure_threshold, config.breaker_open_seconds)
+        };
+        if self.circuit_breaker.record_failure(email, model, threshold, open_seconds) {
+            tracing::warn!(
+                "[CircuitBreaker] {} failed {} times in a row, circuit opened",
+                email, threshold
+            );
+        }
+    }
+
+    /// 冷却结束、需要半开探测的账号 (Email, 模型)
+    pub fn take_due_probes(&self) -> Vec<(String, String)> {
+        self.circuit_breaker.take_due_probes()
+    }
+
+    pub async fn record_probe_result(&self, email: &str, success: bool) {
+        let open_seconds = self.sticky_config.read().await.breaker_open_seconds;
+        self.circuit_breaker.record_probe_result(email, success, open_seconds);
+    }
+
+    /// 非关闭状态的熔断器 (Email, 状态)
+    pub fn breaker_states(&self) -> Vec<(String, BreakerState)> {
+        self.circuit_breaker.snapshot()
     }
     
     /// 从账号文件获取配额刷新时间
     /// 
     /// 返回该账号最近的配额刷新时间字符串（ISO 8601 格式）
-    pub fn get_quota_reset_time(&self, email: &str) -> Option<String> {
+    /// 传入 model 且配额中有该模型时，返回该模型自己的刷新时间
+    pub fn get_quota_reset_time(&self, email: &str, model: Option<&str>) -> Option<String> {
         // 尝试从账号文件读取配额信息
         let accounts_dir = self.data_dir.join("accounts");
         
@@ -970,6 +1792,17 @@ impl TokenManager {
                                 .and_then(|q| q.get("models"))
                                 .and_then(|m| m.as_array()) 
                             {
+                                if let Some(reset) = model.and_then(|m| {
+                                    models
+                                        .iter()
+                                        .find(|q| q.get("name").and_then(|n| n.as_str()) == Some(m))
+                                        .and_then(|q| q.get("reset_time"))
+                                        .and_then(|r| r.as_str())
+                                        .filter(|r| !r.is_empty())
+                                }) {
+                                    return Some(reset.to_string());
+                                }
+
                                 // 找到最早的 reset_time（最保守的锁定策略）
                                 let mut earliest_reset: Option<&str> = None;
                                 for model in models {
@@ -1000,7 +1833,7 @@ impl TokenManager {
     /// # 参数
     /// - `model`: 可选的模型名称,用于模型级别限流
     pub fn set_precise_lockout(&self, email: &str, reason: crate::proxy::rate_limit::RateLimitReason, model: Option<String>) -> bool {
-        if let Some(reset_time_str) = self.get_quota_reset_time(email) {
+        if let Some(reset_time_str) = self.get_quota_reset_time(email, model.as_deref()) {
             tracing::info!("找到账号 {} 的配额刷新时间: {}", email, reset_time_str);
             self.rate_limit_tracker.set_lockout_until_iso(email, &reset_time_str, reason, model)
         } else {
@@ -1048,8 +1881,13 @@ impl TokenManager {
         tracing::info!("账号 {} 正在实时刷新配额...", email);
         match crate::modules::quota::fetch_quota(&access_token, email).await {
             Ok((quota_data, _project_id)) => {
-                // 3. 从最新配额中提取 reset_time
-                let earliest_reset = quota_data.models.iter()
+                // 3. 从最新配额中提取 reset_time (已知模型时优先使用该模型自己的刷新时间)
+                let model_reset = model.as_deref().and_then(|name| {
+                    quota_data.models.iter()
+                        .find(|m| m.name == name && !m.reset_time.is_empty())
+                        .map(|m| m.reset_time.as_str())
+                });
+                let earliest_reset = model_reset.or_else(|| quota_data.models.iter()
                     .filter_map(|m| {
                         if !m.reset_time.is_empty() {
                             Some(m.reset_time.as_str())
@@ -1057,7 +1895,7 @@ impl TokenManager {
                             None
                         }
                     })
-                    .min();
+                    .min());
                 
                 if let Some(reset_time_str) = earliest_reset {
                     tracing::info!(
@@ -1168,6 +2006,20 @@ impl TokenManager {
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
+    /// 更新后台 token 刷新配置
+    pub fn update_token_refresh_config(&self, config: TokenRefreshConfig) {
+        if let Ok(mut current) = self.token_refresh.write() {
+            *current = config;
+        }
+    }
+
+    /// 更新账号分组路由规则
+    pub fn update_routing_rules(&self, rules: Vec<AccountRoutingRule>) {
+        if let Ok(mut current) = self.routing_rules.write() {
+            *current = rules;
+        }
+    }
+
     /// 清除特定会话的粘性映射
     #[allow(dead_code)]
     pub fn clear_session_binding(&self, session_id: &str) {
@@ -1188,3 +2040,431 @@ fn truncate_reason(reason: &str, max_len: usize) -> String {
     s.push('…');
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::sticky_config::SchedulingMode;
+
+    fn token(id: &str) -> ProxyToken {
+        ProxyToken {
+            account_id: id.to_string(),
+            access_token: format!("at-{}", id),
+            refresh_token: String::new(),
+            expires_in: 3600,
+            timestamp: chrono::Utc::now().timestamp() + 3600,
+            email: format!("{}@example.com", id),
+            account_path: PathBuf::new(),
+            project_id: Some("p".to_string()),
+            subscription_tier: None,
+            remaining_quota: None,
+            model_quotas: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    fn quota(name: &str, percentage: i32) -> ModelQuota {
+        ModelQuota {
+            name: name.to_string(),
+            percentage,
+            reset_time: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_least_loaded_respects_in_flight_and_cap() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.tokens.insert("b".to_string(), token("b"));
+        tm.update_sticky_config(StickySessionConfig {
+            mode: SchedulingMode::LeastLoaded,
+            max_concurrency_per_account: 1,
+            ..StickySessionConfig::default()
+        })
+        .await;
+
+        let (first, lease_a) = scope_account_lease(tm.get_token("claude", false, Some("s1"), "")).await;
+        let (second, lease_b) = scope_account_lease(tm.get_token("claude", false, Some("s1"), "")).await;
+        assert_ne!(first.unwrap().2, second.unwrap().2);
+        assert!(lease_a.is_some() && lease_b.is_some());
+
+        // 两个账号均已达到并发上限
+        let (third, _) = scope_account_lease(tm.get_token("claude", false, None, "")).await;
+        assert!(third.unwrap_err().contains("max concurrency"));
+
+        // 释放后重新可用
+        drop(lease_a);
+        let (fourth, _) = scope_account_lease(tm.get_token("claude", false, None, "")).await;
+        assert!(fourth.is_ok());
+        drop(lease_b);
+        assert_eq!(tm.in_flight("a") + tm.in_flight("b"), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_requests_never_exceed_account_cap() {
+        let tm = Arc::new(TokenManager::new(std::env::temp_dir()));
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.update_sticky_config(StickySessionConfig {
+            mode: SchedulingMode::LeastLoaded,
+            max_concurrency_per_account: 1,
+            ..StickySessionConfig::default()
+        })
+        .await;
+
+        let peak = Arc::new(AtomicU32::new(0));
+        let tasks: Vec<_> = (0..64)
+            .map(|_| {
+                let tm = tm.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    let (result, lease) = scope_account_lease(tm.get_token("claude", false, None, "")).await;
+                    peak.fetch_max(tm.in_flight("a"), Ordering::SeqCst);
+                    if result.is_ok() {
+                        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                    }
+                    drop(lease);
+                    result.is_ok()
+                })
+            })
+            .collect();
+        let mut admitted = 0;
+        for task in tasks {
+            admitted += task.await.unwrap() as u32;
+        }
+        assert!(admitted >= 1);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+        assert_eq!(tm.in_flight("a"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_ranking_uses_target_model_quota() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        // a 的 Gemini 额度满但 Claude 几乎耗尽，整体配额会误判其更优
+        let mut a = token("a");
+        a.remaining_quota = Some(100);
+        a.model_quotas = vec![quota("claude-sonnet-4-5", 10), quota("gemini-3-flash", 100)];
+        let mut b = token("b");
+        b.remaining_quota = Some(80);
+        b.model_quotas = vec![quota("claude-sonnet-4-5", 80), quota("gemini-3-flash", 20)];
+        tm.tokens.insert("a".to_string(), a.clone());
+        tm.tokens.insert("b".to_string(), b);
+        tm.update_sticky_config(StickySessionConfig {
+            mode: SchedulingMode::LeastLoaded,
+            ..StickySessionConfig::default()
+        })
+        .await;
+
+        let (claude, _) = scope_account_lease(tm.get_token("claude", false, None, "claude-sonnet-4-5")).await;
+        assert_eq!(claude.unwrap().2, "b@example.com");
+        let (gemini, _) = scope_account_lease(tm.get_token("gemini", false, None, "gemini-3-flash")).await;
+        assert_eq!(gemini.unwrap().2, "a@example.com");
+
+        // 未精确命中时按同系列最低额度，无同系列数据视为未知
+        assert_eq!(a.quota_for_model("claude-opus-4-5-thinking"), Some(10));
+        assert_eq!(a.quota_for_model("gpt-4o"), None);
+        assert_eq!(a.quota_for_model(""), Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_model_lockout_only_skips_locked_model() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.mark_rate_limited("a@example.com", 429, Some("600"), "MODEL_CAPACITY_EXHAUSTED", Some("gemini-3-pro-high"));
+
+        let (claude, _) = scope_account_lease(tm.get_token("claude", false, None, "claude-sonnet-4-5")).await;
+        assert_eq!(claude.unwrap().2, "a@example.com");
+        let (gemini, _) = scope_account_lease(tm.get_token("gemini", true, None, "gemini-3-pro-high")).await;
+        assert!(gemini.unwrap_err().contains("limited"));
+        assert_eq!(tm.available_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_first_waits_for_bound_account() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.tokens.insert("b".to_string(), token("b"));
+        tm.update_sticky_config(StickySessionConfig {
+            mode: SchedulingMode::CacheFirst,
+            max_wait_seconds: 10,
+            ..StickySessionConfig::default()
+        })
+        .await;
+
+        let (bound, _) = scope_account_lease(tm.get_token("claude", false, Some("s1"), "claude-sonnet-4-5")).await;
+        let bound = bound.unwrap().2;
+        tm.mark_rate_limited(&bound, 429, Some("2"), "", Some("claude-sonnet-4-5"));
+
+        // 绑定账号短暂限流: 等待解除而非切换账号
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let (again, _) = scope_account_lease(scope_sticky_wait(
+            tx,
+            tm.get_token("claude", false, Some("s1"), "claude-sonnet-4-5"),
+        ))
+        .await;
+        assert_eq!(again.unwrap().2, bound);
+        assert_eq!(rx.recv().await.unwrap().email, bound);
+
+        // Balance 模式立即切换
+        tm.mark_rate_limited(&bound, 429, Some("2"), "", Some("claude-sonnet-4-5"));
+        tm.update_sticky_config(StickySessionConfig::default()).await;
+        let (switched, _) = scope_account_lease(tm.get_token("claude", false, Some("s1"), "claude-sonnet-4-5")).await;
+        assert_ne!(switched.unwrap().2, bound);
+    }
+
+    #[test]
+    fn test_scheduler_state_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("ag-sched-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let tm = TokenManager::new(dir.clone());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.mark_rate_limited("a@example.com", 429, Some("600"), "", Some("claude-sonnet-4-5"));
+        tm.mark_rate_limited("gone@example.com", 429, Some("600"), "", None);
+        tm.session_accounts.insert("s1".to_string(), "a".to_string());
+        tm.session_accounts.insert("s2".to_string(), "gone".to_string());
+        tm.save_scheduler_state().unwrap();
+
+        let restarted = TokenManager::new(dir.clone());
+        restarted.tokens.insert("a".to_string(), token("a"));
+        restarted.restore_scheduler_state();
+        assert!(restarted.is_rate_limited("a@example.com", Some("claude-sonnet-4-5")));
+        assert!(!restarted.is_rate_limited("gone@example.com", None));
+        assert_eq!(restarted.session_accounts.get("s1").map(|v| v.clone()), Some("a".to_string()));
+        assert!(restarted.session_accounts.get("s2").is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_admission_queue_waits_for_earliest_reset() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.mark_rate_limited("a@example.com", 429, Some("4"), "", Some("gemini-3-flash"));
+
+        // 不排队: 立即失败，retry-after 为真实的解除时间
+        tm.update_sticky_config(StickySessionConfig {
+            queue_max_depth: 0,
+            ..StickySessionConfig::default()
+        })
+        .await;
+        assert!(tm.get_token("gemini", false, None, "gemini-3-flash").await.is_err());
+        let retry_after = tm.pool_retry_after("gemini-3-flash").unwrap();
+        assert!((4..=5).contains(&retry_after));
+        assert_eq!(tm.pool_retry_after("claude-sonnet-4-5"), None);
+        assert_eq!(tm.admission_stats().rejected_full, 1);
+
+        // 排队: 等待锁定解除后分配
+        tm.update_sticky_config(StickySessionConfig {
+            queue_max_depth: 32,
+            ..StickySessionConfig::default()
+        })
+        .await;
+        // 配置了降级链的请求不排队，也不计入队列统计
+        assert!(tm.get_token_queued("gemini", false, None, "gemini-3-flash", false).await.is_err());
+        assert_eq!(tm.admission_stats().rejected_full, 1);
+        let token = tm.get_token("gemini", false, None, "gemini-3-flash").await.unwrap();
+        assert_eq!(token.2, "a@example.com");
+        let stats = tm.admission_stats();
+        assert_eq!((stats.depth, stats.admitted), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_new_request_queues_behind_waiting_head() {
+        let tm = Arc::new(TokenManager::new(std::env::temp_dir()));
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.update_sticky_config(StickySessionConfig {
+            queue_max_depth: 32,
+            ..StickySessionConfig::default()
+        })
+        .await;
+
+        // 已有请求在排队: 账号空闲时新请求也要等队首先被放行
+        let head = tm.admission.enter("gemini-3-flash", 32, 1).unwrap();
+        let waiting = tokio::spawn({
+            let tm = tm.clone();
+            async move { tm.get_token("gemini", false, None, "gemini-3-flash").await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        assert_eq!(tm.admission_stats().depth, 2);
+        // 其它模型的请求不受影响
+        assert!(tm.get_token("claude", false, None, "claude-sonnet-4-5").await.is_ok());
+
+        drop(head);
+        assert_eq!(waiting.await.unwrap().unwrap().2, "a@example.com");
+        assert_eq!(tm.admission_stats().depth, 0);
+    }
+
+    #[tokio::test]
+    async fn test_routing_rules_limit_accounts_to_group() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.tokens.insert("b".to_string(), ProxyToken { tags: vec!["Ultra-Only".to_string()], ..token("b") });
+        tm.update_routing_rules(vec![
+            AccountRoutingRule {
+                models: vec!["claude-opus-*".to_string()],
+                group: "ultra-only".to_string(),
+                ..Default::default()
+            },
+            AccountRoutingRule {
+                api_keys: vec!["ci-bot".to_string()],
+                group: "batch".to_string(),
+                ..Default::default()
+            },
+        ]);
+
+        for _ in 0..3 {
+            let token = tm.get_token("claude", true, None, "claude-opus-4-5-thinking").await.unwrap();
+            assert_eq!(token.2, "b@example.com");
+        }
+
+        let ci_bot = RequestRoute {
+            api_key: Some("ci-bot".to_string()),
+            route: Some(ApiKeyScope::Openai),
+        };
+        let err = scope_request_route(ci_bot, tm.get_token("gemini", true, None, "gemini-3-flash"))
+            .await
+            .unwrap_err();
+        assert!(err.contains("batch"));
+        assert!(tm.get_token("gemini", true, None, "gemini-3-flash").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_open_account_gets_no_traffic_until_probe() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.tokens.insert("b".to_string(), token("b"));
+        tm.update_sticky_config(StickySessionConfig {
+            breaker_failure_threshold: 2,
+            breaker_open_seconds: 0,
+            ..StickySessionConfig::default()
+        })
+        .await;
+
+        tm.mark_upstream_failure("a@example.com", Some("gemini-3-flash")).await;
+        tm.mark_upstream_failure("a@example.com", Some("gemini-3-flash")).await;
+        for _ in 0..3 {
+            let token = tm.get_token("gemini", true, None, "gemini-3-flash").await.unwrap();
+            assert_eq!(token.2, "b@example.com");
+        }
+
+        let probes = tm.take_due_probes();
+        assert_eq!(probes, vec![("a@example.com".to_string(), "gemini-3-flash".to_string())]);
+        assert_eq!(tm.breaker_states(), vec![("a@example.com".to_string(), BreakerState::HalfOpen)]);
+        tm.record_probe_result("a@example.com", true).await;
+        assert!(tm.breaker_states().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scheduling_trace_records_candidates_and_rule() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), ProxyToken { tags: vec!["main".to_string()], ..token("a") });
+        tm.tokens.insert("b".to_string(), ProxyToken { tags: vec!["main".to_string()], ..token("b") });
+        tm.tokens.insert("c".to_string(), ProxyToken { tags: vec!["batch".to_string()], ..token("c") });
+        tm.excluded_accounts.insert("d@example.com".to_string(), CandidateStatus::QuotaProtected);
+        tm.update_routing_rules(vec![
+            AccountRoutingRule {
+                models: vec!["claude-*".to_string()],
+                group: "main".to_string(),
+                ..Default::default()
+            },
+            AccountRoutingRule {
+                models: vec!["gemini-*".to_string()],
+                group: "batch".to_string(),
+                ..Default::default()
+            },
+        ]);
+        tm.mark_rate_limited("a@example.com", 429, Some("600"), "", None);
+
+        let slot = SchedulingTraceSlot::default();
+        let token = scope_scheduling_trace(Some(slot.clone()), tm.get_token("claude", true, None, "claude-sonnet-4-5")).await;
+        assert_eq!(token.unwrap().2, "b@example.com");
+        // 无监控作用域时不记录
+        assert!(tm.get_token("claude", true, None, "claude-sonnet-4-5").await.is_ok());
+        let token = scope_scheduling_trace(Some(slot.clone()), tm.get_token("gemini", false, None, "gemini-3-flash")).await;
+        assert_eq!(token.unwrap().2, "c@example.com");
+
+        let decisions = slot.take();
+        assert_eq!(decisions.len(), 2);
+        let first = &decisions[0];
+        assert_eq!(first.selected.as_deref(), Some("b@example.com"));
+        assert_eq!(first.rule, Some(SelectionRule::ForceRotate));
+        assert!(first.round_robin_start.is_some());
+        let statuses: Vec<(&str, CandidateStatus)> =
+            first.candidates.iter().map(|c| (c.email.as_str(), c.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("a@example.com", CandidateStatus::RateLimited),
+                ("b@example.com", CandidateStatus::Selected),
+                ("c@example.com", CandidateStatus::OutsideGroup),
+                ("d@example.com", CandidateStatus::QuotaProtected),
+            ]
+        );
+
+        let second = &decisions[1];
+        assert_eq!(second.group.as_deref(), Some("batch"));
+        assert_eq!(second.rule, Some(SelectionRule::RoundRobin));
+        assert_eq!(second.candidates[1].status, CandidateStatus::OutsideGroup);
+        assert!(second.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scheduling_trace_keeps_status_seen_at_selection() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        tm.tokens.insert("a".to_string(), token("a"));
+        tm.tokens.insert("b".to_string(), token("b"));
+        tm.mark_rate_limited("a@example.com", 429, Some("1"), "", None);
+        tm.mark_rate_limited("b@example.com", 429, Some("1"), "", None);
+
+        // 全部短暂限流 -> 乐观重置后选中其中一个，另一个仍记录为选号时的限流状态
+        let slot = SchedulingTraceSlot::default();
+        let token = scope_scheduling_trace(Some(slot.clone()), tm.get_token("gemini", true, None, "")).await;
+        let selected = token.unwrap().2;
+        let decision = slot.take().remove(0);
+        assert_eq!(decision.rule, Some(SelectionRule::OptimisticReset));
+        for candidate in &decision.candidates {
+            let expected = if candidate.email == selected { CandidateStatus::Selected } else { CandidateStatus::RateLimited };
+            assert_eq!(candidate.status, expected);
+        }
+        assert_eq!(decision.candidates.len(), 2);
+    }
+
+    #[test]
+    fn test_pool_average_quota() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        assert_eq!(tm.pool_average_quota("gemini-3-pro-high"), None);
+        let mut a = token("a");
+        a.model_quotas = vec![quota("gemini-3-pro-high", 10), quota("gemini-3-flash", 100)];
+        let mut b = token("b");
+        b.model_quotas = vec![quota("gemini-3-pro-high", 30)];
+        let mut c = token("c");
+        c.tags = vec!["batch".to_string()];
+        c.remaining_quota = Some(80);
+        tm.tokens.insert("a".to_string(), a);
+        tm.tokens.insert("b".to_string(), b);
+        tm.tokens.insert("c".to_string(), c);
+
+        // c 无按模型配额，使用整体配额
+        assert_eq!(tm.pool_average_quota("gemini-3-pro-high"), Some(40.0));
+        tm.update_routing_rules(vec![AccountRoutingRule {
+            models: vec!["gemini-*".to_string()],
+            group: "batch".to_string(),
+            ..Default::default()
+        }]);
+        assert_eq!(tm.pool_average_quota("gemini-3-pro-high"), Some(80.0));
+    }
+
+    #[test]
+    fn test_tokens_due_for_refresh_uses_margin() {
+        let tm = TokenManager::new(std::env::temp_dir());
+        let now = chrono::Utc::now().timestamp();
+        tm.tokens.insert("a".to_string(), ProxyToken { timestamp: now + 300, ..token("a") });
+        tm.tokens.insert("b".to_string(), ProxyToken { timestamp: now + 3000, ..token("b") });
+
+        let due: Vec<String> = tm.tokens_due_for_refresh(900, now).into_iter().map(|t| t.account_id).collect();
+        assert_eq!(due, vec!["a".to_string()]);
+        assert_eq!(tm.tokens_due_for_refresh(3600, now).len(), 2);
+        assert!(tm.tokens_due_for_refresh(0, now).is_empty());
+    }
+}
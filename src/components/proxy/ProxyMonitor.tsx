@@ -8,6 +8,25 @@ import { AppConfig } from '../../types/config';
 import { formatCompactNumber } from '../../utils/format';
 import { useVirtualizer } from '@tanstack/react-virtual';
 
+interface SchedulingCandidate {
+    email: string;
+    status: string;
+}
+
+interface SchedulingDecision {
+    quota_group: string;
+    target_model: string;
+    session_id?: string;
+    mode: string;
+    group?: string;
+    candidates: SchedulingCandidate[];
+    selected?: string;
+    rule?: string;
+    round_robin_start?: number;
+    error?: string;
+    duration_ms: number;
+}
+
 interface ProxyRequestLog {
     id: string;
     timestamp: number;
@@ -23,6 +42,8 @@ interface ProxyRequestLog {
     input_tokens?: number;
     output_tokens?: number;
     account_email?: string;
+    api_key_name?: string;
+    scheduling_trace?: SchedulingDecision[];
 }
 
 interface ProxyStats {
@@ -454,6 +475,36 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                                 )}
                             </div>
 
+                            {selectedLog.scheduling_trace && selectedLog.scheduling_trace.length > 0 && (
+                                <div>
+                                    <h3 className="text-xs font-bold uppercase text-gray-400 mb-2 flex items-center gap-2">{t('monitor.details.scheduling', { defaultValue: 'Scheduling Decisions' })}</h3>
+                                    <div className="space-y-2">
+                                        {selectedLog.scheduling_trace.map((decision, idx) => (
+                                            <div key={idx} className="bg-gray-50 dark:bg-base-300 rounded-lg p-3 border border-gray-100 dark:border-base-300 text-xs font-mono">
+                                                <div className="flex flex-wrap gap-x-4 gap-y-1 mb-2 text-gray-700 dark:text-slate-300">
+                                                    <span>{decision.target_model || decision.quota_group}</span>
+                                                    <span>{decision.mode}</span>
+                                                    {decision.group && <span>group: {decision.group}</span>}
+                                                    <span>{t('monitor.details.scheduling_rule', { defaultValue: 'Rule' })}: {decision.rule || '-'}</span>
+                                                    <span>{decision.duration_ms}ms</span>
+                                                </div>
+                                                {decision.error && <div className="text-red-500 mb-2 break-all">{decision.error}</div>}
+                                                <div className="flex flex-wrap gap-1.5">
+                                                    {decision.candidates.map((c) => (
+                                                        <span
+                                                            key={c.email}
+                                                            className={`px-2 py-0.5 rounded border ${c.status === 'selected' ? 'border-green-300 text-green-700 dark:text-green-400 font-bold' : 'border-gray-200 dark:border-slate-700 text-gray-500 dark:text-slate-400'}`}
+                                                        >
+                                                            {c.email} · {c.status}
+                                                        </span>
+                                                    ))}
+                                                </div>
+                                            </div>
+                                        ))}
+                                    </div>
+                                </div>
+                            )}
+
                             {/* Payloads */}
                             <div className="space-y-4">
                                 <div>
@@ -24,7 +24,7 @@ import {
     X,
     Edit2
 } from 'lucide-react';
-import { AppConfig, ProxyConfig, StickySessionConfig, ExperimentalConfig } from '../types/config';
+import { AppConfig, ProxyConfig, StickySessionConfig, ExperimentalConfig, ModelMappingRule } from '../types/config';
 import HelpTooltip from '../components/common/HelpTooltip';
 import ModalDialog from '../components/common/ModalDialog';
 import { showToast } from '../components/common/ToastContainer';
@@ -152,7 +152,8 @@ export default function ApiProxy() {
     const [zaiNewMappingFrom, setZaiNewMappingFrom] = useState('');
     const [zaiNewMappingTo, setZaiNewMappingTo] = useState('');
     const [customMappingValue, setCustomMappingValue] = useState(''); // 自定义映射表单的选中值
-    const [editingKey, setEditingKey] = useState<string | null>(null);
+    const [customMappingRegex, setCustomMappingRegex] = useState(false); // 新增规则是否为正则
+    const [editingIndex, setEditingIndex] = useState<number | null>(null);
     const [editingValue, setEditingValue] = useState<string>('');
 
     // Modal states
@@ -216,13 +217,22 @@ export default function ApiProxy() {
     };
 
     // 专门处理模型映射的热更新 (全量)
-    const handleMappingUpdate = async (type: 'custom', key: string, value: string) => {
+    const handleMappingUpdate = async (type: 'custom', key: string, value: string, regex = false, index?: number) => {
         if (!appConfig) return;
 
-        console.log('[DEBUG] handleMappingUpdate called:', { type, key, value });
+        console.log('[DEBUG] handleMappingUpdate called:', { type, key, value, regex });
 
+        // 已有规则只更新目标模型 (保留顺序、优先级与条件)，否则追加到末尾
+        // 同一 pattern 可能有多条带条件的规则，列表内编辑按下标定位
         const newConfig = { ...appConfig.proxy };
-        newConfig.custom_mapping = { ...(newConfig.custom_mapping || {}), [key]: value };
+        const rules = [...(newConfig.custom_mapping || [])];
+        const idx = index ?? rules.findIndex(r => r.pattern === key && !r.when);
+        if (idx >= 0) {
+            rules[idx] = { ...rules[idx], target: value };
+        } else {
+            rules.push({ pattern: key, target: value, regex });
+        }
+        newConfig.custom_mapping = rules;
 
         try {
             await invoke('update_model_mapping', { config: newConfig });
@@ -247,7 +257,7 @@ export default function ApiProxy() {
         // 恢复到默认映射值 (空映射)
         const newConfig = {
             ...appConfig.proxy,
-            custom_mapping: {}
+            custom_mapping: []
         };
 
         try {
@@ -265,10 +275,11 @@ export default function ApiProxy() {
     const handleApplyPresets = async () => {
         if (!appConfig) return;
 
+        // 规则按顺序匹配，更具体的模式需排在前面
         const presets: Record<string, string> = {
             // OpenAI (通配符)
-            "gpt-4*": "gemini-3-pro-high",
             "gpt-4o*": "gemini-3-flash",
+            "gpt-4*": "gemini-3-pro-high",
             "gpt-3.5*": "gemini-2.5-flash",
             "o1-*": "gemini-3-pro-high",
             "o3-*": "gemini-3-pro-high",
@@ -281,9 +292,17 @@ export default function ApiProxy() {
             "claude-3-haiku-*": "gemini-2.5-flash-lite",
         };
 
+        // 已存在的规则更新目标模型，其余预设追加到末尾
+        const existing = appConfig.proxy.custom_mapping || [];
+        const merged: ModelMappingRule[] = [
+            ...existing.map(r => (!r.regex && presets[r.pattern] ? { ...r, target: presets[r.pattern] } : r)),
+            ...Object.entries(presets)
+                .filter(([pattern]) => !existing.some(r => !r.regex && r.pattern === pattern))
+                .map(([pattern, target]) => ({ pattern, target })),
+        ];
         const newConfig = {
             ...appConfig.proxy,
-            custom_mapping: { ...appConfig.proxy.custom_mapping, ...presets }
+            custom_mapping: merged
         };
 
         try {
@@ -296,10 +315,9 @@ export default function ApiProxy() {
         }
     };
 
-    const handleRemoveCustomMapping = async (key: string) => {
+    const handleRemoveCustomMapping = async (index: number) => {
         if (!appConfig || !appConfig.proxy.custom_mapping) return;
-        const newCustom = { ...appConfig.proxy.custom_mapping };
-        delete newCustom[key];
+        const newCustom = appConfig.proxy.custom_mapping.filter((_, i) => i !== index);
         const newConfig = { ...appConfig.proxy, custom_mapping: newCustom };
         try {
             await invoke('update_model_mapping', { config: newConfig });
@@ -1131,7 +1149,7 @@ print(response.text)`;
                                                 </button>
                                             </div>
                                             <div className="grid grid-cols-1 gap-2">
-                                                {(['CacheFirst', 'Balance', 'PerformanceFirst'] as const).map(mode => (
+                                                {(['CacheFirst', 'Balance', 'PerformanceFirst', 'LeastLoaded'] as const).map(mode => (
                                                     <label
                                                         key={mode}
                                                         className={`flex items-start gap-3 p-3 rounded-xl border cursor-pointer transition-all duration-200 ${(appConfig.proxy.scheduling?.mode || 'Balance') === mode
@@ -1153,7 +1171,8 @@ print(response.text)`;
                                                                 {t(`proxy.config.scheduling.modes_desc.${mode}`, {
                                                                     defaultValue: mode === 'CacheFirst' ? 'Binds session to account, waits precisely if limited (Maximizes Prompt Cache hits).' :
                                                                         mode === 'Balance' ? 'Binds session, auto-switches to available account if limited (Balanced cache & availability).' :
-                                                                            'No session binding, pure round-robin rotation (Best for high concurrency).'
+                                                                            mode === 'LeastLoaded' ? 'No session binding, picks the account with the fewest in-flight requests (Best for many concurrent streams).' :
+                                                                                'No session binding, pure round-robin rotation (Best for high concurrency).'
                                                                 })}
                                                             </div>
                                                         </div>
@@ -1189,6 +1208,80 @@ print(response.text)`;
                                                 </div>
                                             </div>
 
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700">
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.max_concurrency', { defaultValue: 'Max concurrency per account' })}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.max_concurrency_tooltip', { defaultValue: 'Upper limit of in-flight requests per account in every mode (0 = unlimited).' })} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="input input-bordered input-xs w-20 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.max_concurrency_per_account ?? 0}
+                                                        onChange={(e) => updateSchedulingConfig({ max_concurrency_per_account: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                            </div>
+
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700 space-y-3">
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.queue_max_depth', { defaultValue: 'Queue depth' })}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.queue_max_depth_tooltip', { defaultValue: 'Requests allowed to wait when every account is rate limited (0 = reject immediately with 429).' })} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="input input-bordered input-xs w-20 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.queue_max_depth ?? 0}
+                                                        onChange={(e) => updateSchedulingConfig({ queue_max_depth: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.queue_max_wait', { defaultValue: 'Queue max wait (sec)' })}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.queue_max_wait_tooltip', { defaultValue: 'Longest time a queued request waits for the earliest rate-limit reset before getting a 429.' })} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="input input-bordered input-xs w-20 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.queue_max_wait_seconds ?? 60}
+                                                        onChange={(e) => updateSchedulingConfig({ queue_max_wait_seconds: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                            </div>
+
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700 space-y-3">
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.breaker_failure_threshold', { defaultValue: 'Circuit breaker threshold' })}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.breaker_failure_threshold_tooltip', { defaultValue: 'Consecutive 5xx, network or empty-stream failures before an account stops receiving traffic (0 = off).' })} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="input input-bordered input-xs w-20 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.breaker_failure_threshold ?? 0}
+                                                        onChange={(e) => updateSchedulingConfig({ breaker_failure_threshold: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.breaker_open_seconds', { defaultValue: 'Circuit open time (sec)' })}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.breaker_open_seconds_tooltip', { defaultValue: 'Cooldown before a probe request is sent. Doubles after each failed probe, up to 600s.' })} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="input input-bordered input-xs w-20 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.breaker_open_seconds ?? 30}
+                                                        onChange={(e) => updateSchedulingConfig({ breaker_open_seconds: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                            </div>
+
                                             <div className="p-3 bg-amber-50 dark:bg-amber-900/10 border border-amber-100 dark:border-amber-900/20 rounded-xl">
                                                 <p className="text-[10px] text-amber-700 dark:text-amber-500 leading-relaxed">
                                                     <strong>{t('common.info')}:</strong> {t('proxy.config.scheduling.subtitle')}
@@ -1288,14 +1381,17 @@ print(response.text)`;
                                             </div>
                                             <div className="overflow-y-auto max-h-[180px] border border-gray-100 dark:border-white/5 rounded-lg bg-gray-50/10 dark:bg-white/5 p-3" data-custom-mapping-list>
                                                 <div className="grid grid-cols-1 md:grid-cols-2 gap-x-6 gap-y-2">
-                                                    {appConfig.proxy.custom_mapping && Object.entries(appConfig.proxy.custom_mapping).length > 0 ? (
-                                                        Object.entries(appConfig.proxy.custom_mapping).map(([key, val]) => (
-                                                            <div key={key} className={`flex items-center justify-between p-1.5 rounded-md transition-all border group ${editingKey === key ? 'bg-blue-50/80 dark:bg-blue-900/15 border-blue-300/50 dark:border-blue-500/30 shadow-sm' : 'border-transparent hover:bg-gray-100 dark:hover:bg-white/5 hover:border-gray-200 dark:hover:border-white/10'}`}>
+                                                    {appConfig.proxy.custom_mapping && appConfig.proxy.custom_mapping.length > 0 ? (
+                                                        appConfig.proxy.custom_mapping.map(({ pattern: key, target: val, regex, priority, when }, index) => (
+                                                            <div key={index} className={`flex items-center justify-between p-1.5 rounded-md transition-all border group ${editingIndex === index ? 'bg-blue-50/80 dark:bg-blue-900/15 border-blue-300/50 dark:border-blue-500/30 shadow-sm' : 'border-transparent hover:bg-gray-100 dark:hover:bg-white/5 hover:border-gray-200 dark:hover:border-white/10'}`}>
                                                                 <div className="flex items-center gap-2.5 overflow-hidden flex-1">
                                                                     <span className="font-mono text-[10px] font-bold text-blue-600 dark:text-blue-400 truncate max-w-[140px]" title={key}>{key}</span>
+                                                                    {regex && <span className="badge badge-xs badge-ghost font-mono shrink-0">re</span>}
+                                                                    {!!priority && <span className="badge badge-xs badge-ghost font-mono shrink-0" title="priority">P{priority}</span>}
+                                                                    {when && Object.keys(when).length > 0 && <span className="badge badge-xs badge-ghost font-mono shrink-0" title={JSON.stringify(when)}>{t('proxy.router.conditional', { defaultValue: 'if' })}</span>}
                                                                     <ArrowRight size={10} className="text-gray-300 dark:text-gray-600 shrink-0" />
 
-                                                                    {editingKey === key ? (
+                                                                    {editingIndex === index ? (
                                                                         <div className="flex-1 mr-2">
                                                                             <GroupedSelect
                                                                                 value={editingValue}
@@ -1307,19 +1403,19 @@ print(response.text)`;
                                                                         </div>
                                                                     ) : (
                                                                         <span className="font-mono text-[10px] text-gray-500 dark:text-gray-400 truncate cursor-pointer hover:text-blue-500"
-                                                                            onClick={() => { setEditingKey(key); setEditingValue(val); }}
+                                                                            onClick={() => { setEditingIndex(index); setEditingValue(val); }}
                                                                             title={val}>{val}</span>
                                                                     )}
                                                                 </div>
 
                                                                 <div className="flex items-center gap-1.5 shrink-0">
-                                                                    {editingKey === key ? (
+                                                                    {editingIndex === index ? (
                                                                         <div className="flex items-center gap-1 bg-white dark:bg-gray-800 rounded-md border border-blue-200 dark:border-blue-800 p-0.5 shadow-sm">
                                                                             <button
                                                                                 className="btn btn-ghost btn-xs text-primary hover:bg-blue-50 dark:hover:bg-blue-900/30 p-0 h-6 w-6 min-h-0"
                                                                                 onClick={() => {
-                                                                                    handleMappingUpdate('custom', key, editingValue);
-                                                                                    setEditingKey(null);
+                                                                                    handleMappingUpdate('custom', key, editingValue, regex, index);
+                                                                                    setEditingIndex(null);
                                                                                 }}
                                                                                 title={t('common.save') || 'Save'}
                                                                             >
@@ -1328,7 +1424,7 @@ print(response.text)`;
                                                                             <div className="w-[1px] h-3 bg-gray-200 dark:bg-gray-700" />
                                                                             <button
                                                                                 className="btn btn-ghost btn-xs text-gray-400 hover:bg-gray-100 dark:hover:bg-gray-700 p-0 h-6 w-6 min-h-0"
-                                                                                onClick={() => setEditingKey(null)}
+                                                                                onClick={() => setEditingIndex(null)}
                                                                                 title={t('common.cancel') || 'Cancel'}
                                                                             >
                                                                                 <X size={14} strokeWidth={3} />
@@ -1338,14 +1434,14 @@ print(response.text)`;
                                                                         <div className="flex items-center gap-1 opacity-0 group-hover:opacity-100 transition-opacity">
                                                                             <button
                                                                                 className="btn btn-ghost btn-xs text-gray-400 hover:text-blue-500 hover:bg-blue-50 dark:hover:bg-white/10 p-0 h-6 w-6 min-h-0"
-                                                                                onClick={() => { setEditingKey(key); setEditingValue(val); }}
+                                                                                onClick={() => { setEditingIndex(index); setEditingValue(val); }}
                                                                                 title={t('common.edit') || 'Edit'}
                                                                             >
                                                                                 <Edit2 size={12} />
                                                                             </button>
                                                                             <button
                                                                                 className="btn btn-ghost btn-xs text-error hover:bg-red-50 dark:hover:bg-red-900/20 p-0 h-6 w-6 min-h-0"
-                                                                                onClick={() => handleRemoveCustomMapping(key)}
+                                                                                onClick={() => handleRemoveCustomMapping(index)}
                                                                                 title={t('common.delete') || 'Delete'}
                                                                             >
                                                                                 <Trash2 size={12} />
@@ -1373,17 +1469,37 @@ print(response.text)`;
                                                     <input
                                                         id="custom-key"
                                                         type="text"
-                                                        placeholder="Original (e.g. gpt-4 or gpt-4*)"
+                                                        placeholder={customMappingRegex ? 'Regex (e.g. ^claude-(.*)-2025\\d+$)' : 'Original (e.g. gpt-4 or gpt-*-mini*)'}
                                                         className="input input-xs input-bordered flex-1 font-mono text-[11px] bg-white dark:bg-gray-800 border border-gray-200 dark:border-gray-700 shadow-sm focus:border-blue-500 focus:ring-1 focus:ring-blue-500 transition-all placeholder:text-gray-400 dark:placeholder:text-gray-600 h-8"
                                                     />
-                                                    <div className="w-full sm:w-48">
-                                                        <GroupedSelect
-                                                            value={customMappingValue}
-                                                            onChange={setCustomMappingValue}
-                                                            options={customMappingOptions}
-                                                            placeholder={t('proxy.router.select_target_model') || 'Select Target Model'}
-                                                            className="font-mono text-[11px] h-8 dark:bg-gray-800"
+                                                    <label className="flex items-center gap-1.5 shrink-0 cursor-pointer">
+                                                        <input
+                                                            type="checkbox"
+                                                            className="checkbox checkbox-xs"
+                                                            checked={customMappingRegex}
+                                                            onChange={(e) => setCustomMappingRegex(e.target.checked)}
                                                         />
+                                                        <span className="text-[10px] font-bold text-gray-400 dark:text-gray-500 uppercase tracking-wider">{t('proxy.router.regex', { defaultValue: 'Regex' })}</span>
+                                                    </label>
+                                                    <div className="w-full sm:w-48">
+                                                        {customMappingRegex ? (
+                                                            // 正则规则的目标可引用捕获组，使用自由输入
+                                                            <input
+                                                                type="text"
+                                                                value={customMappingValue}
+                                                                onChange={(e) => setCustomMappingValue(e.target.value)}
+                                                                placeholder="Target (e.g. claude-$1)"
+                                                                className="input input-xs input-bordered w-full font-mono text-[11px] bg-white dark:bg-gray-800 border border-gray-200 dark:border-gray-700 h-8"
+                                                            />
+                                                        ) : (
+                                                            <GroupedSelect
+                                                                value={customMappingValue}
+                                                                onChange={setCustomMappingValue}
+                                                                options={customMappingOptions}
+                                                                placeholder={t('proxy.router.select_target_model') || 'Select Target Model'}
+                                                                className="font-mono text-[11px] h-8 dark:bg-gray-800"
+                                                            />
+                                                        )}
                                                     </div>
                                                 </div>
                                                 <button
@@ -1392,7 +1508,7 @@ print(response.text)`;
                                                         const k = (document.getElementById('custom-key') as HTMLInputElement).value;
                                                         const v = customMappingValue;
                                                         if (k && v) {
-                                                            handleMappingUpdate('custom', k, v);
+                                                            handleMappingUpdate('custom', k, v, customMappingRegex);
                                                             (document.getElementById('custom-key') as HTMLInputElement).value = '';
                                                             setCustomMappingValue(''); // 清空选择
                                                         }
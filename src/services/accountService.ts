@@ -111,6 +111,10 @@ export async function toggleProxyStatus(accountId: string, enable: boolean, reas
     return await invoke('toggle_proxy_status', { accountId, enable, reason });
 }
 
+export async function updateAccountTags(accountId: string, tags: string[]): Promise<Account> {
+    return await invoke('update_account_tags', { accountId, tags });
+}
+
 /**
  * 重新排序账号列表
  * @param accountIds 按新顺序排列的账号ID数组
@@ -12,6 +12,7 @@ export interface Account {
     proxy_disabled?: boolean;
     proxy_disabled_reason?: string;
     proxy_disabled_at?: number;
+    tags?: string[]; // 反代路由规则使用的账号分组
     created_at: number;
     last_used: number;
 }
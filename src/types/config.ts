@@ -3,27 +3,135 @@ export interface UpstreamProxyConfig {
     url: string;
 }
 
+export interface ProxyTlsConfig {
+    enabled: boolean;
+    cert_path?: string | null;
+    key_path?: string | null;
+    subject_alt_names?: string[];
+}
+
+export interface ProxyUnixSocketConfig {
+    enabled: boolean;
+    path?: string; // 为空时使用数据目录下的 proxy.sock
+    mode?: string; // 八进制权限，如 "660"
+    disable_tcp?: boolean;
+    auth_mode?: 'off' | 'strict' | 'all_except_health' | 'auto';
+}
+
+export type ApiKeyScope = 'openai' | 'claude' | 'gemini' | 'mcp' | 'images' | 'audio';
+
+export interface ApiKeyConfig {
+    name: string;
+    key: string;
+    enabled: boolean;
+    expires_at?: number | null; // Unix seconds
+    scopes?: ApiKeyScope[];
+    allowed_models?: string[];
+    limits?: ApiKeyLimits;
+}
+
+export interface ApiKeyLimits {
+    requests_per_minute?: number | null;
+    max_concurrency?: number | null;
+    daily_tokens?: number | null;
+    monthly_tokens?: number | null;
+}
+
+export interface ModelMappingRule {
+    pattern: string; // 精确名称、glob (可含多个 *) 或正则
+    target: string; // 正则规则可引用捕获组 ($1)
+    regex?: boolean;
+    priority?: number; // 数值大的先匹配，相同时按列表顺序
+    when?: MappingConditions; // 请求特征条件，全部满足时规则才生效
+}
+
+export interface MappingConditions {
+    has_images?: boolean;
+    has_audio?: boolean;
+    has_tools?: boolean;
+    has_thinking?: boolean;
+    min_tokens?: number; // 估算输入 token 数
+    max_tokens?: number;
+    max_messages?: number;
+    api_keys?: string[];
+    protocols?: ApiKeyScope[];
+}
+
+export interface ModelFallbackConfig {
+    chains?: Record<string, string[]>; // 映射后的模型名 -> 依次尝试的替代模型
+    response_header?: boolean; // 降级时附加 X-Model-Fallback-From 响应头
+}
+
+export interface QuotaDowngradeRule {
+    model: string; // 映射后的模型名 (glob)
+    target: string;
+    threshold_percentage: number; // 账号池平均剩余配额低于该值时降级
+}
+
+export interface ModelDescriptor {
+    id: string; // 映射后的模型名 (glob)，优先于内置条目
+    context_window: number;
+    max_output_tokens: number;
+    thinking?: boolean;
+    default_thinking_budget?: number; // 设置后默认开启思考
+    max_thinking_budget?: number;
+    tools?: boolean;
+    vision?: boolean;
+    audio?: boolean;
+    image_output?: boolean;
+    search?: boolean; // 支持 googleSearch 联网
+}
+
+export interface AccountRoutingRule {
+    models?: string[]; // glob，匹配映射后的模型名
+    api_keys?: string[]; // API Key 名称
+    routes?: ApiKeyScope[];
+    group: string; // 账号标签
+}
+
+export interface TokenRefreshConfig {
+    enabled: boolean;
+    margin_seconds?: number; // 距过期多少秒时刷新
+    max_concurrency?: number;
+}
+
 export interface ProxyConfig {
     enabled: boolean;
     allow_lan_access?: boolean;
     auth_mode?: 'off' | 'strict' | 'all_except_health' | 'auto';
     port: number;
     api_key: string;
+    admin_key?: string;
+    api_keys?: ApiKeyConfig[];
     auto_start: boolean;
-    custom_mapping?: Record<string, string>;
+    custom_mapping?: ModelMappingRule[];
+    model_fallback?: ModelFallbackConfig;
+    quota_downgrade?: QuotaDowngradeRule[];
+    model_registry?: ModelDescriptor[];
     request_timeout: number;
     enable_logging: boolean;
+    enable_metrics?: boolean; // Prometheus /metrics 端点
+    tls?: ProxyTlsConfig;
+    unix_socket?: ProxyUnixSocketConfig;
+    shutdown_grace_seconds?: number; // 停止服务时等待进行中请求的最长时间
     upstream_proxy: UpstreamProxyConfig;
     zai?: ZaiConfig;
     scheduling?: StickySessionConfig;
+    routing_rules?: AccountRoutingRule[];
+    token_refresh?: TokenRefreshConfig;
     experimental?: ExperimentalConfig;
 }
 
-export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
+export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst' | 'LeastLoaded';
 
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    max_concurrency_per_account?: number; // 0 = 不限制
+    queue_max_depth?: number; // 0 = 不排队
+    queue_max_wait_seconds?: number;
+    breaker_failure_threshold?: number; // 0 = 关闭熔断
+    breaker_open_seconds?: number;
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';